@@ -9,11 +9,24 @@ let
 
   cfg = config.services.hotResize;
   devicesJson = builtins.toJSON (
-    map (dev: {
-      device = dev.device;
-      fs_type = dev.fsType;
-      mount_point = dev.mountPoint;
-    }) cfg.devices
+    map (
+      dev:
+      lib.optionalAttrs (dev.mountPoint != null) { mount_point = dev.mountPoint; }
+      // lib.optionalAttrs (dev.device != null) { device = dev.device; }
+      // lib.optionalAttrs (dev.fsType != null) { fs_type = dev.fsType; }
+      // lib.optionalAttrs (dev.lvmPool != null) { lvm_pool = dev.lvmPool; }
+      // lib.optionalAttrs (dev.luksKey != null) {
+        luks_key = lib.filterAttrs (_: value: value != null) {
+          key_file = dev.luksKey.keyFile;
+          key_slot = dev.luksKey.keySlot;
+          token_id = dev.luksKey.tokenId;
+          token_type = dev.luksKey.tokenType;
+          passphrase_fd = dev.luksKey.passphraseFd;
+        };
+      }
+      // lib.optionalAttrs (dev.luksHeader != null) { luks_header = dev.luksHeader; }
+      // lib.optionalAttrs dev.plainCrypt { plain_crypt = true; }
+    ) cfg.devices
   );
 
 in
@@ -29,23 +42,87 @@ in
         lib.types.submodule {
           options = {
             device = lib.mkOption {
-              type = lib.types.str;
+              type = lib.types.nullOr lib.types.str;
+              default = null;
               example = "/dev/sda1";
-              description = "Block device path to resize";
+              description = "Block device path to resize (detected from the mount point when null)";
             };
             fsType = lib.mkOption {
-              type = lib.types.enum [
-                "ext4"
-                "xfs"
-                "btrfs"
-              ];
-              default = "ext4";
-              description = "Filesystem type (supported: ext4, xfs, btrfs)";
+              type = lib.types.nullOr (
+                lib.types.enum [
+                  "ext4"
+                  "xfs"
+                  "btrfs"
+                  "f2fs"
+                  "bcachefs"
+                  "nilfs2"
+                  "swap"
+                  "ntfs"
+                  "vfat"
+                ]
+              );
+              default = null;
+              description = "Filesystem type (supported: ext4, xfs, btrfs, f2fs, bcachefs, nilfs2, swap, ntfs, vfat; detected from the mount point when null)";
             };
             mountPoint = lib.mkOption {
-              type = lib.types.str;
+              type = lib.types.nullOr lib.types.str;
+              default = null;
               example = "/";
-              description = "Mount point of the filesystem to resize";
+              description = "Mount point of the filesystem to resize (null for a filesystem resized while unmounted)";
+            };
+            lvmPool = lib.mkOption {
+              type = lib.types.nullOr lib.types.str;
+              default = null;
+              example = "vg/pool";
+              description = "LVM thin pool or VDO pool to extend after the physical volume grows";
+            };
+            luksKey = lib.mkOption {
+              type = lib.types.nullOr (
+                lib.types.submodule {
+                  options = {
+                    keyFile = lib.mkOption {
+                      type = lib.types.nullOr lib.types.str;
+                      default = null;
+                      example = "/etc/keys/root.key";
+                      description = "Key file passed to cryptsetup (a string, so that the key is not copied to the Nix store)";
+                    };
+                    keySlot = lib.mkOption {
+                      type = lib.types.nullOr lib.types.ints.unsigned;
+                      default = null;
+                      description = "Key slot to use with the key file or passphrase";
+                    };
+                    tokenId = lib.mkOption {
+                      type = lib.types.nullOr lib.types.ints.unsigned;
+                      default = null;
+                      description = "LUKS2 token to unlock with";
+                    };
+                    tokenType = lib.mkOption {
+                      type = lib.types.nullOr lib.types.str;
+                      default = null;
+                      example = "systemd-tpm2";
+                      description = "LUKS2 token type to unlock with, e.g. one enrolled by systemd-cryptenroll";
+                    };
+                    passphraseFd = lib.mkOption {
+                      type = lib.types.nullOr lib.types.ints.unsigned;
+                      default = null;
+                      description = "Inherited file descriptor to read the passphrase from";
+                    };
+                  };
+                }
+              );
+              default = null;
+              description = "Key source for a LUKS2 volume whose volume key is not in the kernel keyring";
+            };
+            luksHeader = lib.mkOption {
+              type = lib.types.nullOr lib.types.str;
+              default = null;
+              example = "/etc/luks/root.header";
+              description = "Detached LUKS header of the volume";
+            };
+            plainCrypt = lib.mkOption {
+              type = lib.types.bool;
+              default = false;
+              description = "The device holds plain dm-crypt, which has no header to detect";
             };
           };
         }
@@ -0,0 +1,224 @@
+use crate::resize::{self, ResizeError};
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// A bcache device and the backing device it caches.
+#[derive(Debug, PartialEq)]
+pub struct BcacheDevice {
+    /// Kernel name of the bcache device (e.g. `bcache0`).
+    pub name: String,
+    /// Kernel name of the backing device (e.g. `sdb1`).
+    pub backing: String,
+}
+
+/// Returns the bcache device for a disk name, or `None` if it is not one.
+pub fn detect_bcache(disk_name: &str) -> Result<Option<BcacheDevice>, ResizeError> {
+    read_bcache_device(Path::new("/sys/class/block"), disk_name)
+}
+
+/// Reads bcache device information from a sysfs-like directory structure.
+///
+/// `<sysfs_base>/bcacheN/bcache` is a symlink to the `bcache` directory of
+/// the backing device (e.g. `../../sdb/sdb1/bcache`), so the parent of the
+/// link target names the backing device. Backing and cache devices have a
+/// real `bcache` directory instead, and are not reported.
+fn read_bcache_device(sysfs_base: &Path, name: &str) -> Result<Option<BcacheDevice>, ResizeError> {
+    let link = sysfs_base.join(name).join("bcache");
+    let target = match std::fs::read_link(&link) {
+        Ok(target) => target,
+        Err(_) => return Ok(None),
+    };
+
+    let backing = target
+        .parent()
+        .and_then(|p| p.file_name())
+        .and_then(|n| n.to_str())
+        .map(String::from)
+        .ok_or_else(|| {
+            ResizeError::DeviceNotFound(format!(
+                "Could not find the backing device of {} from {:?}",
+                name, target
+            ))
+        })?;
+
+    Ok(Some(BcacheDevice {
+        name: name.to_string(),
+        backing,
+    }))
+}
+
+/// Grows the backing partition of a bcache device, then tells bcache
+/// about the new size.
+///
+/// Returns `true` if the bcache device grew.
+pub fn grow_bcache(bcache: &BcacheDevice) -> Result<bool, ResizeError> {
+    let backing_path = PathBuf::from(format!("/dev/{}", bcache.backing));
+    let backing_device = crate::analyze_device(&backing_path).map_err(|e| {
+        ResizeError::DeviceNotFound(format!("bcache backing device {}: {}", bcache.backing, e))
+    })?;
+
+    if backing_device.partition_number.is_some() {
+        info!(
+            "Growing bcache backing device {} of {}",
+            bcache.backing, bcache.name
+        );
+        resize::grow_partition(
+            &format!("/dev/{}", backing_device.disk_name),
+            backing_device.partition_number,
+            None,
+        )?;
+    }
+
+    resize_bcache_sysfs(Path::new("/sys/class/block"), bcache)
+}
+
+/// Makes a bcache device pick up the size of its backing device.
+///
+/// Newer kernels expose `<sysfs_base>/bcacheN/bcache/resize` for this. Older
+/// ones only pick up the new size when the backing device is registered
+/// again, which cannot be done while the filesystem is mounted.
+fn resize_bcache_sysfs(sysfs_base: &Path, bcache: &BcacheDevice) -> Result<bool, ResizeError> {
+    let size_of = |name: &str| {
+        crate::read_sysfs_device_size(sysfs_base, name)
+            .map_err(|e| ResizeError::DeviceNotFound(e.to_string()))
+    };
+
+    // bcache starts its data after a superblock area on the backing device
+    // (8 KiB by default), so the bcache device is always a little smaller
+    let size_before = size_of(&bcache.name)?;
+    let backing_size = size_of(&bcache.backing)?;
+    if backing_size.saturating_sub(size_before) < resize::GROW_FUDGE_BYTES {
+        info!(
+            "bcache device {} already matches its backing device {}",
+            bcache.name, bcache.backing
+        );
+        return Ok(false);
+    }
+
+    let resize_attr = sysfs_base.join(&bcache.name).join("bcache").join("resize");
+    if !resize_attr.exists() {
+        return Err(ResizeError::ResizeBcache(format!(
+            "{}: this kernel has no bcache resize support; the backing device {} grew, \
+             but {} only picks up the new size after it is registered again",
+            bcache.name, bcache.backing, bcache.name
+        )));
+    }
+
+    info!("Resizing bcache device {}", bcache.name);
+    std::fs::write(&resize_attr, "1").map_err(|e| {
+        ResizeError::ResizeBcache(format!("Failed to write {}: {}", resize_attr.display(), e))
+    })?;
+
+    let size_after = size_of(&bcache.name)?;
+    if size_after <= size_before {
+        warn!(
+            "bcache device {} did not grow after resize ({} bytes)",
+            bcache.name, size_after
+        );
+        return Ok(false);
+    }
+
+    info!(
+        "bcache device {} grew from {} to {} bytes",
+        bcache.name, size_before, size_after
+    );
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates a fake bcache device on top of a backing partition.
+    /// Structure: `<base>/sdb/sdb1/bcache/` and `<base>/bcache0/bcache -> ../sdb/sdb1/bcache`
+    fn create_fake_bcache(base: &Path, name: &str, disk: &str, backing: &str) {
+        let backing_dir = base.join(disk).join(backing);
+        std::fs::create_dir_all(backing_dir.join("bcache")).unwrap();
+        std::os::unix::fs::symlink(PathBuf::from(disk).join(backing), base.join(backing)).unwrap();
+
+        std::fs::create_dir_all(base.join(name)).unwrap();
+        let target = PathBuf::from("..").join(disk).join(backing).join("bcache");
+        std::os::unix::fs::symlink(target, base.join(name).join("bcache")).unwrap();
+    }
+
+    fn write_size(base: &Path, name: &str, sectors: u64) {
+        std::fs::write(base.join(name).join("size"), format!("{}\n", sectors)).unwrap();
+    }
+
+    #[test]
+    fn test_read_bcache_device() {
+        let tmpdir = tempfile::tempdir().expect("Failed to create temp dir");
+        create_fake_bcache(tmpdir.path(), "bcache0", "sdb", "sdb1");
+
+        let bcache = read_bcache_device(tmpdir.path(), "bcache0")
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            bcache,
+            BcacheDevice {
+                name: "bcache0".to_string(),
+                backing: "sdb1".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_read_bcache_backing_is_not_bcache() {
+        let tmpdir = tempfile::tempdir().expect("Failed to create temp dir");
+        create_fake_bcache(tmpdir.path(), "bcache0", "sdb", "sdb1");
+
+        // The backing device has a real bcache directory, not a link
+        assert!(read_bcache_device(tmpdir.path(), "sdb1").unwrap().is_none());
+        assert!(read_bcache_device(tmpdir.path(), "sdc").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_resize_bcache_already_full() {
+        let tmpdir = tempfile::tempdir().expect("Failed to create temp dir");
+        create_fake_bcache(tmpdir.path(), "bcache0", "sdb", "sdb1");
+        // Backing device minus the 8 KiB bcache superblock area: nothing to do
+        write_size(tmpdir.path(), "bcache0", 4080);
+        write_size(tmpdir.path(), "sdb1", 4096);
+        let bcache = read_bcache_device(tmpdir.path(), "bcache0")
+            .unwrap()
+            .unwrap();
+
+        assert!(!resize_bcache_sysfs(tmpdir.path(), &bcache).unwrap());
+    }
+
+    #[test]
+    fn test_resize_bcache_without_kernel_support() {
+        let tmpdir = tempfile::tempdir().expect("Failed to create temp dir");
+        create_fake_bcache(tmpdir.path(), "bcache0", "sdb", "sdb1");
+        write_size(tmpdir.path(), "bcache0", 4080);
+        write_size(tmpdir.path(), "sdb1", 8192 * 1024);
+        let bcache = read_bcache_device(tmpdir.path(), "bcache0")
+            .unwrap()
+            .unwrap();
+
+        let result = resize_bcache_sysfs(tmpdir.path(), &bcache);
+        assert!(matches!(result, Err(ResizeError::ResizeBcache(_))));
+    }
+
+    #[test]
+    fn test_resize_bcache_writes_resize() {
+        let tmpdir = tempfile::tempdir().expect("Failed to create temp dir");
+        create_fake_bcache(tmpdir.path(), "bcache0", "sdb", "sdb1");
+        write_size(tmpdir.path(), "bcache0", 4080);
+        write_size(tmpdir.path(), "sdb1", 8192 * 1024);
+        let resize_attr = tmpdir
+            .path()
+            .join("sdb")
+            .join("sdb1")
+            .join("bcache")
+            .join("resize");
+        std::fs::write(&resize_attr, "").unwrap();
+        let bcache = read_bcache_device(tmpdir.path(), "bcache0")
+            .unwrap()
+            .unwrap();
+
+        // The fake kernel does not grow the device, so no change is reported
+        assert!(!resize_bcache_sysfs(tmpdir.path(), &bcache).unwrap());
+        assert_eq!(std::fs::read_to_string(&resize_attr).unwrap(), "1");
+    }
+}
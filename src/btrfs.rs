@@ -0,0 +1,216 @@
+use crate::resize::{GROW_FUDGE_BYTES, ResizeError};
+use crate::superblock::{self, FsDetail};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tracing::{info, warn};
+
+/// A member device of a Btrfs filesystem.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BtrfsMember {
+    /// Device path (e.g. `/dev/vdb1`).
+    pub device: PathBuf,
+    /// Device id within the filesystem.
+    pub devid: u64,
+    /// Size the filesystem uses on this device, in bytes.
+    pub fs_bytes: u64,
+    /// Size of the block device, in bytes.
+    pub device_bytes: u64,
+}
+
+impl BtrfsMember {
+    /// Returns true if the device has grown past what the filesystem uses.
+    pub fn has_grown(&self) -> bool {
+        self.device_bytes > self.fs_bytes.saturating_add(GROW_FUDGE_BYTES)
+    }
+}
+
+/// Grows a mounted Btrfs filesystem on every member device that got bigger.
+///
+/// `btrfs filesystem resize max` only resizes devid 1, so each device is
+/// resized by id with `<devid>:max`. `device` is always resized; the other
+/// members are listed from `/sys/fs/btrfs/<fsid>/devices/` and resized when
+/// their block device is bigger than the size recorded in their superblock.
+pub fn resize_btrfs(device: &Path, mount_point: &Path) -> Result<(), ResizeError> {
+    for devid in devids_to_grow(device)? {
+        let target = format!("{}:max", devid);
+        info!(
+            "Resizing Btrfs devid {} at {}",
+            devid,
+            mount_point.display()
+        );
+
+        let output = Command::new("btrfs")
+            .args(["filesystem", "resize", &target])
+            .arg(mount_point)
+            .output()
+            .map_err(|e| {
+                ResizeError::CommandFailed(format!(
+                    "Failed to execute btrfs filesystem resize: {}",
+                    e
+                ))
+            })?;
+
+        if !output.status.success() {
+            let error = String::from_utf8_lossy(&output.stderr);
+            return Err(ResizeError::ResizeFs(format!(
+                "btrfs filesystem resize {} failed: {}",
+                target,
+                error.trim_end()
+            )));
+        }
+    }
+
+    info!("Successfully resized Btrfs filesystem");
+    Ok(())
+}
+
+/// Returns the ids of the Btrfs devices to resize: the one on `device`,
+/// then every other member that got bigger.
+pub fn devids_to_grow(device: &Path) -> Result<Vec<u64>, ResizeError> {
+    let info = superblock::read_fs_info(device)?;
+    let FsDetail::Btrfs { devid, .. } = info.detail else {
+        return Err(ResizeError::ResizeFs(format!(
+            "{} does not hold a Btrfs filesystem",
+            device.display()
+        )));
+    };
+
+    let members = match &info.uuid {
+        Some(fsid) => read_members(fsid),
+        None => Vec::new(),
+    };
+    Ok(devids_to_resize(devid, &members))
+}
+
+/// Returns true if the Btrfs filesystem on a device is mounted, through this
+/// device or any other member.
+pub fn is_mounted(device: &Path) -> Result<bool, ResizeError> {
+    let info = superblock::read_fs_info(device)?;
+    Ok(info
+        .uuid
+        .is_some_and(|fsid| is_registered_sysfs(Path::new("/sys/fs/btrfs"), &fsid)))
+}
+
+/// The kernel only creates `<sysfs_base>/<fsid>` while the filesystem is
+/// mounted.
+fn is_registered_sysfs(sysfs_base: &Path, fsid: &str) -> bool {
+    sysfs_base.join(fsid).is_dir()
+}
+
+/// Lists the members of a mounted Btrfs filesystem with their sizes.
+/// Members whose superblock or size cannot be read are skipped.
+pub fn read_members(fsid: &str) -> Vec<BtrfsMember> {
+    read_member_names_sysfs(Path::new("/sys/fs/btrfs"), fsid)
+        .into_iter()
+        .filter_map(|name| {
+            let device = PathBuf::from("/dev").join(&name);
+            let member = read_member(&device);
+            if member.is_none() {
+                warn!("Could not read Btrfs member {}", device.display());
+            }
+            member
+        })
+        .collect()
+}
+
+fn read_member(device: &Path) -> Option<BtrfsMember> {
+    let info = superblock::read_fs_info(device).ok()?;
+    let FsDetail::Btrfs {
+        devid,
+        dev_total_bytes,
+        ..
+    } = info.detail
+    else {
+        return None;
+    };
+
+    Some(BtrfsMember {
+        device: device.to_path_buf(),
+        devid,
+        fs_bytes: dev_total_bytes,
+        device_bytes: crate::get_device_size(device).ok()?,
+    })
+}
+
+/// Reads the kernel names of the member devices of a Btrfs filesystem from
+/// a sysfs-like directory structure (`<sysfs_base>/<fsid>/devices/<name>`).
+fn read_member_names_sysfs(sysfs_base: &Path, fsid: &str) -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir(sysfs_base.join(fsid).join("devices")) else {
+        return Vec::new();
+    };
+
+    let mut names: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.file_name().to_str().map(String::from))
+        .collect();
+    names.sort();
+    names
+}
+
+/// Returns the device ids to resize: the grown device first, then every
+/// other member that got bigger.
+fn devids_to_resize(devid: u64, members: &[BtrfsMember]) -> Vec<u64> {
+    let mut devids = vec![devid];
+    for member in members {
+        if member.has_grown() && !devids.contains(&member.devid) {
+            devids.push(member.devid);
+        }
+    }
+    devids
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn member(devid: u64, fs_bytes: u64, device_bytes: u64) -> BtrfsMember {
+        BtrfsMember {
+            device: PathBuf::from(format!("/dev/vd{}1", (b'a' + devid as u8) as char)),
+            devid,
+            fs_bytes,
+            device_bytes,
+        }
+    }
+
+    #[test]
+    fn test_devids_to_resize() {
+        const GIB: u64 = 1 << 30;
+        let members = vec![
+            member(1, 10 * GIB, 10 * GIB),
+            member(2, 10 * GIB, 20 * GIB),
+            member(3, 10 * GIB, 10 * GIB + 4096),
+            member(4, 10 * GIB, 15 * GIB),
+        ];
+
+        // Only the grown members follow the configured one
+        assert_eq!(devids_to_resize(3, &members), vec![3, 2, 4]);
+        assert_eq!(devids_to_resize(2, &members), vec![2, 4]);
+        assert_eq!(devids_to_resize(1, &[]), vec![1]);
+    }
+
+    #[test]
+    fn test_member_has_grown() {
+        assert!(!member(1, 1 << 30, 1 << 30).has_grown());
+        assert!(!member(1, 1 << 30, (1 << 30) + GROW_FUDGE_BYTES).has_grown());
+        assert!(member(1, 1 << 30, 2 << 30).has_grown());
+    }
+
+    #[test]
+    fn test_read_member_names_sysfs() {
+        let tmpdir = tempfile::tempdir().expect("Failed to create temp dir");
+        let base = tmpdir.path();
+        let fsid = "5b8a1e4c-7f2d-4a8e-9c31-0d6f2b7e8a90";
+        for name in ["vdc1", "dm-2", "vdb1"] {
+            std::fs::create_dir_all(base.join(fsid).join("devices").join(name)).unwrap();
+        }
+
+        assert_eq!(
+            read_member_names_sysfs(base, fsid),
+            vec!["dm-2", "vdb1", "vdc1"]
+        );
+        assert!(read_member_names_sysfs(base, "unknown").is_empty());
+
+        assert!(is_registered_sysfs(base, fsid));
+        assert!(!is_registered_sysfs(base, "unknown"));
+    }
+}
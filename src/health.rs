@@ -0,0 +1,383 @@
+use crate::mountinfo::MountInfo;
+use crate::resize::ResizeError;
+use crate::superblock::{self, FsDetail, FsInfo, FsKind};
+use std::path::Path;
+use tracing::warn;
+
+/// Something found wrong with a filesystem before resizing it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HealthIssue {
+    /// Worth reporting, but does not stop the resize.
+    Warning(String),
+    /// The filesystem must not be grown until it is repaired.
+    Problem(String),
+}
+
+/// Checks that a filesystem is in a state where growing it is safe.
+///
+/// `mount` is the mount of the filesystem, if it is mounted. Warnings are
+/// logged; problems are returned together as an error.
+pub fn check_health(device: &Path, mount: Option<&MountInfo>) -> Result<(), ResizeError> {
+    let issues = collect_issues(device, mount);
+    let mut problems = Vec::new();
+
+    for issue in issues {
+        match issue {
+            HealthIssue::Warning(message) => warn!("{}: {}", device.display(), message),
+            HealthIssue::Problem(message) => problems.push(message),
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(ResizeError::Unhealthy(format!(
+            "{}: {}",
+            device.display(),
+            problems.join("; ")
+        )))
+    }
+}
+
+/// Runs every check that applies to the filesystem on a device.
+///
+/// A superblock that cannot be read only skips the checks that need it, so
+/// the mount is still checked.
+pub fn collect_issues(device: &Path, mount: Option<&MountInfo>) -> Vec<HealthIssue> {
+    let mut issues = Vec::new();
+    let info = match superblock::read_fs_info(device) {
+        Ok(info) => Some(info),
+        Err(e) => {
+            issues.push(HealthIssue::Warning(format!(
+                "superblock checks skipped: {}",
+                e
+            )));
+            None
+        }
+    };
+    let kind = info.as_ref().map(|info| info.kind);
+
+    if let Some(info) = &info {
+        issues.extend(superblock_issues(info, mount.is_some()));
+    }
+
+    if let Some(mount) = mount {
+        issues.extend(mount_issues(mount));
+
+        // A shut down XFS filesystem fails every call with EIO, statfs included
+        if kind == Some(FsKind::Xfs)
+            && let Err(nix::errno::Errno::EIO) = nix::sys::statvfs::statvfs(&mount.mount_point)
+        {
+            issues.push(HealthIssue::Problem(
+                "filesystem has been shut down after an error".to_string(),
+            ));
+        }
+    }
+
+    if let Some(info) = &info
+        && info.kind == FsKind::Btrfs
+        && let Some(fsid) = &info.uuid
+    {
+        issues.extend(btrfs_device_issues_sysfs(Path::new("/sys/fs/btrfs"), fsid));
+    }
+
+    issues
+}
+
+/// Checks the state recorded in the superblock.
+///
+/// An unmounted ext2/3/4 filesystem is checked with e2fsck before it is
+/// resized, so its recorded errors are only warnings.
+fn superblock_issues(info: &FsInfo, mounted: bool) -> Vec<HealthIssue> {
+    let mut issues = Vec::new();
+
+    match &info.detail {
+        FsDetail::Ext { error_count, .. } => {
+            let error = if *error_count > 0 {
+                Some(format!(
+                    "{} errors recorded since the last fsck",
+                    error_count
+                ))
+            } else if info.has_errors {
+                Some("superblock state has the error flag set".to_string())
+            } else {
+                None
+            };
+            match error {
+                Some(message) if mounted => issues.push(HealthIssue::Problem(message)),
+                Some(message) => issues.push(HealthIssue::Warning(format!(
+                    "{}, e2fsck will check it before resizing",
+                    message
+                ))),
+                None => {}
+            }
+            // The valid flag is only cleared on mount without a journal
+            if mounted && !info.clean {
+                issues.push(HealthIssue::Warning(
+                    "filesystem was not cleanly unmounted, running e2fsck is recommended"
+                        .to_string(),
+                ));
+            }
+        }
+        FsDetail::Xfs { .. } => {
+            if !info.clean {
+                issues.push(HealthIssue::Problem(
+                    "superblock is marked as still being created".to_string(),
+                ));
+            }
+        }
+        FsDetail::Btrfs { .. } => {
+            if info.has_errors {
+                issues.push(HealthIssue::Problem(
+                    "superblock has the error flag set".to_string(),
+                ));
+            }
+        }
+        FsDetail::F2fs { .. } => {
+            if info.has_errors {
+                issues.push(HealthIssue::Problem(
+                    "superblock has recorded errors, run fsck.f2fs".to_string(),
+                ));
+            }
+        }
+        FsDetail::Nilfs2 { .. } => {
+            if info.has_errors {
+                issues.push(HealthIssue::Problem(
+                    "superblock state has the error flag set".to_string(),
+                ));
+            }
+        }
+        FsDetail::Bcachefs { .. }
+        | FsDetail::Swap { .. }
+        | FsDetail::Ntfs { .. }
+        | FsDetail::Vfat { .. } => {}
+    }
+
+    issues
+}
+
+/// Checks that a mounted filesystem can be written to.
+fn mount_issues(mount: &MountInfo) -> Vec<HealthIssue> {
+    let mut issues = Vec::new();
+
+    if !mount.is_read_write() {
+        issues.push(HealthIssue::Problem(format!(
+            "{} is mounted read-only",
+            mount.mount_point.display()
+        )));
+    } else if mount.super_options.split(',').any(|opt| opt == "ro") {
+        // The superblock went read-only, e.g. ext4 with errors=remount-ro
+        issues.push(HealthIssue::Problem(format!(
+            "filesystem at {} has been remounted read-only",
+            mount.mount_point.display()
+        )));
+    }
+
+    issues
+}
+
+/// Checks the devices of a Btrfs filesystem in a sysfs-like directory
+/// structure.
+///
+/// `<sysfs_base>/<fsid>/devinfo/<devid>/` has a `missing` flag and the
+/// `error_stats` counters that `btrfs device stats` reports. Kernels without
+/// `error_stats` only get the missing device check.
+fn btrfs_device_issues_sysfs(sysfs_base: &Path, fsid: &str) -> Vec<HealthIssue> {
+    let devinfo = sysfs_base.join(fsid).join("devinfo");
+    let mut devids: Vec<_> = match std::fs::read_dir(&devinfo) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .filter_map(|e| e.file_name().to_str().map(String::from))
+            .collect(),
+        Err(_) => {
+            return vec![HealthIssue::Warning(format!(
+                "could not read {}, device errors were not checked",
+                devinfo.display()
+            ))];
+        }
+    };
+    devids.sort();
+
+    let mut issues = Vec::new();
+    for devid in devids {
+        let dir = devinfo.join(&devid);
+
+        let missing = std::fs::read_to_string(dir.join("missing")).unwrap_or_default();
+        if missing.trim() == "1" {
+            issues.push(HealthIssue::Problem(format!("device {} is missing", devid)));
+            continue;
+        }
+
+        let Ok(stats) = std::fs::read_to_string(dir.join("error_stats")) else {
+            continue;
+        };
+        let errors: Vec<String> = stats
+            .lines()
+            .filter_map(|line| line.split_once(' '))
+            .filter(|(_, count)| count.trim().parse::<u64>().is_ok_and(|n| n > 0))
+            .map(|(name, count)| format!("{} {}", name, count.trim()))
+            .collect();
+        if !errors.is_empty() {
+            issues.push(HealthIssue::Problem(format!(
+                "device {} has recorded errors ({})",
+                devid,
+                errors.join(", ")
+            )));
+        }
+    }
+
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::superblock::FsFeatures;
+    use std::path::PathBuf;
+
+    fn ext_info(clean: bool, has_errors: bool, error_count: u32) -> FsInfo {
+        FsInfo {
+            kind: FsKind::Ext4,
+            uuid: None,
+            label: None,
+            block_size: 4096,
+            total_blocks: 262_144,
+            features: FsFeatures::default(),
+            clean,
+            has_errors,
+            detail: FsDetail::Ext {
+                blocks_per_group: 32_768,
+                reserved_gdt_blocks: 63,
+                desc_size: 64,
+                errors_behavior: 1,
+                error_count,
+            },
+        }
+    }
+
+    fn mount(mount_options: &str, super_options: &str) -> MountInfo {
+        MountInfo {
+            major: 252,
+            minor: 1,
+            mount_point: PathBuf::from("/data"),
+            mount_options: mount_options.to_string(),
+            fs_type: "ext4".to_string(),
+            source: "/dev/vda1".to_string(),
+            super_options: super_options.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_superblock_issues_ext() {
+        assert!(superblock_issues(&ext_info(true, false, 0), true).is_empty());
+
+        let issues = superblock_issues(&ext_info(true, true, 3), true);
+        assert_eq!(
+            issues,
+            vec![HealthIssue::Problem(
+                "3 errors recorded since the last fsck".to_string()
+            )]
+        );
+
+        let issues = superblock_issues(&ext_info(true, true, 0), true);
+        assert!(matches!(issues[..], [HealthIssue::Problem(_)]));
+
+        let issues = superblock_issues(&ext_info(false, false, 0), true);
+        assert!(matches!(issues[..], [HealthIssue::Warning(_)]));
+
+        // Unmounted, e2fsck runs first
+        let issues = superblock_issues(&ext_info(true, true, 3), false);
+        assert!(matches!(issues[..], [HealthIssue::Warning(ref m)] if m.contains("e2fsck")));
+    }
+
+    #[test]
+    fn test_superblock_issues_xfs_in_progress() {
+        let info = FsInfo {
+            kind: FsKind::Xfs,
+            clean: false,
+            detail: FsDetail::Xfs {
+                ag_blocks: 655_360,
+                ag_count: 4,
+                internal_log_blocks: 2560,
+                version: 5,
+            },
+            ..ext_info(true, false, 0)
+        };
+        assert!(matches!(
+            superblock_issues(&info, true)[..],
+            [HealthIssue::Problem(_)]
+        ));
+    }
+
+    #[test]
+    fn test_mount_issues() {
+        assert!(mount_issues(&mount("rw,relatime", "rw,errors=remount-ro")).is_empty());
+
+        let issues = mount_issues(&mount("ro,relatime", "ro"));
+        assert_eq!(
+            issues,
+            vec![HealthIssue::Problem(
+                "/data is mounted read-only".to_string()
+            )]
+        );
+
+        let issues = mount_issues(&mount("rw,relatime", "ro,errors=remount-ro"));
+        assert!(matches!(issues[..], [HealthIssue::Problem(ref m)] if m.contains("remounted")));
+    }
+
+    #[test]
+    fn test_collect_issues_without_superblock() {
+        let file = tempfile::NamedTempFile::new().expect("Failed to create temp file");
+        file.as_file().set_len(1024 * 1024).unwrap();
+
+        let issues = collect_issues(file.path(), Some(&mount("ro,relatime", "ro")));
+        assert!(matches!(
+            issues[..],
+            [HealthIssue::Warning(ref w), HealthIssue::Problem(ref p)]
+                if w.contains("superblock checks skipped") && p.contains("read-only")
+        ));
+    }
+
+    #[test]
+    fn test_btrfs_device_issues() {
+        let tmpdir = tempfile::tempdir().expect("Failed to create temp dir");
+        let base = tmpdir.path();
+        let fsid = "5b8a1e4c-7f2d-4a8e-9c31-0d6f2b7e8a90";
+        let devinfo = base.join(fsid).join("devinfo");
+
+        for devid in ["1", "2", "3"] {
+            std::fs::create_dir_all(devinfo.join(devid)).unwrap();
+            std::fs::write(devinfo.join(devid).join("missing"), "0\n").unwrap();
+        }
+        std::fs::write(
+            devinfo.join("1").join("error_stats"),
+            "write_errs 0\nread_errs 0\nflush_errs 0\ncorruption_errs 0\ngeneration_errs 0\n",
+        )
+        .unwrap();
+        assert!(btrfs_device_issues_sysfs(base, fsid).is_empty());
+
+        std::fs::write(
+            devinfo.join("2").join("error_stats"),
+            "write_errs 0\nread_errs 4\nflush_errs 0\ncorruption_errs 1\ngeneration_errs 0\n",
+        )
+        .unwrap();
+        std::fs::write(devinfo.join("3").join("missing"), "1\n").unwrap();
+
+        assert_eq!(
+            btrfs_device_issues_sysfs(base, fsid),
+            vec![
+                HealthIssue::Problem(
+                    "device 2 has recorded errors (read_errs 4, corruption_errs 1)".to_string()
+                ),
+                HealthIssue::Problem("device 3 is missing".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_btrfs_device_issues_without_sysfs() {
+        let tmpdir = tempfile::tempdir().expect("Failed to create temp dir");
+        let issues = btrfs_device_issues_sysfs(tmpdir.path(), "missing-fsid");
+        assert!(matches!(issues[..], [HealthIssue::Warning(_)]));
+    }
+}
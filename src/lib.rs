@@ -2,7 +2,18 @@ use std::path::{Path, PathBuf};
 use thiserror::Error;
 use tracing::debug;
 
+pub mod bcache;
+pub mod btrfs;
+pub mod health;
+pub mod lvm;
+pub mod mdraid;
+pub mod mountinfo;
+pub mod multipath;
 pub mod resize;
+pub mod superblock;
+pub mod swap;
+pub mod tempmount;
+pub mod zfs;
 
 /// Searches for an executable in the system PATH.
 ///
@@ -63,7 +74,10 @@ pub fn get_device_size(device_path: &Path) -> Result<u64, DeviceError> {
 ///
 /// Expects a file at `<sysfs_base>/<dev_name>/size` containing the sector count.
 /// The sector size in sysfs is always 512 bytes.
-fn read_sysfs_device_size(sysfs_base: &Path, dev_name: &str) -> Result<u64, DeviceError> {
+pub(crate) fn read_sysfs_device_size(
+    sysfs_base: &Path,
+    dev_name: &str,
+) -> Result<u64, DeviceError> {
     let size_path = sysfs_base.join(dev_name).join("size");
 
     let sectors_str = std::fs::read_to_string(&size_path).map_err(|e| {
@@ -81,6 +95,29 @@ fn read_sysfs_device_size(sysfs_base: &Path, dev_name: &str) -> Result<u64, Devi
     Ok(sectors * 512)
 }
 
+/// Lists the kernel names of the devices under a block device.
+///
+/// Reads `<sysfs_base>/<dev_name>/slaves/`, which device-mapper and md
+/// devices use to point at the devices they are built on. The result is
+/// sorted, and empty for devices without a `slaves` directory.
+pub(crate) fn read_sysfs_slaves(
+    sysfs_base: &Path,
+    dev_name: &str,
+) -> Result<Vec<String>, DeviceError> {
+    let slaves_dir = sysfs_base.join(dev_name).join("slaves");
+    if !slaves_dir.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut slaves: Vec<String> = std::fs::read_dir(&slaves_dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .collect();
+    slaves.sort();
+
+    Ok(slaves)
+}
+
 /// Resolves a device path to its kernel name (e.g. `/dev/sda1` → `sda1`, `/dev/mapper/foo` → `dm-0`)
 fn resolve_device_name(device_path: &Path) -> Result<String, DeviceError> {
     let real_path = std::fs::canonicalize(device_path)
@@ -98,6 +135,134 @@ fn resolve_device_name(device_path: &Path) -> Result<String, DeviceError> {
         })
 }
 
+/// fstab-style device specifiers and the `/dev/disk` directory holding
+/// their udev links.
+const DEVICE_SPECIFIERS: &[(&str, &str)] = &[
+    ("UUID=", "by-uuid"),
+    ("LABEL=", "by-label"),
+    ("PARTUUID=", "by-partuuid"),
+    ("PARTLABEL=", "by-partlabel"),
+];
+
+/// Resolves an fstab-style device specifier (`UUID=…`, `LABEL=…`,
+/// `PARTUUID=…` or `PARTLABEL=…`) to a device path. Plain paths are
+/// returned unchanged.
+///
+/// The `/dev/disk/by-*` links are used when udev created them. Otherwise
+/// filesystem UUIDs and labels are looked up by reading the superblock of
+/// every block device, and partition UUIDs and labels from their `uevent`.
+pub fn resolve_device_spec(device: &Path) -> Result<PathBuf, DeviceError> {
+    resolve_device_spec_in(
+        Path::new("/dev/disk"),
+        Path::new("/sys/class/block"),
+        Path::new("/dev"),
+        device,
+    )
+}
+
+fn resolve_device_spec_in(
+    disk_dir: &Path,
+    sysfs_base: &Path,
+    dev_dir: &Path,
+    device: &Path,
+) -> Result<PathBuf, DeviceError> {
+    let Some(spec) = device.to_str() else {
+        return Ok(device.to_path_buf());
+    };
+    let Some(&(prefix, by_dir)) = DEVICE_SPECIFIERS
+        .iter()
+        .find(|(prefix, _)| spec.starts_with(prefix))
+    else {
+        return Ok(device.to_path_buf());
+    };
+
+    let value = spec[prefix.len()..].trim_matches('"');
+    if value.is_empty() {
+        return Err(DeviceError::DeviceInfo(format!(
+            "Empty device specifier {}",
+            spec
+        )));
+    }
+
+    let link = disk_dir.join(by_dir).join(udev_escape(value));
+    if let Ok(real) = std::fs::canonicalize(&link) {
+        debug!("Resolved {} to {:?} through {:?}", spec, real, link);
+        return Ok(real);
+    }
+
+    debug!(
+        "{:?} does not exist, scanning block devices for {}",
+        link, spec
+    );
+    let mut names: Vec<String> = std::fs::read_dir(sysfs_base)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .collect();
+    names.sort();
+
+    let matches: Vec<PathBuf> = names
+        .iter()
+        .filter(|name| match prefix {
+            "UUID=" => read_identity(sysfs_base, dev_dir, name)
+                .and_then(|id| id.uuid)
+                .is_some_and(|uuid| uuid.eq_ignore_ascii_case(value)),
+            "LABEL=" => read_identity(sysfs_base, dev_dir, name)
+                .and_then(|id| id.label)
+                .is_some_and(|label| label == value),
+            "PARTUUID=" => read_uevent_var(sysfs_base, name, "PARTUUID")
+                .is_some_and(|uuid| uuid.eq_ignore_ascii_case(value)),
+            _ => read_uevent_var(sysfs_base, name, "PARTNAME").is_some_and(|label| label == value),
+        })
+        .map(|name| dev_dir.join(name))
+        .collect();
+
+    match matches.as_slice() {
+        [found] => {
+            debug!("Resolved {} to {:?} by scanning", spec, found);
+            Ok(found.clone())
+        }
+        [] => Err(DeviceError::NotFound(device.to_path_buf())),
+        _ => Err(DeviceError::DeviceInfo(format!(
+            "{} matches several devices: {:?}",
+            spec, matches
+        ))),
+    }
+}
+
+/// Reads the filesystem identity of a block device, skipping empty ones
+/// (e.g. unused loop devices or drives without media).
+fn read_identity(sysfs_base: &Path, dev_dir: &Path, name: &str) -> Option<resize::FsIdentity> {
+    match read_sysfs_device_size(sysfs_base, name) {
+        Ok(size) if size > 0 => resize::read_fs_identity(&dev_dir.join(name)).ok(),
+        _ => None,
+    }
+}
+
+/// Reads a variable (e.g. `PARTNAME`) from `<sysfs_base>/<name>/uevent`.
+fn read_uevent_var(sysfs_base: &Path, name: &str, key: &str) -> Option<String> {
+    let uevent = std::fs::read_to_string(sysfs_base.join(name).join("uevent")).ok()?;
+    uevent.lines().find_map(|line| {
+        line.strip_prefix(key)
+            .and_then(|rest| rest.strip_prefix('='))
+            .map(String::from)
+    })
+}
+
+/// Escapes a UUID or label the way udev names its `/dev/disk/by-*` links:
+/// bytes other than ASCII alphanumerics, `#+-.:=@_` and UTF-8 sequences
+/// become `\xNN`.
+fn udev_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if !c.is_ascii() || c.is_ascii_alphanumeric() || "#+-.:=@_".contains(c) {
+            escaped.push(c);
+        } else {
+            escaped.push_str(&format!("\\x{:02x}", c as u32));
+        }
+    }
+    escaped
+}
+
 /// Checks if all required tools are available in the system
 pub fn check_requirements(fs_types: &[&str]) -> Result<(), DeviceError> {
     let mut required_tools = vec!["sfdisk"];
@@ -108,6 +273,7 @@ pub fn check_requirements(fs_types: &[&str]) -> Result<(), DeviceError> {
             "ext4" | "ext3" | "ext2" => {
                 if !required_tools.contains(&"resize2fs") {
                     required_tools.push("resize2fs");
+                    required_tools.push("e2fsck");
                 }
             }
             "xfs" => {
@@ -120,6 +286,36 @@ pub fn check_requirements(fs_types: &[&str]) -> Result<(), DeviceError> {
                     required_tools.push("btrfs");
                 }
             }
+            "f2fs" => {
+                if !required_tools.contains(&"resize.f2fs") {
+                    required_tools.push("resize.f2fs");
+                }
+            }
+            "bcachefs" => {
+                if !required_tools.contains(&"bcachefs") {
+                    required_tools.push("bcachefs");
+                }
+            }
+            "nilfs2" => {
+                if !required_tools.contains(&"nilfs-resize") {
+                    required_tools.push("nilfs-resize");
+                }
+            }
+            "swap" => {
+                if !required_tools.contains(&"mkswap") {
+                    required_tools.extend(["mkswap", "swapon", "swapoff"]);
+                }
+            }
+            "ntfs" => {
+                if !required_tools.contains(&"ntfsresize") {
+                    required_tools.extend(["ntfsfix", "ntfsresize"]);
+                }
+            }
+            "vfat" => {
+                if !required_tools.contains(&"fatresize") {
+                    required_tools.push("fatresize");
+                }
+            }
             _ => {
                 return Err(DeviceError::MissingTool(format!(
                     "Unsupported filesystem: {}",
@@ -129,7 +325,12 @@ pub fn check_requirements(fs_types: &[&str]) -> Result<(), DeviceError> {
         }
     }
 
-    for tool in &required_tools {
+    check_tools(&required_tools)
+}
+
+/// Checks that every tool in the list can be found in the system PATH
+pub fn check_tools(tools: &[&str]) -> Result<(), DeviceError> {
+    for tool in tools {
         if find_in_path(tool).is_none() {
             return Err(DeviceError::MissingTool((*tool).to_string()));
         }
@@ -185,6 +386,10 @@ pub fn analyze_device(device_path: &Path) -> Result<BlockDevice, DeviceError> {
 /// - The symlink `/sys/class/block/sda1` points to `../../devices/.../sda/sda1`,
 ///   so the parent directory name is the disk name
 ///
+/// For a kpartx partition on a device-mapper disk (e.g. a multipath map):
+/// - `/sys/class/block/dm-4/dm/uuid` starts with `part<N>-`
+/// - `/sys/class/block/dm-4/slaves/` holds the single dm disk under it
+///
 /// For a whole disk (e.g. `vdb`):
 /// - `/sys/class/block/vdb/partition` does not exist
 /// - The disk name is the device name itself
@@ -217,6 +422,12 @@ fn analyze_device_sysfs(
         None
     };
 
+    if partition_number.is_none()
+        && let Some(kpartx) = analyze_kpartx_partition(sysfs_base, dev_name)?
+    {
+        return Ok(kpartx);
+    }
+
     // Find parent disk name
     let disk_name = if partition_number.is_some() {
         // Follow symlink: /sys/class/block/sda1 -> ../../devices/.../sda/sda1
@@ -243,6 +454,41 @@ fn analyze_device_sysfs(
     Ok((disk_name, partition_number))
 }
 
+/// Detects a kpartx partition mapping from its dm uuid (`part<N>-<disk uuid>`).
+///
+/// Returns the dm disk under it and the partition number, or `None` if the
+/// device is not a kpartx partition.
+fn analyze_kpartx_partition(
+    sysfs_base: &Path,
+    dev_name: &str,
+) -> Result<Option<(String, Option<u32>)>, DeviceError> {
+    let uuid_path = sysfs_base.join(dev_name).join("dm").join("uuid");
+    let uuid = match std::fs::read_to_string(&uuid_path) {
+        Ok(uuid) => uuid,
+        Err(_) => return Ok(None),
+    };
+
+    let partition_number = match uuid
+        .trim()
+        .strip_prefix("part")
+        .and_then(|rest| rest.split_once('-'))
+        .and_then(|(num, _)| num.parse::<u32>().ok())
+    {
+        Some(num) => num,
+        None => return Ok(None),
+    };
+
+    let slaves = read_sysfs_slaves(sysfs_base, dev_name)?;
+    match slaves.as_slice() {
+        [disk] => Ok(Some((disk.clone(), Some(partition_number)))),
+        _ => Err(DeviceError::DeviceInfo(format!(
+            "kpartx partition {} should have exactly one parent, found {}",
+            dev_name,
+            slaves.len()
+        ))),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -418,6 +664,171 @@ mod tests {
         assert!(matches!(result, Err(DeviceError::DeviceInfo(_))));
     }
 
+    /// Creates a fake device-mapper device with a dm uuid and slaves.
+    fn create_fake_sysfs_dm(base: &Path, dm_name: &str, uuid: &str, slaves: &[&str]) {
+        let dm_dir = base.join(dm_name).join("dm");
+        std::fs::create_dir_all(&dm_dir).expect("Failed to create dm dir");
+        std::fs::write(dm_dir.join("uuid"), format!("{}\n", uuid)).expect("Failed to write uuid");
+        let slaves_dir = base.join(dm_name).join("slaves");
+        std::fs::create_dir_all(&slaves_dir).expect("Failed to create slaves dir");
+        for slave in slaves {
+            std::fs::create_dir_all(slaves_dir.join(slave)).expect("Failed to create slave");
+        }
+    }
+
+    #[test]
+    fn test_analyze_sysfs_kpartx_partition() {
+        let tmpdir = tempfile::tempdir().expect("Failed to create temp dir");
+        create_fake_sysfs_dm(tmpdir.path(), "dm-3", "mpath-3600a0b80", &["sdb", "sdc"]);
+        create_fake_sysfs_dm(tmpdir.path(), "dm-4", "part2-mpath-3600a0b80", &["dm-3"]);
+
+        let (disk_name, part_num) = analyze_device_sysfs(tmpdir.path(), "dm-4").unwrap();
+        assert_eq!(disk_name, "dm-3");
+        assert_eq!(part_num, Some(2));
+
+        // The multipath map itself is a whole disk
+        let (disk_name, part_num) = analyze_device_sysfs(tmpdir.path(), "dm-3").unwrap();
+        assert_eq!(disk_name, "dm-3");
+        assert_eq!(part_num, None);
+    }
+
+    #[test]
+    fn test_read_sysfs_slaves() {
+        let tmpdir = tempfile::tempdir().expect("Failed to create temp dir");
+        create_fake_sysfs_dm(tmpdir.path(), "dm-0", "LVM-abc", &["sdb1", "sda1"]);
+        create_fake_sysfs_disk(tmpdir.path(), "sda");
+
+        let slaves = read_sysfs_slaves(tmpdir.path(), "dm-0").unwrap();
+        assert_eq!(slaves, vec!["sda1", "sdb1"]);
+        assert!(read_sysfs_slaves(tmpdir.path(), "sda").unwrap().is_empty());
+    }
+
+    /// Creates `<base>/sysfs/<name>` with a size and uevent, and
+    /// `<base>/dev/<name>` holding the given bytes at their offsets.
+    fn create_fake_block(base: &Path, name: &str, uevent: &str, writes: &[(u64, &[u8])]) {
+        use std::os::unix::fs::FileExt;
+
+        let sys_dir = base.join("sysfs").join(name);
+        std::fs::create_dir_all(&sys_dir).unwrap();
+        std::fs::write(sys_dir.join("size"), "256\n").unwrap();
+        std::fs::write(sys_dir.join("uevent"), uevent).unwrap();
+
+        std::fs::create_dir_all(base.join("dev")).unwrap();
+        let file = std::fs::File::create(base.join("dev").join(name)).unwrap();
+        file.set_len(256 * 512).unwrap();
+        for (offset, data) in writes {
+            file.write_all_at(data, *offset).unwrap();
+        }
+    }
+
+    fn resolve_in(base: &Path, spec: &str) -> Result<PathBuf, DeviceError> {
+        resolve_device_spec_in(
+            &base.join("disk"),
+            &base.join("sysfs"),
+            &base.join("dev"),
+            Path::new(spec),
+        )
+    }
+
+    #[test]
+    fn test_resolve_device_spec_plain_path() {
+        let tmpdir = tempfile::tempdir().expect("Failed to create temp dir");
+        assert_eq!(
+            resolve_in(tmpdir.path(), "/dev/vda1").unwrap(),
+            PathBuf::from("/dev/vda1")
+        );
+    }
+
+    #[test]
+    fn test_resolve_device_spec_udev_link() {
+        let tmpdir = tempfile::tempdir().expect("Failed to create temp dir");
+        let base = tmpdir.path();
+        create_fake_block(base, "vda1", "", &[]);
+        let by_label = base.join("disk").join("by-label");
+        std::fs::create_dir_all(&by_label).unwrap();
+        std::os::unix::fs::symlink(base.join("dev").join("vda1"), by_label.join("my\\x20root"))
+            .unwrap();
+
+        let resolved = resolve_in(base, "LABEL=\"my root\"").unwrap();
+        assert_eq!(
+            resolved,
+            base.join("dev").join("vda1").canonicalize().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_resolve_device_spec_superblock_scan() {
+        let tmpdir = tempfile::tempdir().expect("Failed to create temp dir");
+        let base = tmpdir.path();
+        let magic = 0xEF53u16.to_le_bytes();
+        let uuid = [
+            0x3f, 0x1c, 0x4e, 0x2a, 0x9b, 0x01, 0x4c, 0x5d, 0x8e, 0x7f, 0x10, 0x22, 0x33, 0x44,
+            0x55, 0x66,
+        ];
+        create_fake_block(
+            base,
+            "vda1",
+            "",
+            &[(1080, &magic), (1128, &uuid), (1144, b"boot")],
+        );
+        create_fake_block(base, "vda2", "", &[(0, b"XFSB"), (108, b"data")]);
+        std::fs::create_dir_all(base.join("disk")).unwrap();
+
+        assert_eq!(
+            resolve_in(base, "UUID=3F1C4E2A-9B01-4C5D-8E7F-102233445566").unwrap(),
+            base.join("dev").join("vda1")
+        );
+        assert_eq!(
+            resolve_in(base, "LABEL=data").unwrap(),
+            base.join("dev").join("vda2")
+        );
+        assert!(matches!(
+            resolve_in(base, "LABEL=missing"),
+            Err(DeviceError::NotFound(_))
+        ));
+        assert!(matches!(
+            resolve_in(base, "UUID="),
+            Err(DeviceError::DeviceInfo(_))
+        ));
+    }
+
+    #[test]
+    fn test_resolve_device_spec_partition_scan() {
+        let tmpdir = tempfile::tempdir().expect("Failed to create temp dir");
+        let base = tmpdir.path();
+        create_fake_block(
+            base,
+            "vda1",
+            "MAJOR=252\nMINOR=1\nDEVNAME=vda1\nDEVTYPE=partition\nPARTN=1\n\
+             PARTNAME=EFI\nPARTUUID=0fc63daf-8483-4772-8e79-3d69d8477de4\n",
+            &[],
+        );
+        create_fake_block(base, "vda2", "PARTN=2\nPARTNAME=root\n", &[]);
+        create_fake_block(base, "vdb1", "PARTN=1\nPARTNAME=root\n", &[]);
+
+        assert_eq!(
+            resolve_in(base, "PARTUUID=0FC63DAF-8483-4772-8E79-3D69D8477DE4").unwrap(),
+            base.join("dev").join("vda1")
+        );
+        assert_eq!(
+            resolve_in(base, "PARTLABEL=EFI").unwrap(),
+            base.join("dev").join("vda1")
+        );
+        // Two partitions share the label: refuse to guess
+        assert!(matches!(
+            resolve_in(base, "PARTLABEL=root"),
+            Err(DeviceError::DeviceInfo(_))
+        ));
+    }
+
+    #[test]
+    fn test_udev_escape() {
+        assert_eq!(udev_escape("root"), "root");
+        assert_eq!(udev_escape("my root"), "my\\x20root");
+        assert_eq!(udev_escape("a/b"), "a\\x2fb");
+        assert_eq!(udev_escape("été_1.0"), "été_1.0");
+    }
+
     #[test]
     fn test_find_in_path_existing_tool() {
         // "sh" should exist on any Unix system
@@ -439,12 +850,30 @@ mod tests {
 
     #[test]
     fn test_check_requirements() {
-        let fs_types = vec!["ext4", "xfs", "btrfs"];
+        let fs_types = vec![
+            "ext4", "xfs", "btrfs", "f2fs", "bcachefs", "nilfs2", "swap", "ntfs", "vfat",
+        ];
         match check_requirements(&fs_types) {
             Ok(_) => println!("All tools are available"),
             Err(DeviceError::MissingTool(tool)) => {
                 assert!(
-                    ["sfdisk", "resize2fs", "xfs_growfs", "btrfs"].contains(&tool.as_str()),
+                    [
+                        "sfdisk",
+                        "resize2fs",
+                        "e2fsck",
+                        "xfs_growfs",
+                        "btrfs",
+                        "resize.f2fs",
+                        "bcachefs",
+                        "nilfs-resize",
+                        "mkswap",
+                        "swapon",
+                        "swapoff",
+                        "ntfsfix",
+                        "ntfsresize",
+                        "fatresize"
+                    ]
+                    .contains(&tool.as_str()),
                     "Unexpected missing tool: {}",
                     tool
                 );
@@ -453,6 +882,15 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_check_tools() {
+        assert!(check_tools(&["sh"]).is_ok());
+        assert!(matches!(
+            check_tools(&["sh", "nonexistent_tool_xyz_12345"]),
+            Err(DeviceError::MissingTool(tool)) if tool == "nonexistent_tool_xyz_12345"
+        ));
+    }
+
     #[test]
     fn test_analyze_invalid_device() {
         let result = analyze_device(Path::new("/dev/nonexistent"));
@@ -0,0 +1,638 @@
+use crate::resize::ResizeError;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tracing::info;
+
+/// Smallest metadata LV that lvm creates for a thin pool (2 MiB).
+const THIN_POOL_MIN_METADATA_BYTES: u64 = 2 * 1024 * 1024;
+
+/// Largest metadata size the dm-thin target can use (`DM_THIN_MAX_METADATA_SIZE`,
+/// about 15.9 GiB). Anything above this is wasted, so metadata is capped here.
+const THIN_POOL_MAX_METADATA_BYTES: u64 = 255 * ((1 << 14) - 64) * 4096;
+
+/// Kind of LVM pool that can be extended after its PV grows.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PoolKind {
+    /// Thin pool: a data LV plus a metadata LV (`segtype` `thin-pool`).
+    Thin,
+    /// VDO pool: a single deduplicated data LV (`segtype` `vdo-pool`).
+    Vdo,
+}
+
+impl PoolKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PoolKind::Thin => "thin pool",
+            PoolKind::Vdo => "VDO pool",
+        }
+    }
+}
+
+/// Current state of an LVM pool and its VG, in bytes.
+#[derive(Debug, PartialEq)]
+struct PoolInfo {
+    kind: PoolKind,
+    data_size: u64,
+    /// Size of the thin pool metadata LV, or 0 for VDO pools.
+    metadata_size: u64,
+    vg_free: u64,
+    extent_size: u64,
+}
+
+/// Sizes an LVM pool would be extended to, in bytes.
+#[derive(Debug, PartialEq)]
+pub struct PoolPlan {
+    pub kind: PoolKind,
+    pub data_size: u64,
+    pub new_data_size: u64,
+    pub metadata_size: u64,
+    pub new_metadata_size: u64,
+}
+
+impl PoolPlan {
+    /// Returns true if the plan changes neither the data nor the metadata size.
+    pub fn is_noop(&self) -> bool {
+        self.new_data_size == self.data_size && self.new_metadata_size == self.metadata_size
+    }
+}
+
+/// Computes how an LVM pool (`vg/pool`) would be extended into `growth`
+/// bytes, the space its PV gained (see [`pv_tail_free`]).
+///
+/// Free space the VG already had is left alone, so the extension is capped
+/// at the VG free space plus `pending`, which lets dry-run account for a PV
+/// that has not been resized yet.
+pub fn plan_pool_extension(pool: &str, growth: u64, pending: u64) -> Result<PoolPlan, ResizeError> {
+    let info = query_pool(pool)?;
+    Ok(compute_pool_plan(&info, growth, pending))
+}
+
+/// Extends an LVM thin pool or VDO pool (`vg/pool`) into `growth` bytes,
+/// the space its PV gained.
+///
+/// For thin pools the metadata LV is extended first, keeping its current
+/// ratio to the data LV, so that the data LV can then take the rest.
+///
+/// Returns `true` if the pool was extended.
+pub fn extend_pool(pool: &str, growth: u64) -> Result<bool, ResizeError> {
+    let plan = plan_pool_extension(pool, growth, 0)?;
+
+    if plan.is_noop() {
+        info!(
+            "LVM {} {} has no new space to grow into",
+            plan.kind.as_str(),
+            pool
+        );
+        return Ok(false);
+    }
+
+    if plan.new_metadata_size > plan.metadata_size {
+        info!(
+            "Extending metadata of {} {}: {} -> {} bytes",
+            plan.kind.as_str(),
+            pool,
+            plan.metadata_size,
+            plan.new_metadata_size
+        );
+        run_lvextend(&[
+            "--poolmetadatasize",
+            &format!("{}b", plan.new_metadata_size),
+            pool,
+        ])?;
+    }
+
+    if plan.new_data_size > plan.data_size {
+        info!(
+            "Extending {} {}: {} -> {} bytes",
+            plan.kind.as_str(),
+            pool,
+            plan.data_size,
+            plan.new_data_size
+        );
+        run_lvextend(&["-L", &format!("{}b", plan.new_data_size), pool])?;
+    }
+
+    info!("Successfully extended LVM {} {}", plan.kind.as_str(), pool);
+    Ok(true)
+}
+
+/// Returns the free space at the end of a PV, in bytes: the extents that
+/// `pvresize` appended and nothing has allocated yet.
+///
+/// This is read from the PV on every call rather than taken from what
+/// `pvresize` reported, so space left behind by a failed `lvextend` is still
+/// found on the next run, while free extents earlier on the PV, e.g. left
+/// unallocated on purpose, are not counted.
+pub fn pv_tail_free(pv_device: &Path) -> Result<u64, ResizeError> {
+    let output = Command::new("lvm")
+        .args([
+            "pvs",
+            "--segments",
+            "--noheadings",
+            "--nosuffix",
+            "--units",
+            "b",
+            "--separator",
+            ":",
+            "-o",
+            "pvseg_start,pvseg_size,lv_name,vg_extent_size",
+            &pv_device.to_string_lossy(),
+        ])
+        .output()
+        .map_err(|e| ResizeError::CommandFailed(format!("Failed to execute lvm pvs: {}", e)))?;
+    if !output.status.success() {
+        let error = String::from_utf8_lossy(&output.stderr);
+        return Err(ResizeError::DeviceNotFound(format!(
+            "PV {}: {}",
+            pv_device.display(),
+            error.trim_end()
+        )));
+    }
+
+    parse_pv_tail_free(&String::from_utf8_lossy(&output.stdout)).ok_or_else(|| {
+        ResizeError::ResizeFs(format!("Unexpected pvs output for {}", pv_device.display()))
+    })
+}
+
+/// Parses `pvs --segments` output with the fields
+/// `pvseg_start,pvseg_size,lv_name,vg_extent_size` (extents, extents, name,
+/// bytes) and returns the size of the last segment if it is free.
+fn parse_pv_tail_free(output: &str) -> Option<u64> {
+    let mut last: Option<(u64, u64, bool, u64)> = None;
+    for line in output.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        let fields: Vec<&str> = line.split(':').map(str::trim).collect();
+        let [start, size, lv_name, extent_size] = fields[..] else {
+            return None;
+        };
+        let segment = (
+            start.parse().ok()?,
+            size.parse().ok()?,
+            lv_name.is_empty(),
+            extent_size.parse().ok()?,
+        );
+        if last.is_none_or(|last| segment.0 > last.0) {
+            last = Some(segment);
+        }
+    }
+
+    let (_, size, free, extent_size) = last?;
+    Some(if free { size * extent_size } else { 0 })
+}
+
+/// Returns the device-mapper name (e.g. `vg-swap`) of an LVM logical
+/// volume, or `None` if the device is not one.
+pub fn logical_volume_name(device: &Path) -> Option<String> {
+    let canonical = std::fs::canonicalize(device).ok()?;
+    let name = canonical.file_name()?.to_str()?;
+    read_lv_dm_name_sysfs(Path::new("/sys/class/block"), name)
+}
+
+/// Reads the dm name of a logical volume from a sysfs-like directory
+/// structure. LVs have a `dm/uuid` starting with `LVM-`; the hidden LVs
+/// behind thin pools, snapshots and RAID LVs carry a suffix after the LV
+/// uuid (e.g. `-tpool`) and are skipped.
+fn read_lv_dm_name_sysfs(sysfs_base: &Path, name: &str) -> Option<String> {
+    let dm_dir = sysfs_base.join(name).join("dm");
+    let uuid = std::fs::read_to_string(dm_dir.join("uuid")).ok()?;
+    let lv_uuid = uuid.trim().strip_prefix("LVM-")?;
+    if lv_uuid.contains('-') {
+        return None;
+    }
+
+    let dm_name = std::fs::read_to_string(dm_dir.join("name")).ok()?;
+    Some(dm_name.trim().to_string())
+}
+
+/// Returns the devices under a logical volume, i.e. the PVs it sits on.
+pub fn physical_volumes(device: &Path) -> Vec<PathBuf> {
+    let Some(name) = std::fs::canonicalize(device)
+        .ok()
+        .and_then(|path| path.file_name()?.to_str().map(str::to_string))
+    else {
+        return Vec::new();
+    };
+
+    crate::read_sysfs_slaves(Path::new("/sys/class/block"), &name)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|slave| PathBuf::from(format!("/dev/{}", slave)))
+        .collect()
+}
+
+/// Extends a logical volume (given by its dm name) by `growth` bytes, the
+/// space its PVs gained, with `lvextend -l +<extents>`.
+///
+/// Free space the VG already had is left alone, e.g. space left unallocated
+/// on purpose or on other PVs.
+///
+/// Returns `true` if the LV was extended, `false` if there is not a whole
+/// extent to add.
+pub fn extend_lv(dm_name: &str, growth: u64) -> Result<bool, ResizeError> {
+    let lv = format!("/dev/mapper/{}", dm_name);
+    let output = Command::new("lvm")
+        .args([
+            "lvs",
+            "--noheadings",
+            "--nosuffix",
+            "--units",
+            "b",
+            "--separator",
+            ":",
+            "-o",
+            "vg_free,vg_extent_size",
+            &lv,
+        ])
+        .output()
+        .map_err(|e| ResizeError::CommandFailed(format!("Failed to execute lvm lvs: {}", e)))?;
+    if !output.status.success() {
+        let error = String::from_utf8_lossy(&output.stderr);
+        return Err(ResizeError::DeviceNotFound(format!(
+            "LV {}: {}",
+            lv,
+            error.trim_end()
+        )));
+    }
+
+    let (vg_free, extent_size) = parse_vg_free(&String::from_utf8_lossy(&output.stdout))
+        .ok_or_else(|| ResizeError::ResizeFs(format!("Unexpected lvs output for {}", lv)))?;
+    let extents = lv_growth_extents(growth, vg_free, extent_size);
+    if extents == 0 {
+        info!("{} has no new space to grow into", lv);
+        return Ok(false);
+    }
+
+    info!(
+        "Extending LV {} by {} extents ({} bytes)",
+        lv,
+        extents,
+        extents * extent_size
+    );
+    run_lvextend(&["-l", &format!("+{}", extents), &lv])?;
+    Ok(true)
+}
+
+/// Returns how many whole extents of `growth` bytes an LV can take, capped
+/// at the free space of its VG.
+fn lv_growth_extents(growth: u64, vg_free: u64, extent_size: u64) -> u64 {
+    growth.min(vg_free) / extent_size.max(1)
+}
+
+/// Parses `lvs` output with the fields `vg_free,vg_extent_size`.
+fn parse_vg_free(output: &str) -> Option<(u64, u64)> {
+    let line = output.lines().map(str::trim).find(|l| !l.is_empty())?;
+    let (free, extent_size) = line.split_once(':')?;
+    Some((free.trim().parse().ok()?, extent_size.trim().parse().ok()?))
+}
+
+/// Runs `lvm lvextend` with the given arguments.
+fn run_lvextend(args: &[&str]) -> Result<(), ResizeError> {
+    let output = Command::new("lvm")
+        .arg("lvextend")
+        .args(args)
+        .output()
+        .map_err(|e| {
+            ResizeError::CommandFailed(format!("Failed to execute lvm lvextend: {}", e))
+        })?;
+
+    if output.status.success() {
+        return Ok(());
+    }
+
+    let error = String::from_utf8_lossy(&output.stderr);
+    Err(ResizeError::ResizeFs(format!(
+        "lvm lvextend failed: {}",
+        error.trim_end()
+    )))
+}
+
+/// Queries the pool type, sizes and VG free space with `lvm lvs`.
+fn query_pool(pool: &str) -> Result<PoolInfo, ResizeError> {
+    let output = Command::new("lvm")
+        .args([
+            "lvs",
+            "--noheadings",
+            "--nosuffix",
+            "--units",
+            "b",
+            "--separator",
+            ":",
+            "-o",
+            "segtype,lv_size,lv_metadata_size,vg_free,vg_extent_size",
+            pool,
+        ])
+        .output()
+        .map_err(|e| ResizeError::CommandFailed(format!("Failed to execute lvm lvs: {}", e)))?;
+
+    if !output.status.success() {
+        let error = String::from_utf8_lossy(&output.stderr);
+        return Err(ResizeError::DeviceNotFound(format!(
+            "LVM pool {}: {}",
+            pool,
+            error.trim_end()
+        )));
+    }
+
+    parse_lvs_pool_output(&String::from_utf8_lossy(&output.stdout), pool)
+}
+
+/// Parses `lvs` output with the fields
+/// `segtype,lv_size,lv_metadata_size,vg_free,vg_extent_size`.
+fn parse_lvs_pool_output(output: &str, pool: &str) -> Result<PoolInfo, ResizeError> {
+    let invalid = || ResizeError::ResizeFs(format!("Unexpected lvs output for {}", pool));
+
+    let line = output
+        .lines()
+        .map(str::trim)
+        .find(|l| !l.is_empty())
+        .ok_or_else(invalid)?;
+    let fields: Vec<&str> = line.split(':').map(str::trim).collect();
+    if fields.len() != 5 {
+        return Err(invalid());
+    }
+
+    let kind = match fields[0] {
+        "thin-pool" => PoolKind::Thin,
+        "vdo-pool" => PoolKind::Vdo,
+        other => {
+            return Err(ResizeError::ResizeFs(format!(
+                "{} is not a thin pool or VDO pool (segtype {})",
+                pool, other
+            )));
+        }
+    };
+
+    let parse = |s: &str| s.parse::<u64>().map_err(|_| invalid());
+    // VDO pools have no metadata LV, so lvs leaves the field empty
+    let metadata_size = if fields[2].is_empty() {
+        0
+    } else {
+        parse(fields[2])?
+    };
+
+    Ok(PoolInfo {
+        kind,
+        data_size: parse(fields[1])?,
+        metadata_size,
+        vg_free: parse(fields[3])?,
+        extent_size: parse(fields[4])?,
+    })
+}
+
+/// Splits the space the PV gained (at most the VG free space) between the
+/// pool data and metadata LVs.
+///
+/// Thin pool metadata keeps its current ratio to the data size, clamped to
+/// what dm-thin can use. lvm keeps a spare copy of the metadata LV
+/// (`lvol0_pmspare`) the same size as the largest pool metadata, so metadata
+/// growth is reserved twice. Sizes are rounded to whole extents.
+fn compute_pool_plan(info: &PoolInfo, growth: u64, pending: u64) -> PoolPlan {
+    let free = growth.min(info.vg_free + pending);
+    let extent = info.extent_size.max(1);
+    let round_down = |bytes: u64| bytes / extent * extent;
+    let round_up = |bytes: u64| bytes.div_ceil(extent) * extent;
+
+    let mut plan = PoolPlan {
+        kind: info.kind,
+        data_size: info.data_size,
+        new_data_size: info.data_size,
+        metadata_size: info.metadata_size,
+        new_metadata_size: info.metadata_size,
+    };
+
+    if info.kind == PoolKind::Vdo || info.data_size == 0 {
+        plan.new_data_size = info.data_size + round_down(free);
+        return plan;
+    }
+
+    // Solve data_growth + 2 * metadata_growth = free, with
+    // metadata_growth = data_growth * metadata / data
+    let data = info.data_size as u128;
+    let meta = info.metadata_size as u128;
+    let data_growth = (free as u128 * data / (data + 2 * meta)) as u64;
+
+    let wanted_meta = ((meta * (data + data_growth as u128)).div_ceil(data)) as u64;
+    let new_meta = round_up(wanted_meta)
+        .clamp(THIN_POOL_MIN_METADATA_BYTES, THIN_POOL_MAX_METADATA_BYTES)
+        .max(info.metadata_size);
+    let meta_growth = new_meta - info.metadata_size;
+
+    if 2 * meta_growth > free {
+        return plan;
+    }
+
+    plan.new_metadata_size = new_meta;
+    plan.new_data_size = info.data_size + round_down(free - 2 * meta_growth);
+    plan
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIB: u64 = 1024 * 1024;
+    const GIB: u64 = 1024 * MIB;
+
+    #[test]
+    fn test_parse_lvs_thin_pool() {
+        let output = "  thin-pool:107374182400:109051904:53687091200:4194304\n";
+        let info = parse_lvs_pool_output(output, "vg/pool").unwrap();
+        assert_eq!(
+            info,
+            PoolInfo {
+                kind: PoolKind::Thin,
+                data_size: 100 * GIB,
+                metadata_size: 104 * MIB,
+                vg_free: 50 * GIB,
+                extent_size: 4 * MIB,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_lvs_vdo_pool() {
+        let output = "  vdo-pool:21474836480::10737418240:4194304\n";
+        let info = parse_lvs_pool_output(output, "vg/vpool").unwrap();
+        assert_eq!(info.kind, PoolKind::Vdo);
+        assert_eq!(info.metadata_size, 0);
+        assert_eq!(info.vg_free, 10 * GIB);
+    }
+
+    #[test]
+    fn test_parse_lvs_not_a_pool() {
+        let output = "  linear:21474836480::0:4194304\n";
+        let result = parse_lvs_pool_output(output, "vg/root");
+        assert!(matches!(result, Err(ResizeError::ResizeFs(_))));
+    }
+
+    #[test]
+    fn test_parse_vg_free() {
+        assert_eq!(
+            parse_vg_free("  10737418240:4194304\n"),
+            Some((10 * GIB, 4 * MIB))
+        );
+        assert_eq!(parse_vg_free("  0:4194304\n"), Some((0, 4 * MIB)));
+        assert_eq!(parse_vg_free(""), None);
+        assert_eq!(parse_vg_free("  abc\n"), None);
+    }
+
+    #[test]
+    fn test_parse_pv_tail_free() {
+        let output = "  0:2560:root:4194304\n  2560:1024::4194304\n  3584:2560:pool_tdata:4194304\n  6144:2560::4194304\n";
+        assert_eq!(parse_pv_tail_free(output), Some(10 * GIB));
+
+        // Free space in the middle of the PV is not counted
+        let output =
+            "  0:2560:root:4194304\n  2560:1024::4194304\n  3584:2560:pool_tdata:4194304\n";
+        assert_eq!(parse_pv_tail_free(output), Some(0));
+
+        assert_eq!(parse_pv_tail_free(""), None);
+        assert_eq!(parse_pv_tail_free("  0:abc::4194304\n"), None);
+    }
+
+    #[test]
+    fn test_pool_growth_after_failed_lvextend() {
+        let info = PoolInfo {
+            kind: PoolKind::Vdo,
+            data_size: 20 * GIB,
+            metadata_size: 0,
+            vg_free: 10 * GIB,
+            extent_size: 4 * MIB,
+        };
+
+        // pvresize added 10 GiB, then lvextend failed. On the next run the
+        // PV already spans its device, but the space is still at its end
+        let rerun = "  0:5120:vpool_vdata:4194304\n  5120:2560::4194304\n";
+        let growth = parse_pv_tail_free(rerun).unwrap();
+        let plan = compute_pool_plan(&info, growth, 0);
+        assert_eq!(plan.new_data_size, 30 * GIB);
+
+        // Once the pool took it, there is nothing left to grow into
+        let extended = "  0:5120:vpool_vdata:4194304\n  5120:2560:vpool_vdata:4194304\n";
+        let growth = parse_pv_tail_free(extended).unwrap();
+        let info = PoolInfo {
+            data_size: 30 * GIB,
+            vg_free: 0,
+            ..info
+        };
+        assert!(compute_pool_plan(&info, growth, 0).is_noop());
+    }
+
+    #[test]
+    fn test_lv_growth_extents() {
+        // Only the PV growth is used, not the rest of the VG free space
+        assert_eq!(lv_growth_extents(10 * GIB, 50 * GIB, 4 * MIB), 2560);
+        assert_eq!(lv_growth_extents(10 * GIB, 8 * GIB, 4 * MIB), 2048);
+        assert_eq!(lv_growth_extents(3 * MIB, 50 * GIB, 4 * MIB), 0);
+        assert_eq!(lv_growth_extents(0, 50 * GIB, 4 * MIB), 0);
+    }
+
+    #[test]
+    fn test_read_lv_dm_name_sysfs() {
+        let tmpdir = tempfile::tempdir().expect("Failed to create temp dir");
+        let base = tmpdir.path();
+        let lv_uuid = "LVM-Xo4dm5v0TfVXbYwQx2c7g1Pq8sKzR3nLa9hJeUiOyTwBvCdFgHkMnPqRsTuVwXyZ";
+        for (name, uuid, dm_name) in [
+            ("dm-1", lv_uuid.to_string(), "vg-swap"),
+            ("dm-2", format!("{}-tpool", lv_uuid), "vg-pool-tpool"),
+            ("dm-3", "CRYPT-LUKS2-abc-swap".to_string(), "swap"),
+        ] {
+            let dm_dir = base.join(name).join("dm");
+            std::fs::create_dir_all(&dm_dir).unwrap();
+            std::fs::write(dm_dir.join("uuid"), format!("{}\n", uuid)).unwrap();
+            std::fs::write(dm_dir.join("name"), format!("{}\n", dm_name)).unwrap();
+        }
+        std::fs::create_dir_all(base.join("sda2")).unwrap();
+
+        assert_eq!(
+            read_lv_dm_name_sysfs(base, "dm-1").as_deref(),
+            Some("vg-swap")
+        );
+        assert_eq!(read_lv_dm_name_sysfs(base, "dm-2"), None);
+        assert_eq!(read_lv_dm_name_sysfs(base, "dm-3"), None);
+        assert_eq!(read_lv_dm_name_sysfs(base, "sda2"), None);
+    }
+
+    #[test]
+    fn test_parse_lvs_garbage() {
+        assert!(parse_lvs_pool_output("", "vg/pool").is_err());
+        assert!(parse_lvs_pool_output("  thin-pool:abc\n", "vg/pool").is_err());
+    }
+
+    #[test]
+    fn test_thin_pool_plan_keeps_ratio() {
+        let info = PoolInfo {
+            kind: PoolKind::Thin,
+            data_size: 100 * GIB,
+            metadata_size: 128 * MIB,
+            vg_free: 100 * GIB,
+            extent_size: 4 * MIB,
+        };
+        let plan = compute_pool_plan(&info, 100 * GIB, 0);
+
+        // Metadata roughly doubles along with the data LV
+        assert!(plan.new_metadata_size >= 2 * 128 * MIB - 4 * MIB);
+        assert!(plan.new_metadata_size <= 2 * 128 * MIB + 4 * MIB);
+        // Data and twice the metadata growth fit in the free space
+        let used = (plan.new_data_size - plan.data_size)
+            + 2 * (plan.new_metadata_size - plan.metadata_size);
+        assert!(used <= 100 * GIB);
+        assert_eq!(plan.new_data_size % (4 * MIB), 0);
+        assert_eq!(plan.new_metadata_size % (4 * MIB), 0);
+    }
+
+    #[test]
+    fn test_thin_pool_plan_caps_metadata() {
+        let info = PoolInfo {
+            kind: PoolKind::Thin,
+            data_size: 1024 * GIB,
+            metadata_size: 15 * GIB,
+            vg_free: 1024 * GIB,
+            extent_size: 4 * MIB,
+        };
+        let plan = compute_pool_plan(&info, 1024 * GIB, 0);
+        assert!(plan.new_metadata_size <= THIN_POOL_MAX_METADATA_BYTES);
+        assert!(plan.new_data_size > plan.data_size);
+    }
+
+    #[test]
+    fn test_thin_pool_plan_no_free_space() {
+        let info = PoolInfo {
+            kind: PoolKind::Thin,
+            data_size: 100 * GIB,
+            metadata_size: 128 * MIB,
+            vg_free: 0,
+            extent_size: 4 * MIB,
+        };
+        assert!(compute_pool_plan(&info, 0, 0).is_noop());
+        // The PV growth is capped at what the VG actually has free
+        assert!(compute_pool_plan(&info, 10 * GIB, 0).is_noop());
+    }
+
+    #[test]
+    fn test_pool_plan_keeps_existing_free_space() {
+        let info = PoolInfo {
+            kind: PoolKind::Vdo,
+            data_size: 20 * GIB,
+            metadata_size: 0,
+            vg_free: 50 * GIB,
+            extent_size: 4 * MIB,
+        };
+        let plan = compute_pool_plan(&info, 10 * GIB, 0);
+        assert_eq!(plan.new_data_size, 30 * GIB);
+        assert!(compute_pool_plan(&info, 0, 0).is_noop());
+    }
+
+    #[test]
+    fn test_pool_plan_extra_free() {
+        let info = PoolInfo {
+            kind: PoolKind::Vdo,
+            data_size: 20 * GIB,
+            metadata_size: 0,
+            vg_free: 0,
+            extent_size: 4 * MIB,
+        };
+        let plan = compute_pool_plan(&info, 10 * GIB + MIB, 10 * GIB + MIB);
+        assert_eq!(plan.new_data_size, 30 * GIB);
+        assert_eq!(plan.new_metadata_size, 0);
+    }
+}
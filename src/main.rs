@@ -1,6 +1,11 @@
 use clap::Parser;
-use hot_resize::{analyze_device, check_requirements, get_device_size, resize};
+use hot_resize::resize::CryptKind;
+use hot_resize::{
+    BlockDevice, analyze_device, bcache, btrfs, check_requirements, check_tools, get_device_size,
+    health, lvm, mdraid, mountinfo, multipath, resize, resolve_device_spec, superblock, swap, zfs,
+};
 use std::collections::HashMap;
+use std::collections::hash_map::Entry;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::sync::Arc;
@@ -19,6 +24,7 @@ use tracing::{debug, error, info, warn};
 struct Args {
     /// Devices to resize in JSON format
     /// Example: '[{"device":"/dev/vda1","fs_type":"ext4","mount_point":"/"}]'
+    /// or simply '[{"mount_point":"/"}]'
     #[arg(short, long)]
     devices: String,
 
@@ -41,33 +47,332 @@ struct Args {
     /// Check interval in seconds for daemon mode (default: 60)
     #[arg(long, default_value = "60", requires = "auto")]
     interval: u64,
+
+    /// What to do when the detected filesystem type differs from fs_type
+    #[arg(long, value_enum, default_value_t = FsTypeMismatch::Warn)]
+    fs_type_mismatch: FsTypeMismatch,
+
+    /// Grow past filesystem limits: the size ext4 can reach, and the
+    /// XFS allocation group ratio (only warn)
+    #[arg(long)]
+    force: bool,
+
+    /// Largest growth in XFS allocation group count (new count / current
+    /// count) allowed without --force in one run, at least 1
+    #[arg(long, default_value_t = 10.0, value_parser = parse_ag_ratio)]
+    xfs_max_ag_ratio: f64,
+}
+
+/// Parses `--xfs-max-ag-ratio`, which must be a finite number of at least 1.
+fn parse_ag_ratio(value: &str) -> Result<f64, String> {
+    let ratio: f64 = value.parse().map_err(|e| format!("{}", e))?;
+    if !ratio.is_finite() || ratio < 1.0 {
+        return Err(format!("{} is not a ratio of at least 1", value));
+    }
+    Ok(ratio)
+}
+
+/// Policy for a configured `fs_type` that does not match the filesystem
+/// found on the device.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq)]
+enum FsTypeMismatch {
+    /// Log a warning and resize the detected filesystem
+    Warn,
+    /// Resize the detected filesystem
+    Detect,
+    /// Fail without changing anything
+    Refuse,
 }
 
 #[derive(Debug, serde::Deserialize, Clone)]
 struct Device {
+    /// Block device holding the filesystem, as a path or as `UUID=`,
+    /// `LABEL=`, `PARTUUID=` or `PARTLABEL=`. Taken from the mount of
+    /// `mount_point` when left out.
+    #[serde(default)]
     device: PathBuf,
-    fs_type: FileSystem,
-    mount_point: PathBuf,
+    /// Filesystem type, detected when left out or set to `auto`
+    #[serde(default)]
+    fs_type: Option<FileSystem>,
+    #[serde(default)]
+    mount_point: Option<PathBuf>,
+    /// LVM thin pool or VDO pool (`vg/pool`) to extend after the PV grows
+    #[serde(default)]
+    lvm_pool: Option<String>,
+    /// Key source for LUKS2 volumes whose volume key is not in the keyring
+    #[serde(default)]
+    luks_key: Option<resize::LuksKey>,
+    /// Detached LUKS header of the volume
+    #[serde(default)]
+    luks_header: Option<PathBuf>,
+    /// The device holds plain dm-crypt, which has no header to detect
+    #[serde(default)]
+    plain_crypt: bool,
 }
 
-#[derive(Debug, serde::Deserialize, Clone)]
+impl Device {
+    /// Fills in `device` and `fs_type` from the mount at `mount_point`.
+    ///
+    /// A given `device` must match the mount: it may be the mount source or
+    /// a device under it (e.g. the partition holding a LUKS volume), and
+    /// links such as `/dev/disk/by-uuid/...` are followed. A given `fs_type`
+    /// that differs from the mounted one is handled according to `policy`.
+    fn resolve_from_mount(
+        &mut self,
+        mounts: &[mountinfo::MountInfo],
+        policy: FsTypeMismatch,
+    ) -> Result<(), String> {
+        let Some(mount_point) = &self.mount_point else {
+            if self.device.as_os_str().is_empty() {
+                return Err("device or mount_point is required".to_string());
+            }
+            return Ok(());
+        };
+
+        let Some(mount) = mountinfo::find_mount(mounts, mount_point) else {
+            if self.device.as_os_str().is_empty() {
+                return Err(format!(
+                    "{:?} is not mounted, device is required",
+                    mount_point
+                ));
+            }
+            return Ok(());
+        };
+
+        let mounted_fs = FileSystem::from_type_name(&mount.fs_type).ok_or_else(|| {
+            if mount.fs_type == "zfs" {
+                return format!(
+                    "{:?} is ZFS dataset {}, set device to the pool member partition instead",
+                    mount_point, mount.source
+                );
+            }
+            format!(
+                "{:?} is mounted as {}, which is not supported",
+                mount_point, mount.fs_type
+            )
+        })?;
+        let configured = self.fs_type.clone().unwrap_or(FileSystem::Auto);
+        self.fs_type = Some(
+            reconcile_fs_type(&configured, mounted_fs, policy)
+                .map_err(|e| format!("{:?}: {}", mount_point, e))?,
+        );
+
+        if self.device.as_os_str().is_empty() {
+            // The bottom of the chain is what holds the data, e.g. the
+            // partition under a LUKS mapping
+            let backing = mountinfo::mount_backing_devices(mount)
+                .map_err(|e| format!("{:?}: {}", mount_point, e))?;
+            self.device = backing.last().cloned().unwrap_or_default();
+            info!(
+                "Using {:?} ({}) for {:?}",
+                self.device, mount.fs_type, mount_point
+            );
+        } else {
+            mountinfo::check_mounted_from(mount, &self.device).map_err(|e| e.to_string())?;
+        }
+
+        Ok(())
+    }
+
+    /// Checks that the entry describes something to resize.
+    ///
+    /// An entry needs a `mount_point`, an `fs_type` (for a filesystem that is
+    /// resized while unmounted), or an LVM pool.
+    fn validate(&self) -> Result<(), String> {
+        if let Some(key) = &self.luks_key {
+            key.validate()
+                .map_err(|e| format!("{:?}: {}", self.device, e))?;
+        }
+
+        if self.plain_crypt && (self.luks_header.is_some() || self.luks_key.is_some()) {
+            return Err(format!(
+                "{:?}: plain_crypt cannot be combined with luks_header or luks_key",
+                self.device
+            ));
+        }
+
+        match (&self.fs_type, &self.mount_point) {
+            (_, Some(_)) | (Some(_), None) => Ok(()),
+            (None, None) if self.lvm_pool.is_some() => Ok(()),
+            (None, None) => Err(format!(
+                "{:?}: mount_point or fs_type is required unless lvm_pool is set",
+                self.device
+            )),
+        }
+    }
+}
+
+#[derive(Debug, serde::Deserialize, Clone, PartialEq)]
 #[serde(rename_all = "lowercase")]
 enum FileSystem {
+    /// Use the filesystem found on the device
+    Auto,
     Ext4,
     Xfs,
     Btrfs,
+    F2fs,
+    Bcachefs,
+    Nilfs2,
+    Swap,
+    Ntfs,
+    /// FAT12, FAT16 or FAT32
+    Vfat,
 }
 
 impl FileSystem {
     fn as_str(&self) -> &'static str {
         match self {
+            FileSystem::Auto => "auto",
             FileSystem::Ext4 => "ext4",
             FileSystem::Xfs => "xfs",
             FileSystem::Btrfs => "btrfs",
+            FileSystem::F2fs => "f2fs",
+            FileSystem::Bcachefs => "bcachefs",
+            FileSystem::Nilfs2 => "nilfs2",
+            FileSystem::Swap => "swap",
+            FileSystem::Ntfs => "ntfs",
+            FileSystem::Vfat => "vfat",
+        }
+    }
+
+    /// Maps a filesystem type name from mountinfo or superblock detection.
+    /// ext2 and ext3 are handled like ext4, since resize2fs covers all of them.
+    fn from_type_name(fs_type: &str) -> Option<FileSystem> {
+        match fs_type {
+            "ext2" | "ext3" | "ext4" => Some(FileSystem::Ext4),
+            "xfs" => Some(FileSystem::Xfs),
+            "btrfs" => Some(FileSystem::Btrfs),
+            "f2fs" => Some(FileSystem::F2fs),
+            "bcachefs" => Some(FileSystem::Bcachefs),
+            "nilfs2" => Some(FileSystem::Nilfs2),
+            "swap" => Some(FileSystem::Swap),
+            "ntfs" | "ntfs3" => Some(FileSystem::Ntfs),
+            "vfat" | "msdos" => Some(FileSystem::Vfat),
+            _ => None,
         }
     }
 }
 
+/// Picks the filesystem type to resize from the configured and the
+/// detected one.
+fn reconcile_fs_type(
+    configured: &FileSystem,
+    detected: FileSystem,
+    policy: FsTypeMismatch,
+) -> Result<FileSystem, String> {
+    if *configured == FileSystem::Auto || *configured == detected {
+        return Ok(detected);
+    }
+
+    match policy {
+        FsTypeMismatch::Warn => {
+            warn!(
+                "fs_type is {}, but the filesystem is {}; resizing it as {}",
+                configured.as_str(),
+                detected.as_str(),
+                detected.as_str()
+            );
+            Ok(detected)
+        }
+        FsTypeMismatch::Detect => {
+            info!(
+                "Using detected filesystem type {} instead of {}",
+                detected.as_str(),
+                configured.as_str()
+            );
+            Ok(detected)
+        }
+        FsTypeMismatch::Refuse => Err(format!(
+            "fs_type is {}, but the filesystem is {}",
+            configured.as_str(),
+            detected.as_str()
+        )),
+    }
+}
+
+/// Detects the filesystem on a device and reconciles it with the
+/// configured type. A configured type is kept when nothing is detected.
+///
+/// Returns `None` when there is no filesystem to resize on the device
+/// itself: an LVM physical volume is grown by pvresize, and the logical
+/// volumes on it are left alone. Such a device cannot hold the filesystem
+/// mounted at `mount_point`, so that is refused; a configured type only
+/// gets a warning, unless `policy` refuses mismatches.
+fn detect_fs_type(
+    device: &Path,
+    configured: &FileSystem,
+    mount_point: Option<&Path>,
+    policy: FsTypeMismatch,
+) -> Result<Option<FileSystem>, String> {
+    match resize::get_fs_type(device) {
+        Ok(name) => match FileSystem::from_type_name(&name) {
+            Some(detected) => reconcile_fs_type(configured, detected, policy).map(Some),
+            None if name == "LVM2_member" || name == "zfs_member" => {
+                let (what, grown) = if name == "LVM2_member" {
+                    ("an LVM physical volume", "only the PV")
+                } else {
+                    ("a ZFS pool member", "only the pool")
+                };
+                if let Some(mount_point) = mount_point {
+                    return Err(format!(
+                        "{:?} is {}, not the filesystem mounted at {:?}; {} would be grown",
+                        device, what, mount_point, grown
+                    ));
+                }
+                if *configured != FileSystem::Auto {
+                    let message = format!(
+                        "fs_type is {}, but {:?} is {}; {} will be grown",
+                        configured.as_str(),
+                        device,
+                        what,
+                        grown
+                    );
+                    if policy == FsTypeMismatch::Refuse {
+                        return Err(message);
+                    }
+                    warn!("{}", message);
+                    return Ok(None);
+                }
+                info!("{:?} is {}, {} will be grown", device, what, grown);
+                Ok(None)
+            }
+            None => Err(unsupported_signature(device, &name)),
+        },
+        Err(e) if *configured == FileSystem::Auto => Err(format!(
+            "Could not detect the filesystem type of {:?}: {}",
+            device, e
+        )),
+        Err(_) => {
+            warn!(
+                "Could not detect filesystem type, using specified type: {}",
+                configured.as_str()
+            );
+            Ok(Some(configured.clone()))
+        }
+    }
+}
+
+/// Explains why a detected signature cannot be resized as a filesystem.
+fn unsupported_signature(device: &Path, name: &str) -> String {
+    match name {
+        "linux_raid_member" => format!(
+            "{:?} is an md RAID member, configure the md array (e.g. /dev/md0) instead",
+            device
+        ),
+        "bcache" => format!(
+            "{:?} is a bcache backing or cache device, configure the bcache device \
+             (e.g. /dev/bcache0) instead",
+            device
+        ),
+        "crypto_LUKS" => format!("{:?} is a LUKS volume without an open mapping", device),
+        "iso9660" => format!("{:?} holds a read-only ISO9660 filesystem", device),
+        _ => format!(
+            "{:?} holds {}, which hot-resize cannot resize",
+            device, name
+        ),
+    }
+}
+
 fn is_root() -> bool {
     #[cfg(unix)]
     {
@@ -84,11 +389,25 @@ fn process_device(
     device: &Device,
     dry_run: bool,
     skip_verify: bool,
+    fs_type_mismatch: FsTypeMismatch,
+    force: bool,
+    xfs_max_ag_ratio: f64,
 ) -> Result<(), Box<dyn std::error::Error>> {
     // Analyze device
     info!("Analyzing device: {:?}", device.device);
     let block_device = analyze_device(&device.device)?;
 
+    // Mounts may have changed since startup, so make sure the mount point
+    // still holds this device before anything is grown
+    let mounts = mountinfo::read_mountinfo()?;
+    let mount = device
+        .mount_point
+        .as_ref()
+        .and_then(|mount_point| mountinfo::find_mount(&mounts, mount_point));
+    if let Some(mount) = mount {
+        mountinfo::check_mounted_from(mount, &block_device.real_device)?;
+    }
+
     info!("Device information:");
     info!("  Real device: {:?}", block_device.real_device);
     info!("  Disk: {}", block_device.disk_name);
@@ -99,12 +418,280 @@ fn process_device(
         info!("  Whole disk (no partition)");
     }
 
+    let multipath_map = multipath::detect_multipath(&block_device.disk_name)?;
+    if let Some(map) = &multipath_map {
+        info!(
+            "  Multipath map: {}, paths: {}",
+            map.name,
+            map.paths.join(", ")
+        );
+    }
+
+    let bcache_device = bcache::detect_bcache(&block_device.disk_name)?;
+    if let Some(bcache) = &bcache_device {
+        info!("  bcache backing device: {}", bcache.backing);
+    }
+
+    // Refuse to touch an md array (or the members under it) while it is busy
+    let md_array = mdraid::detect_md_array(&block_device.disk_name)?;
+    if let Some(array) = &md_array {
+        info!(
+            "  md array: {} ({}), members: {}",
+            array.name,
+            array.level,
+            array.members.join(", ")
+        );
+        mdraid::check_md_idle(array)?;
+    }
+
+    let is_luks = Command::new("cryptsetup")
+        .args(["isLuks", &block_device.real_device.to_string_lossy()])
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false);
+
+    // Find the crypt mapping through the device tree, which also covers
+    // detached LUKS headers and plain dm-crypt
+    let crypt_mapping = resize::find_crypt_mapping(&block_device.real_device)?;
+    match &crypt_mapping {
+        Some(mapping) => {
+            info!(
+                "Detected {} dm-crypt mapping {}",
+                mapping.kind.as_str(),
+                mapping.name
+            );
+            if device.plain_crypt && mapping.kind != CryptKind::Plain {
+                warn!(
+                    "{:?} is configured as plain dm-crypt, but {} is {}",
+                    device.device,
+                    mapping.name,
+                    mapping.kind.as_str()
+                );
+            }
+            if device.luks_header.is_some() && mapping.kind == CryptKind::Plain {
+                return Err(format!(
+                    "{:?} has a luks_header configured, but {} is plain dm-crypt",
+                    device.device, mapping.name
+                )
+                .into());
+            }
+        }
+        None if is_luks || device.luks_header.is_some() || device.plain_crypt => {
+            return Err(format!(
+                "{:?} is encrypted but has no active crypt mapping, open it before resizing",
+                device.device
+            )
+            .into());
+        }
+        None => {}
+    }
+
+    // Detect the filesystem before growing anything, so a mismatch or a
+    // missing tool leaves the device untouched
+    let fs_device = crypt_mapping
+        .as_ref()
+        .map(|mapping| mapping.mapper_path())
+        .unwrap_or_else(|| block_device.real_device.clone());
+    // On LUKS or plain dm-crypt, an LVM PV is the mapping
+    let pv_device = resize::lvm_pv_device(&block_device.real_device, crypt_mapping.as_ref());
+    let fs_type = if device.mount_point.is_some() || device.fs_type.is_some() {
+        detect_fs_type(
+            &fs_device,
+            device.fs_type.as_ref().unwrap_or(&FileSystem::Auto),
+            device.mount_point.as_deref(),
+            fs_type_mismatch,
+        )?
+    } else {
+        None
+    };
+
+    // A filesystem that is not mounted anywhere is resized offline
+    let fs_mount = match mount {
+        Some(mount) => Some(mount),
+        None if fs_type.is_some() => {
+            let other = mountinfo::find_device_mount(&mounts, &fs_device);
+            if let (Some(other), Some(mount_point)) = (other, &device.mount_point) {
+                warn!(
+                    "{:?} is mounted at {:?}, not at {:?}, resizing it there",
+                    fs_device, other.mount_point, mount_point
+                );
+            }
+            other
+        }
+        None => None,
+    };
+    if let Some(fs_type) = &fs_type
+        && let Err(e) = check_requirements(&[fs_type.as_str()])
+    {
+        if !dry_run {
+            return Err(e.into());
+        }
+        warn!(
+            "Missing tools detected (will continue in dry run mode): {}",
+            e
+        );
+    }
+
+    // A ZFS pool member is expanded with zpool once its partition has grown.
+    // On LUKS the pool sits on the crypt mapping, not on the partition
+    let zfs_vdev = match zfs::detect_pool_vdev(&fs_device) {
+        Ok(vdev) => vdev,
+        Err(e) if dry_run => {
+            warn!("[DRY RUN] {}", e);
+            None
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    // Refuse a mounted filesystem that cannot grow online before the
+    // partition under it is touched
+    if let (Some(fs_type), Some(mount)) = (&fs_type, fs_mount)
+        && let Err(e) = resize::check_online_resizable(fs_type.as_str(), &mount.mount_point)
+    {
+        if !dry_run {
+            return Err(e.into());
+        }
+        warn!("[DRY RUN] Would refuse: {}", e);
+    }
+
+    let fs_info = match &fs_type {
+        Some(_) => superblock::read_fs_info(&fs_device).ok(),
+        None => None,
+    };
+    if let Some(fs_info) = &fs_info {
+        info!(
+            "  Filesystem: {}, uuid {}, label {}",
+            fs_info.kind.as_str(),
+            fs_info.uuid.as_deref().unwrap_or("(none)"),
+            fs_info.label.as_deref().unwrap_or("(none)")
+        );
+        info!(
+            "  Filesystem size: {} blocks of {} bytes ({} bytes)",
+            fs_info.total_blocks,
+            fs_info.block_size,
+            fs_info.size_bytes()
+        );
+    }
+
+    // Keep the partition within what the filesystem can follow. Offline,
+    // resize2fs can add group descriptor blocks that an online resize cannot
+    let resize_limit = fs_info.as_ref().and_then(|info| match fs_mount {
+        Some(_) => info.ext_online_limit(),
+        None => info.ext_offline_limit().or_else(|| info.fat_resize_limit()),
+    });
+    if let Some(limit) = &resize_limit {
+        info!(
+            "  {} resize limit: {} bytes ({})",
+            if fs_mount.is_some() {
+                "Online"
+            } else {
+                "Offline"
+            },
+            limit.max_bytes,
+            limit.reason
+        );
+    }
+    if let Some(limit) = &resize_limit
+        && block_device.partition_number.is_none()
+        && get_device_size(&fs_device)? > limit.max_bytes
+    {
+        warn!(
+            "{:?} is larger than its filesystem can grow, the rest will stay unused",
+            device.device
+        );
+    }
+    let partition_cap = match &resize_limit {
+        Some(_) if force => None,
+        Some(limit) => Some(partition_size_cap(
+            limit,
+            &block_device.real_device,
+            &fs_device,
+        )?),
+        None => None,
+    };
+
+    // Growing a small XFS filesystem a lot leaves it with many small
+    // allocation groups, since their size is fixed at mkfs time
+    if let Some(fs_info) = &fs_info
+        && fs_info.kind == superblock::FsKind::Xfs
+    {
+        let target = projected_fs_size(&block_device, &fs_device, partition_cap)?;
+        if let Some(projection) = fs_info.xfs_ag_projection(target) {
+            info!(
+                "  XFS allocation groups: {} -> {} ({} blocks each)",
+                projection.current, projection.projected, projection.ag_blocks
+            );
+            if projection.ratio() > xfs_max_ag_ratio {
+                let message = format!(
+                    "growing {:?} would take XFS from {} to {} allocation groups, more than {} times as many",
+                    device.device, projection.current, projection.projected, xfs_max_ag_ratio
+                );
+                if dry_run {
+                    warn!("[DRY RUN] Would refuse: {}", message);
+                } else if force {
+                    warn!("{}", message);
+                } else {
+                    return Err(format!(
+                        "{} (recreate the filesystem, or use --force or --xfs-max-ag-ratio)",
+                        message
+                    )
+                    .into());
+                }
+            }
+        }
+    }
+
+    // Refuse to grow a damaged or read-only filesystem
+    if fs_type.is_some() {
+        if dry_run {
+            for issue in health::collect_issues(&fs_device, fs_mount) {
+                match issue {
+                    health::HealthIssue::Warning(message) => warn!("[DRY RUN] {}", message),
+                    health::HealthIssue::Problem(message) => {
+                        warn!("[DRY RUN] Would refuse to resize: {}", message)
+                    }
+                }
+            }
+        } else {
+            health::check_health(&fs_device, fs_mount)?;
+        }
+    }
+
     if dry_run {
+        if let Some(map) = &multipath_map {
+            info!(
+                "[DRY RUN] Would rescan paths {} and run multipathd resize map {}",
+                map.paths.join(", "),
+                map.name
+            );
+        }
+
+        if let Some(bcache) = &bcache_device {
+            info!(
+                "[DRY RUN] Would grow bcache backing device {} and resize /dev/{}",
+                bcache.backing, bcache.name
+            );
+        }
+
+        if let Some(array) = &md_array {
+            info!(
+                "[DRY RUN] Would grow md members {} and run mdadm --grow --size=max on /dev/{}",
+                array.members.join(", "),
+                array.name
+            );
+        }
+
         if let Some(partition_num) = block_device.partition_number {
             info!(
                 "[DRY RUN] Would resize partition {} on disk /dev/{}",
                 partition_num, block_device.disk_name
             );
+            if let Some(cap) = partition_cap {
+                info!(
+                    "[DRY RUN] Would stop partition {} at {} bytes, the largest size its filesystem can reach (use --force to grow further)",
+                    partition_num, cap
+                );
+            }
         } else {
             info!(
                 "[DRY RUN] Would skip partition resize for whole disk /dev/{}",
@@ -112,92 +699,327 @@ fn process_device(
             );
         }
 
-        info!(
-            "[DRY RUN] Would resize {} filesystem at {:?}",
-            device.fs_type.as_str(),
-            device.mount_point
-        );
+        let pv_unused = resize::lvm_pv_unused_bytes(&pv_device)
+            .ok()
+            .flatten()
+            .unwrap_or(0);
+        if pv_unused > 0 {
+            info!(
+                "[DRY RUN] Would run pvresize on {:?} ({} bytes unused)",
+                pv_device, pv_unused
+            );
+        }
+
+        if let Some(vdev) = &zfs_vdev {
+            let autoexpand = match zfs::autoexpand(&vdev.pool) {
+                Ok(true) => "on",
+                Ok(false) => "off",
+                Err(_) => "unknown",
+            };
+            info!(
+                "[DRY RUN] Would run zpool online -e {} {:?} (autoexpand {}, {} bytes expandable now)",
+                vdev.pool,
+                vdev.path,
+                autoexpand,
+                vdev.expand_bytes.unwrap_or(0)
+            );
+        }
+
+        if let Some(pool) = &device.lvm_pool {
+            let tail_free = lvm::pv_tail_free(&pv_device).unwrap_or(0);
+            let plan = lvm::plan_pool_extension(pool, tail_free + pv_unused, pv_unused)?;
+            if plan.is_noop() {
+                info!(
+                    "[DRY RUN] LVM {} {} has no new space to grow into",
+                    plan.kind.as_str(),
+                    pool
+                );
+            } else {
+                info!(
+                    "[DRY RUN] Would extend LVM {} {}: data {} -> {} bytes, metadata {} -> {} bytes",
+                    plan.kind.as_str(),
+                    pool,
+                    plan.data_size,
+                    plan.new_data_size,
+                    plan.metadata_size,
+                    plan.new_metadata_size
+                );
+            }
+        }
+
+        if let Some(fs_type) = &fs_type {
+            match fs_mount {
+                None if *fs_type == FileSystem::Swap => {
+                    match swap::find_active_swap(&fs_device) {
+                        Ok(Some(active)) => match swap::check_swapoff_safe(&active) {
+                            Ok(()) => info!(
+                                "[DRY RUN] Would grow {:?}, then if it gained space turn off swap on it (priority {}), run mkswap keeping its UUID, label and page size, and turn it back on",
+                                fs_device, active.priority
+                            ),
+                            Err(e) => warn!("[DRY RUN] Would refuse: {}", e),
+                        },
+                        Ok(None) => info!(
+                            "[DRY RUN] Swap on {:?} is off, would grow it and, if it gained space, run mkswap keeping its UUID, label and page size",
+                            fs_device
+                        ),
+                        Err(e) => warn!("[DRY RUN] {}", e),
+                    }
+                    if let Some(lv) = lvm::logical_volume_name(&fs_device) {
+                        let pvs: Vec<String> = lvm::physical_volumes(&fs_device)
+                            .iter()
+                            .map(|pv| pv.display().to_string())
+                            .collect();
+                        info!(
+                            "[DRY RUN] Would grow PVs {} and extend LV {} by the space they gain",
+                            pvs.join(", "),
+                            lv
+                        );
+                    }
+                }
+                Some(mount) => info!(
+                    "[DRY RUN] Would resize {} filesystem at {:?}",
+                    fs_type.as_str(),
+                    mount.mount_point
+                ),
+                None if *fs_type == FileSystem::Ext4 => info!(
+                    "[DRY RUN] {:?} is not mounted, would check it with e2fsck -f -p and run resize2fs",
+                    fs_device
+                ),
+                None if *fs_type == FileSystem::F2fs => info!(
+                    "[DRY RUN] {:?} is not mounted, would run resize.f2fs",
+                    fs_device
+                ),
+                None if *fs_type == FileSystem::Bcachefs => info!(
+                    "[DRY RUN] {:?} is not mounted, would run bcachefs device resize",
+                    fs_device
+                ),
+                None if *fs_type == FileSystem::Ntfs => info!(
+                    "[DRY RUN] {:?} is not mounted, would check it with ntfsfix -n and ntfsresize --check, then run ntfsresize",
+                    fs_device
+                ),
+                None if *fs_type == FileSystem::Vfat => info!(
+                    "[DRY RUN] {:?} is not mounted, would run fatresize -s max",
+                    fs_device
+                ),
+                None => info!(
+                    "[DRY RUN] {:?} is not mounted, would mount it on a private temporary mount point to resize {} filesystem",
+                    fs_device,
+                    fs_type.as_str()
+                ),
+            }
+            if *fs_type == FileSystem::Btrfs
+                && let Ok(devids) = btrfs::devids_to_grow(&fs_device)
+            {
+                let devids: Vec<String> = devids.iter().map(|id| id.to_string()).collect();
+                info!(
+                    "[DRY RUN] Would run btrfs filesystem resize <devid>:max for devid {}",
+                    devids.join(", ")
+                );
+            }
+        }
         return Ok(());
     }
 
-    let is_luks = Command::new("cryptsetup")
-        .args(["isLuks", &block_device.real_device.to_string_lossy()])
-        .status()
-        .map(|status| status.success())
-        .unwrap_or(false);
+    // The multipath map must follow its paths before anything on it can grow
+    if let Some(map) = &multipath_map {
+        multipath::resize_multipath(map)?;
+    }
 
-    if is_luks {
-        info!("Detected LUKS encrypted device");
+    // Grow the md members first, so the array and anything on it can follow
+    if let Some(array) = &md_array {
+        mdraid::grow_md_array(array)?;
+    }
+
+    // bcache only follows its backing device once told about the new size
+    if let Some(bcache) = &bcache_device {
+        bcache::grow_bcache(bcache)?;
     }
 
     // Grow partition
     if block_device.partition_number.is_some() {
-        let changed = resize::grow_partition(
+        resize::grow_partition(
             &format!("/dev/{}", block_device.disk_name),
             block_device.partition_number,
+            partition_cap,
         )?;
-        if changed {
-            resize::maybe_lvm_resize(&block_device.real_device)?;
-        }
     } else {
         info!("Skipping partition resize for whole disk device");
     }
 
-    if is_luks {
-        info!("Resizing LUKS container");
-        let mapper_name = find_luks_mapper(&block_device.real_device)?;
-        resize::resize_luks(&mapper_name)?;
+    // The crypt mapping follows its partition before anything on it grows,
+    // whether a filesystem or a ZFS pool
+    if let Some(mapping) = &crypt_mapping {
+        info!("Resizing {} container", mapping.kind.as_str());
+        resize::resize_luks(
+            &fs_device,
+            device.luks_key.as_ref(),
+            device.luks_header.as_deref(),
+        )?;
+    }
+
+    // Always compare the PV against its device, since the partition or disk
+    // may have grown outside hot-resize
+    resize::maybe_lvm_resize(&pv_device)?;
 
-        resize::resize_filesystem(&mapper_name, device.fs_type.as_str(), &device.mount_point)?;
+    // The pool only takes the free space at the end of the PV, not free space
+    // the VG had elsewhere. It is measured on the PV, so space a failed
+    // lvextend left behind is picked up on the next run
+    if let Some(pool) = &device.lvm_pool {
+        lvm::extend_pool(pool, lvm::pv_tail_free(&pv_device)?)?;
+    }
+
+    if let Some(vdev) = &zfs_vdev {
+        zfs::expand_pool(vdev)?;
+    }
+
+    // Swap on LVM grows with its LV, by what the PVs under it gain
+    if fs_type == Some(FileSystem::Swap)
+        && let Some(lv) = lvm::logical_volume_name(&fs_device)
+    {
+        let mut pv_growth = 0;
+        for pv in lvm::physical_volumes(&fs_device) {
+            pv_growth += grow_physical_volume(&pv)?;
+        }
+        lvm::extend_lv(&lv, pv_growth)?;
+    }
+
+    let Some(fs_type) = &fs_type else {
+        info!("No filesystem to resize on {:?}", device.device);
+        return Ok(());
+    };
+    let mount_point = fs_mount.map(|mount| mount.mount_point.as_path());
+
+    // Turning swap off moves the swapped out pages back into memory, so
+    // swap is only recreated when its device gained space
+    if *fs_type == FileSystem::Swap {
+        let swap_bytes = resize::filesystem_size(&fs_device, fs_type.as_str(), None)?.bytes;
+        if get_device_size(&fs_device)? <= swap_bytes + resize::GROW_FUDGE_BYTES {
+            info!("Swap on {:?} already fills its device", fs_device);
+            return Ok(());
+        }
+    }
+
+    let size_before = if skip_verify {
+        None
     } else {
-        resize::resize_filesystem(
-            &block_device.real_device,
-            device.fs_type.as_str(),
-            &device.mount_point,
-        )?;
+        Some(resize::filesystem_size(
+            &fs_device,
+            fs_type.as_str(),
+            mount_point,
+        )?)
+    };
+
+    // Swap in use cannot grow: it is turned off until mkswap has recreated
+    // it, or turned back on as it was if that fails
+    if *fs_type == FileSystem::Swap {
+        swap::SwapOff::new(&fs_device)?.recreate()?;
+    } else {
+        resize::resize_filesystem(&fs_device, fs_type.as_str(), mount_point)?;
     }
 
     // Verify resize if not skipped
-    if !skip_verify {
-        resize::verify_resize(&device.mount_point)?;
+    if let Some(before) = size_before {
+        resize::verify_resize(&fs_device, fs_type.as_str(), mount_point, before)?;
     }
 
     Ok(())
 }
 
-/// Finds the device-mapper name for a LUKS-encrypted device using sysfs.
-///
-/// Reads `/sys/class/block/<dev>/holders/` to find dm-* entries,
-/// then reads `/sys/class/block/<dm>/dm/name` to get the mapper name.
-fn find_luks_mapper(device_path: &Path) -> Result<PathBuf, Box<dyn std::error::Error>> {
-    let dev_name = device_path
-        .file_name()
-        .and_then(|n| n.to_str())
-        .ok_or_else(|| format!("Invalid device path: {:?}", device_path))?;
-
-    let holders_dir = PathBuf::from(format!("/sys/class/block/{}/holders", dev_name));
-    if !holders_dir.exists() {
-        return Err(format!("No sysfs holders directory for {}", dev_name).into());
-    }
-
-    for entry in std::fs::read_dir(&holders_dir)? {
-        let entry = entry?;
-        let holder_name = entry.file_name().to_string_lossy().to_string();
-
-        if holder_name.starts_with("dm-") {
-            let dm_name_path = PathBuf::from(format!("/sys/class/block/{}/dm/name", holder_name));
-            if let Ok(name) = std::fs::read_to_string(&dm_name_path) {
-                return Ok(PathBuf::from(format!("/dev/mapper/{}", name.trim())));
+/// Grows the partition under an LVM physical volume, if it is one, and
+/// resizes the PV. Returns how many bytes the PV gained.
+fn grow_physical_volume(pv: &Path) -> Result<u64, Box<dyn std::error::Error>> {
+    let pv_device = analyze_device(pv)?;
+    if pv_device.partition_number.is_some() {
+        resize::grow_partition(
+            &format!("/dev/{}", pv_device.disk_name),
+            pv_device.partition_number,
+            None,
+        )?;
+    }
+    Ok(resize::maybe_lvm_resize(&pv_device.real_device)?)
+}
+
+/// Returns the largest partition size that keeps the filesystem within an
+/// online resize limit. Whatever sits between the partition and the
+/// filesystem (e.g. a LUKS header) is added on top.
+fn partition_size_cap(
+    limit: &superblock::OnlineLimit,
+    partition: &Path,
+    fs_device: &Path,
+) -> Result<u64, Box<dyn std::error::Error>> {
+    let overhead = get_device_size(partition)?.saturating_sub(get_device_size(fs_device)?);
+    Ok(limit.max_bytes.saturating_add(overhead))
+}
+
+/// Returns the size the filesystem device will have once its partition is
+/// grown, or its current size when there is no partition to grow.
+fn projected_fs_size(
+    block_device: &BlockDevice,
+    fs_device: &Path,
+    partition_cap: Option<u64>,
+) -> Result<u64, Box<dyn std::error::Error>> {
+    let fs_size = get_device_size(fs_device)?;
+    let Some(partition_num) = block_device.partition_number else {
+        return Ok(fs_size);
+    };
+
+    let partition_size = get_device_size(&block_device.real_device)?;
+    let target = resize::partition_target_size(
+        &format!("/dev/{}", block_device.disk_name),
+        partition_num,
+        partition_cap,
+    )?;
+    Ok(fs_size + target.saturating_sub(partition_size))
+}
+
+/// Returns the disks whose growth should trigger a resize of a device:
+/// the disk holding its partition, the paths of a multipath map, the disks
+/// under md array members, the disk under a bcache backing device, and the
+/// disks under the PVs of an LVM logical volume.
+fn parent_disks(block_device: &BlockDevice) -> Vec<PathBuf> {
+    let mut disks = Vec::new();
+
+    if block_device.partition_number.is_some() {
+        disks.push(PathBuf::from(format!("/dev/{}", block_device.disk_name)));
+    }
+
+    if let Ok(Some(map)) = multipath::detect_multipath(&block_device.disk_name) {
+        for path in &map.paths {
+            disks.push(PathBuf::from(format!("/dev/{}", path)));
+        }
+    }
+
+    if let Ok(Some(array)) = mdraid::detect_md_array(&block_device.disk_name) {
+        for member in &array.members {
+            if let Ok(member_device) = analyze_device(Path::new(&format!("/dev/{}", member))) {
+                disks.push(PathBuf::from(format!("/dev/{}", member_device.disk_name)));
+            }
+        }
+    }
+
+    if let Ok(Some(bcache)) = bcache::detect_bcache(&block_device.disk_name)
+        && let Ok(backing) = analyze_device(Path::new(&format!("/dev/{}", bcache.backing)))
+    {
+        disks.push(PathBuf::from(format!("/dev/{}", backing.disk_name)));
+    }
+
+    if lvm::logical_volume_name(&block_device.real_device).is_some() {
+        for pv in lvm::physical_volumes(&block_device.real_device) {
+            if let Ok(pv_device) = analyze_device(&pv) {
+                disks.push(PathBuf::from(format!("/dev/{}", pv_device.disk_name)));
             }
         }
     }
 
-    Err(format!("Could not find LUKS mapper device for {:?}", device_path).into())
+    disks
 }
 
 fn daemon_loop(
     devices: Vec<Device>,
     skip_verify: bool,
+    fs_type_mismatch: FsTypeMismatch,
+    force: bool,
+    xfs_max_ag_ratio: f64,
     interval: Duration,
     running: Arc<AtomicBool>,
 ) {
@@ -216,16 +1038,19 @@ fn daemon_loop(
                 Err(_) => continue,
             };
 
-            if block_device.partition_number.is_some() {
-                let parent_disk = PathBuf::from(format!("/dev/{}", block_device.disk_name));
+            for parent_disk in parent_disks(&block_device) {
                 if let Ok(parent_size) = get_device_size(&parent_disk) {
-                    let parent_key = PathBuf::from(format!("{}_parent", device.device.display()));
+                    let parent_key = PathBuf::from(format!(
+                        "{}_parent_{}",
+                        device.device.display(),
+                        parent_disk.display()
+                    ));
                     if let Some(&last_parent_size) = known_sizes.get(&parent_key) {
                         if parent_size != last_parent_size {
                             size_changed = true;
                             warn!(
-                                "Parent disk size changed for {:?}: {} -> {}",
-                                device.device, last_parent_size, parent_size
+                                "Parent disk {:?} size changed for {:?}: {} -> {}",
+                                parent_disk, device.device, last_parent_size, parent_size
                             );
                         }
                     } else {
@@ -251,7 +1076,14 @@ fn daemon_loop(
             }
 
             if should_process || (size_changed && !first_run) {
-                match process_device(device, false, skip_verify) {
+                match process_device(
+                    device,
+                    false,
+                    skip_verify,
+                    fs_type_mismatch,
+                    force,
+                    xfs_max_ag_ratio,
+                ) {
                     Ok(_) => {
                         if size_changed && !first_run {
                             warn!("Successfully resized {:?} after size change", device.device);
@@ -302,7 +1134,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // Parse devices from JSON
     info!("Parsing device configuration...");
-    let devices: Vec<Device> = match serde_json::from_str(&args.devices) {
+    let mut devices: Vec<Device> = match serde_json::from_str(&args.devices) {
         Ok(devices) => devices,
         Err(e) => {
             error!("Failed to parse devices JSON: {}", e);
@@ -315,12 +1147,60 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         return Ok(());
     }
 
-    // Collect filesystem types to check required tools
-    let fs_types: Vec<&str> = devices.iter().map(|dev| dev.fs_type.as_str()).collect();
+    let mounts = mountinfo::read_mountinfo()?;
+    for device in &mut devices {
+        if !device.device.as_os_str().is_empty() {
+            match resolve_device_spec(&device.device) {
+                Ok(path) => device.device = path,
+                Err(e) => {
+                    error!("Failed to resolve {:?}: {}", device.device, e);
+                    return Err(e.into());
+                }
+            }
+        }
+
+        if let Err(e) = device
+            .resolve_from_mount(&mounts, args.fs_type_mismatch)
+            .and_then(|_| device.validate())
+        {
+            error!("Invalid device configuration: {}", e);
+            return Err(e.into());
+        }
+    }
+
+    // A passphrase fd is read once: a pipe is drained by the first read, and
+    // the daemon or another entry with the same fd needs the passphrase again
+    let mut passphrases: HashMap<i32, resize::Passphrase> = HashMap::new();
+    for key in devices.iter_mut().filter_map(|dev| dev.luks_key.as_mut()) {
+        let Some(fd) = key.passphrase_fd else {
+            continue;
+        };
+        if let Entry::Vacant(entry) = passphrases.entry(fd) {
+            match resize::read_passphrase_fd(fd) {
+                Ok(passphrase) => {
+                    entry.insert(passphrase);
+                }
+                Err(e) => {
+                    error!("{}", e);
+                    return Err(e.into());
+                }
+            }
+        }
+        key.passphrase = passphrases.get(&fd).cloned();
+    }
+    drop(passphrases);
 
-    // Check required tools for these filesystem types
+    // Filesystem tools are checked per device, once the filesystem type
+    // has been detected
     info!("Checking for required tools...");
-    match check_requirements(&fs_types) {
+    let tool_check = check_requirements(&[]).and_then(|_| {
+        if devices.iter().any(|dev| dev.lvm_pool.is_some()) {
+            check_tools(&["lvm"])
+        } else {
+            Ok(())
+        }
+    });
+    match tool_check {
         Ok(_) => info!("All required tools are available"),
         Err(e) => {
             if args.dry_run {
@@ -349,6 +1229,9 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         daemon_loop(
             devices,
             args.skip_verify,
+            args.fs_type_mismatch,
+            args.force,
+            args.xfs_max_ag_ratio,
             Duration::from_secs(args.interval),
             running,
         );
@@ -365,7 +1248,14 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             total_devices,
             device.device
         );
-        match process_device(device, args.dry_run, args.skip_verify) {
+        match process_device(
+            device,
+            args.dry_run,
+            args.skip_verify,
+            args.fs_type_mismatch,
+            args.force,
+            args.xfs_max_ag_ratio,
+        ) {
             Ok(_) => {
                 info!("Successfully processed device {:?}", device.device);
                 success_count += 1;
@@ -398,14 +1288,34 @@ mod daemon_tests {
         assert_eq!(FileSystem::Ext4.as_str(), "ext4");
         assert_eq!(FileSystem::Xfs.as_str(), "xfs");
         assert_eq!(FileSystem::Btrfs.as_str(), "btrfs");
+        assert_eq!(FileSystem::F2fs.as_str(), "f2fs");
+        assert_eq!(FileSystem::Nilfs2.as_str(), "nilfs2");
+        assert_eq!(FileSystem::Swap.as_str(), "swap");
+        assert_eq!(FileSystem::Vfat.as_str(), "vfat");
+        assert_eq!(FileSystem::Auto.as_str(), "auto");
+        assert_eq!(FileSystem::from_type_name("ext3"), Some(FileSystem::Ext4));
+        assert_eq!(FileSystem::from_type_name("xfs"), Some(FileSystem::Xfs));
+        assert_eq!(FileSystem::from_type_name("f2fs"), Some(FileSystem::F2fs));
+        assert_eq!(
+            FileSystem::from_type_name("bcachefs"),
+            Some(FileSystem::Bcachefs)
+        );
+        assert_eq!(FileSystem::from_type_name("ntfs3"), Some(FileSystem::Ntfs));
+        assert_eq!(FileSystem::from_type_name("msdos"), Some(FileSystem::Vfat));
+        assert_eq!(FileSystem::from_type_name("tmpfs"), None);
+        assert_eq!(FileSystem::from_type_name("crypto_LUKS"), None);
     }
 
     #[test]
     fn test_device_clone() {
         let device = Device {
             device: PathBuf::from("/dev/sda1"),
-            fs_type: FileSystem::Ext4,
-            mount_point: PathBuf::from("/"),
+            fs_type: Some(FileSystem::Ext4),
+            mount_point: Some(PathBuf::from("/")),
+            lvm_pool: None,
+            luks_key: None,
+            luks_header: None,
+            plain_crypt: false,
         };
 
         let cloned = device.clone();
@@ -417,10 +1327,206 @@ mod daemon_tests {
         assert_eq!(device.mount_point, cloned.mount_point);
     }
 
+    #[test]
+    fn test_device_validate() {
+        let devices: Vec<Device> = serde_json::from_str(
+            r#"[
+                {"device":"/dev/vda1","fs_type":"ext4","mount_point":"/"},
+                {"device":"/dev/vdb","lvm_pool":"vg/pool"},
+                {"device":"/dev/vdc"},
+                {"device":"/dev/vdd","fs_type":"xfs"},
+                {"device":"/dev/vde1","mount_point":"/srv"},
+                {"device":"/dev/vdf1","fs_type":"auto","mount_point":"/srv"}
+            ]"#,
+        )
+        .unwrap();
+
+        assert!(devices[0].validate().is_ok());
+        assert!(devices[1].validate().is_ok());
+        assert_eq!(devices[1].lvm_pool.as_deref(), Some("vg/pool"));
+        assert!(devices[2].validate().is_err());
+        // Without mount_point, the filesystem is resized while unmounted
+        assert!(devices[3].validate().is_ok());
+        assert!(devices[4].validate().is_ok());
+        assert!(devices[5].validate().is_ok());
+        assert_eq!(devices[5].fs_type, Some(FileSystem::Auto));
+    }
+
+    #[test]
+    fn test_device_resolve_from_mount() {
+        // /dev/null stands in for a block device that exists on every system
+        let mounts = mountinfo::parse_mountinfo(
+            "22 1 1:3 / /data rw,relatime - ext3 /dev/null rw\n\
+             23 1 0:5 / /tmp rw - tmpfs tmpfs rw\n",
+        );
+        let mut devices: Vec<Device> = serde_json::from_str(
+            r#"[
+                {"mount_point":"/data"},
+                {"device":"/dev/null","fs_type":"ext4","mount_point":"/data"},
+                {"mount_point":"/data","fs_type":"xfs"},
+                {"device":"/dev/zero","mount_point":"/data"},
+                {"mount_point":"/tmp"},
+                {"mount_point":"/srv"},
+                {"device":"/dev/vdb1","fs_type":"xfs","mount_point":"/srv"},
+                {"fs_type":"xfs"},
+                {"mount_point":"/data","fs_type":"auto"}
+            ]"#,
+        )
+        .unwrap();
+        let refuse = FsTypeMismatch::Refuse;
+
+        assert!(devices[0].resolve_from_mount(&mounts, refuse).is_ok());
+        assert_eq!(devices[0].device, PathBuf::from("/dev/null"));
+        assert_eq!(devices[0].fs_type, Some(FileSystem::Ext4));
+        assert!(devices[0].validate().is_ok());
+
+        assert!(devices[1].resolve_from_mount(&mounts, refuse).is_ok());
+        assert!(devices[2].resolve_from_mount(&mounts, refuse).is_err());
+        assert!(devices[3].resolve_from_mount(&mounts, refuse).is_err());
+        assert!(devices[4].resolve_from_mount(&mounts, refuse).is_err());
+        // Not mounted: only usable when device is given
+        assert!(devices[5].resolve_from_mount(&mounts, refuse).is_err());
+        assert!(devices[6].resolve_from_mount(&mounts, refuse).is_ok());
+        assert!(devices[7].resolve_from_mount(&mounts, refuse).is_err());
+        assert!(devices[8].resolve_from_mount(&mounts, refuse).is_ok());
+        assert_eq!(devices[8].fs_type, Some(FileSystem::Ext4));
+
+        // The mounted type wins over a wrong fs_type unless refused
+        assert!(
+            devices[2]
+                .resolve_from_mount(&mounts, FsTypeMismatch::Warn)
+                .is_ok()
+        );
+        assert_eq!(devices[2].fs_type, Some(FileSystem::Ext4));
+    }
+
+    #[test]
+    fn test_detect_fs_type_routing() {
+        let dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let write_device = |name: &str, offset: u64, data: &[u8]| {
+            use std::os::unix::fs::FileExt;
+
+            let path = dir.path().join(name);
+            let file = std::fs::File::create(&path).unwrap();
+            file.set_len(256 * 1024).unwrap();
+            file.write_all_at(data, offset).unwrap();
+            path
+        };
+
+        let mut lvm = [0u8; 32];
+        lvm[0..8].copy_from_slice(b"LABELONE");
+        lvm[24..32].copy_from_slice(b"LVM2 001");
+        let pv = write_device("pv", 512, &lvm);
+        let iso = write_device("iso", 32769, b"CD001");
+        let md = write_device("md", 4096, &0xa92b_4efcu32.to_le_bytes());
+
+        // A PV skips the filesystem step with fs_type auto
+        assert_eq!(
+            detect_fs_type(&pv, &FileSystem::Auto, None, FsTypeMismatch::Refuse),
+            Ok(None)
+        );
+        // A configured type only warns, unless mismatches are refused
+        assert_eq!(
+            detect_fs_type(&pv, &FileSystem::Ext4, None, FsTypeMismatch::Warn),
+            Ok(None)
+        );
+        assert!(detect_fs_type(&pv, &FileSystem::Ext4, None, FsTypeMismatch::Refuse).is_err());
+        // The mounted filesystem cannot be on the PV itself
+        let root = Some(Path::new("/"));
+        assert!(detect_fs_type(&pv, &FileSystem::Ext4, root, FsTypeMismatch::Detect).is_err());
+        // Other signatures are refused, even with a configured type
+        for device in [&iso, &md] {
+            assert!(detect_fs_type(device, &FileSystem::Ext4, None, FsTypeMismatch::Warn).is_err());
+        }
+        assert!(unsupported_signature(&md, "linux_raid_member").contains("md array"));
+    }
+
+    #[test]
+    fn test_reconcile_fs_type() {
+        for policy in [
+            FsTypeMismatch::Warn,
+            FsTypeMismatch::Detect,
+            FsTypeMismatch::Refuse,
+        ] {
+            assert_eq!(
+                reconcile_fs_type(&FileSystem::Auto, FileSystem::Xfs, policy),
+                Ok(FileSystem::Xfs)
+            );
+            assert_eq!(
+                reconcile_fs_type(&FileSystem::Btrfs, FileSystem::Btrfs, policy),
+                Ok(FileSystem::Btrfs)
+            );
+        }
+
+        assert_eq!(
+            reconcile_fs_type(&FileSystem::Ext4, FileSystem::Xfs, FsTypeMismatch::Warn),
+            Ok(FileSystem::Xfs)
+        );
+        assert_eq!(
+            reconcile_fs_type(&FileSystem::Ext4, FileSystem::Xfs, FsTypeMismatch::Detect),
+            Ok(FileSystem::Xfs)
+        );
+        assert!(
+            reconcile_fs_type(&FileSystem::Ext4, FileSystem::Xfs, FsTypeMismatch::Refuse).is_err()
+        );
+    }
+
+    #[test]
+    fn test_detect_fs_type_undetectable() {
+        // /dev/null has no superblock: a configured type is kept, auto fails
+        let null = Path::new("/dev/null");
+        assert_eq!(
+            detect_fs_type(null, &FileSystem::Xfs, None, FsTypeMismatch::Refuse),
+            Ok(Some(FileSystem::Xfs))
+        );
+        assert!(detect_fs_type(null, &FileSystem::Auto, None, FsTypeMismatch::Warn).is_err());
+    }
+
+    #[test]
+    fn test_device_luks_key() {
+        let devices: Vec<Device> = serde_json::from_str(
+            r#"[
+                {"device":"/dev/vda2","fs_type":"ext4","mount_point":"/",
+                 "luks_key":{"key_file":"/etc/keys/root.key","key_slot":1}},
+                {"device":"/dev/vdb1","fs_type":"xfs","mount_point":"/data",
+                 "luks_key":{"token_type":"systemd-tpm2"}},
+                {"device":"/dev/vdc1","fs_type":"xfs","mount_point":"/srv",
+                 "luks_key":{"key_file":"/k","passphrase_fd":3}}
+            ]"#,
+        )
+        .unwrap();
+
+        assert!(devices[0].validate().is_ok());
+        assert_eq!(devices[0].luks_key.as_ref().unwrap().key_slot, Some(1));
+        assert!(devices[1].validate().is_ok());
+        assert!(devices[2].validate().is_err());
+
+        let plain: Vec<Device> = serde_json::from_str(
+            r#"[
+                {"device":"/dev/sdb1","fs_type":"ext4","mount_point":"/scratch","plain_crypt":true},
+                {"device":"/dev/sdc1","fs_type":"ext4","mount_point":"/data",
+                 "luks_header":"/boot/data.header"},
+                {"device":"/dev/sdd1","fs_type":"ext4","mount_point":"/x","plain_crypt":true,
+                 "luks_header":"/boot/x.header"}
+            ]"#,
+        )
+        .unwrap();
+        assert!(plain[0].validate().is_ok());
+        assert!(plain[1].validate().is_ok());
+        assert_eq!(
+            plain[1].luks_header.as_deref(),
+            Some(Path::new("/boot/data.header"))
+        );
+        assert!(plain[2].validate().is_err());
+
+        let unknown: Result<Vec<Device>, _> =
+            serde_json::from_str(r#"[{"device":"/dev/vda2","luks_key":{"passphrase":"secret"}}]"#);
+        assert!(unknown.is_err());
+    }
+
     #[test]
     fn test_is_root() {
-        let root_status = is_root();
-        assert!(root_status || !root_status);
+        assert_eq!(is_root(), nix::unistd::Uid::effective().is_root());
     }
 
     #[test]
@@ -443,5 +1549,51 @@ mod daemon_tests {
         ];
         let result = Args::try_parse_from(&args);
         assert!(result.is_ok());
+        assert_eq!(result.unwrap().fs_type_mismatch, FsTypeMismatch::Warn);
+
+        let args = vec![
+            "hot-resize",
+            "--devices",
+            r#"[]"#,
+            "--fs-type-mismatch",
+            "refuse",
+        ];
+        let result = Args::try_parse_from(&args).unwrap();
+        assert_eq!(result.fs_type_mismatch, FsTypeMismatch::Refuse);
+        assert!(!result.force);
+
+        assert_eq!(result.xfs_max_ag_ratio, 10.0);
+
+        let args = vec!["hot-resize", "--devices", r#"[]"#, "--force"];
+        assert!(Args::try_parse_from(&args).unwrap().force);
+
+        let args = vec![
+            "hot-resize",
+            "--devices",
+            r#"[]"#,
+            "--xfs-max-ag-ratio",
+            "32",
+        ];
+        assert_eq!(Args::try_parse_from(&args).unwrap().xfs_max_ag_ratio, 32.0);
+
+        for ratio in ["NaN", "inf", "0.5", "-2"] {
+            let args = vec![
+                "hot-resize",
+                "--devices",
+                r#"[]"#,
+                "--xfs-max-ag-ratio",
+                ratio,
+            ];
+            assert!(Args::try_parse_from(&args).is_err(), "{}", ratio);
+        }
+
+        let args = vec![
+            "hot-resize",
+            "--devices",
+            r#"[]"#,
+            "--fs-type-mismatch",
+            "x",
+        ];
+        assert!(Args::try_parse_from(&args).is_err());
     }
 }
@@ -0,0 +1,350 @@
+use crate::resize::{self, ResizeError};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tracing::{info, warn};
+
+/// RAID levels whose component size can be changed with `mdadm --grow --size`.
+const GROWABLE_LEVELS: &[&str] = &["raid1", "raid4", "raid5", "raid6", "raid10"];
+
+/// A Linux software RAID array, as described by `/sys/class/block/mdX/md/`.
+#[derive(Debug, PartialEq)]
+pub struct MdArray {
+    /// Kernel name of the array (e.g. `md0`).
+    pub name: String,
+    /// RAID level (e.g. `raid1`).
+    pub level: String,
+    /// Kernel names of the member devices (e.g. `sda1`, `sdb1`).
+    pub members: Vec<String>,
+}
+
+impl MdArray {
+    pub fn device_path(&self) -> PathBuf {
+        PathBuf::from(format!("/dev/{}", self.name))
+    }
+}
+
+/// Returns the md array for a disk name, or `None` if it is not an md array.
+pub fn detect_md_array(disk_name: &str) -> Result<Option<MdArray>, ResizeError> {
+    read_md_array(Path::new("/sys/class/block"), disk_name)
+}
+
+/// Reads md array information from a sysfs-like directory structure.
+///
+/// An md array has a `<sysfs_base>/<name>/md/` directory with a `level`
+/// file and one `dev-<member>` directory per member device.
+fn read_md_array(sysfs_base: &Path, name: &str) -> Result<Option<MdArray>, ResizeError> {
+    let md_dir = sysfs_base.join(name).join("md");
+    if !md_dir.is_dir() {
+        return Ok(None);
+    }
+
+    let level = std::fs::read_to_string(md_dir.join("level"))
+        .map_err(|e| ResizeError::DeviceNotFound(format!("{}: missing md level: {}", name, e)))?
+        .trim()
+        .to_string();
+
+    let entries = std::fs::read_dir(&md_dir).map_err(|e| {
+        ResizeError::DeviceNotFound(format!("Failed to read {}: {}", md_dir.display(), e))
+    })?;
+
+    let mut members: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            entry
+                .file_name()
+                .to_str()
+                .and_then(|n| n.strip_prefix("dev-"))
+                .map(String::from)
+        })
+        .collect();
+    members.sort();
+
+    Ok(Some(MdArray {
+        name: name.to_string(),
+        level,
+        members,
+    }))
+}
+
+/// Refuses to continue while the array is resyncing, recovering or reshaping.
+pub fn check_md_idle(array: &MdArray) -> Result<(), ResizeError> {
+    check_md_idle_sysfs(Path::new("/sys/class/block"), array)
+}
+
+fn check_md_idle_sysfs(sysfs_base: &Path, array: &MdArray) -> Result<(), ResizeError> {
+    let md_dir = sysfs_base.join(&array.name).join("md");
+    let read = |file: &str| {
+        std::fs::read_to_string(md_dir.join(file))
+            .map(|s| s.trim().to_string())
+            .ok()
+    };
+
+    if let Some(action) = read("sync_action")
+        && action != "idle"
+    {
+        return Err(ResizeError::ResizeMd(format!(
+            "{} is busy ({}), refusing to grow it until it is idle",
+            array.name, action
+        )));
+    }
+
+    if let Some(position) = read("reshape_position")
+        && position != "none"
+    {
+        return Err(ResizeError::ResizeMd(format!(
+            "{} is reshaping (position {}), refusing to grow it",
+            array.name, position
+        )));
+    }
+
+    Ok(())
+}
+
+/// Returns how much of a member device the array uses: its data offset plus
+/// the component size, from `md/dev-<member>/offset` (sectors) and
+/// `md/dev-<member>/size` (KiB). `None` if sysfs does not report them.
+fn member_used_bytes(sysfs_base: &Path, array: &MdArray, member: &str) -> Option<u64> {
+    let dev_dir = sysfs_base
+        .join(&array.name)
+        .join("md")
+        .join(format!("dev-{}", member));
+    let read = |file: &str| {
+        std::fs::read_to_string(dev_dir.join(file))
+            .ok()
+            .and_then(|s| s.trim().parse::<u64>().ok())
+    };
+    Some(read("offset")? * 512 + read("size")? * 1024)
+}
+
+/// Returns the size a member device will have once its partition is grown,
+/// or its current size when it is a whole disk.
+fn member_reachable_bytes(member: &str) -> Result<u64, ResizeError> {
+    let member_path = PathBuf::from(format!("/dev/{}", member));
+    let member_device = crate::analyze_device(&member_path)
+        .map_err(|e| ResizeError::DeviceNotFound(format!("md member {}: {}", member, e)))?;
+
+    match member_device.partition_number {
+        Some(partition_num) => resize::partition_target_size(
+            &format!("/dev/{}", member_device.disk_name),
+            partition_num,
+            None,
+        ),
+        None => crate::get_device_size(&member_path)
+            .map_err(|e| ResizeError::DeviceNotFound(format!("md member {}: {}", member, e))),
+    }
+}
+
+/// Returns whether a member can give the array more room, i.e. it will be
+/// larger than what the array uses of it by more than the growth fudge.
+/// A member whose usage is unknown is assumed to be able to grow.
+fn member_can_grow(reachable: u64, used: Option<u64>) -> bool {
+    used.is_none_or(|used| reachable > used.saturating_add(resize::GROW_FUDGE_BYTES))
+}
+
+/// Grows every member partition of an md array, then the array itself.
+///
+/// The array can only grow as far as its smallest member, so nothing is
+/// touched unless every member can grow. Members are then grown one after
+/// the other; if any of them fails, the array is left alone. The array is
+/// then grown with `mdadm --grow --size=max`, which is a no-op when the
+/// members did not get bigger.
+///
+/// Returns `true` if the array size changed.
+pub fn grow_md_array(array: &MdArray) -> Result<bool, ResizeError> {
+    if !GROWABLE_LEVELS.contains(&array.level.as_str()) {
+        return Err(ResizeError::ResizeMd(format!(
+            "{} is {}, which cannot be grown with mdadm --size=max",
+            array.name, array.level
+        )));
+    }
+
+    check_md_idle(array)?;
+
+    if crate::find_in_path("mdadm").is_none() {
+        return Err(ResizeError::CommandFailed(
+            "mdadm not found, cannot grow md array".to_string(),
+        ));
+    }
+
+    let sysfs_base = Path::new("/sys/class/block");
+    for member in &array.members {
+        let reachable = member_reachable_bytes(member)?;
+        if !member_can_grow(reachable, member_used_bytes(sysfs_base, array, member)) {
+            info!(
+                "md member {} of {} has no room to grow, leaving the array as it is",
+                member, array.name
+            );
+            return Ok(false);
+        }
+    }
+
+    for member in &array.members {
+        let member_path = PathBuf::from(format!("/dev/{}", member));
+        let member_device = crate::analyze_device(&member_path)
+            .map_err(|e| ResizeError::DeviceNotFound(format!("md member {}: {}", member, e)))?;
+
+        if member_device.partition_number.is_some() {
+            info!("Growing md member {} of {}", member, array.name);
+            resize::grow_partition(
+                &format!("/dev/{}", member_device.disk_name),
+                member_device.partition_number,
+                None,
+            )?;
+        } else {
+            info!("md member {} is a whole disk, nothing to grow", member);
+        }
+    }
+
+    let md_path = array.device_path();
+    let size_before =
+        crate::get_device_size(&md_path).map_err(|e| ResizeError::DeviceNotFound(e.to_string()))?;
+
+    info!(
+        "Growing md array {} with mdadm --grow --size=max",
+        array.name
+    );
+    let output = Command::new("mdadm")
+        .args(["--grow", &md_path.to_string_lossy(), "--size=max"])
+        .output()
+        .map_err(|e| ResizeError::CommandFailed(format!("Failed to execute mdadm: {}", e)))?;
+
+    if !output.status.success() {
+        let error = String::from_utf8_lossy(&output.stderr);
+        return Err(ResizeError::ResizeMd(error.trim_end().to_string()));
+    }
+
+    let size_after =
+        crate::get_device_size(&md_path).map_err(|e| ResizeError::DeviceNotFound(e.to_string()))?;
+
+    if size_after > size_before {
+        info!(
+            "md array {} grew from {} to {} bytes",
+            array.name, size_before, size_after
+        );
+        Ok(true)
+    } else {
+        if size_after < size_before {
+            warn!(
+                "md array {} shrank from {} to {} bytes",
+                array.name, size_before, size_after
+            );
+        }
+        info!("md array {} is already at maximum size", array.name);
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates a fake md array in sysfs with the given level and members.
+    fn create_fake_md(base: &Path, name: &str, level: &str, members: &[&str]) -> PathBuf {
+        let md_dir = base.join(name).join("md");
+        std::fs::create_dir_all(&md_dir).expect("Failed to create md dir");
+        std::fs::write(md_dir.join("level"), format!("{}\n", level))
+            .expect("Failed to write level");
+        for member in members {
+            std::fs::create_dir_all(md_dir.join(format!("dev-{}", member)))
+                .expect("Failed to create member dir");
+        }
+        md_dir
+    }
+
+    #[test]
+    fn test_read_md_array_raid1() {
+        let tmpdir = tempfile::tempdir().expect("Failed to create temp dir");
+        create_fake_md(tmpdir.path(), "md0", "raid1", &["sdb1", "sda1"]);
+
+        let array = read_md_array(tmpdir.path(), "md0").unwrap().unwrap();
+        assert_eq!(
+            array,
+            MdArray {
+                name: "md0".to_string(),
+                level: "raid1".to_string(),
+                members: vec!["sda1".to_string(), "sdb1".to_string()],
+            }
+        );
+        assert_eq!(array.device_path(), PathBuf::from("/dev/md0"));
+    }
+
+    #[test]
+    fn test_read_md_array_not_md() {
+        let tmpdir = tempfile::tempdir().expect("Failed to create temp dir");
+        std::fs::create_dir_all(tmpdir.path().join("sda")).unwrap();
+
+        assert!(read_md_array(tmpdir.path(), "sda").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_check_md_idle() {
+        let tmpdir = tempfile::tempdir().expect("Failed to create temp dir");
+        let md_dir = create_fake_md(tmpdir.path(), "md0", "raid1", &["sda1", "sdb1"]);
+        std::fs::write(md_dir.join("sync_action"), "idle\n").unwrap();
+        std::fs::write(md_dir.join("reshape_position"), "none\n").unwrap();
+
+        let array = read_md_array(tmpdir.path(), "md0").unwrap().unwrap();
+        assert!(check_md_idle_sysfs(tmpdir.path(), &array).is_ok());
+    }
+
+    #[test]
+    fn test_check_md_resync_refused() {
+        let tmpdir = tempfile::tempdir().expect("Failed to create temp dir");
+        let md_dir = create_fake_md(tmpdir.path(), "md0", "raid1", &["sda1", "sdb1"]);
+        std::fs::write(md_dir.join("sync_action"), "resync\n").unwrap();
+
+        let array = read_md_array(tmpdir.path(), "md0").unwrap().unwrap();
+        let result = check_md_idle_sysfs(tmpdir.path(), &array);
+        assert!(matches!(result, Err(ResizeError::ResizeMd(_))));
+    }
+
+    #[test]
+    fn test_check_md_reshape_refused() {
+        let tmpdir = tempfile::tempdir().expect("Failed to create temp dir");
+        let md_dir = create_fake_md(tmpdir.path(), "md0", "raid5", &["sda1", "sdb1", "sdc1"]);
+        std::fs::write(md_dir.join("sync_action"), "idle\n").unwrap();
+        std::fs::write(md_dir.join("reshape_position"), "123456\n").unwrap();
+
+        let array = read_md_array(tmpdir.path(), "md0").unwrap().unwrap();
+        let result = check_md_idle_sysfs(tmpdir.path(), &array);
+        assert!(matches!(result, Err(ResizeError::ResizeMd(_))));
+    }
+
+    #[test]
+    fn test_member_used_bytes() {
+        let tmpdir = tempfile::tempdir().expect("Failed to create temp dir");
+        let md_dir = create_fake_md(tmpdir.path(), "md0", "raid1", &["sda1", "sdb1"]);
+        std::fs::write(md_dir.join("dev-sda1/offset"), "2048\n").unwrap();
+        std::fs::write(md_dir.join("dev-sda1/size"), "1047552\n").unwrap();
+
+        let array = read_md_array(tmpdir.path(), "md0").unwrap().unwrap();
+        assert_eq!(
+            member_used_bytes(tmpdir.path(), &array, "sda1"),
+            Some(2048 * 512 + 1047552 * 1024)
+        );
+        assert_eq!(member_used_bytes(tmpdir.path(), &array, "sdb1"), None);
+    }
+
+    #[test]
+    fn test_member_can_grow() {
+        let used = 1 << 30;
+        assert!(member_can_grow(2 << 30, Some(used)));
+        assert!(!member_can_grow(used, Some(used)));
+        assert!(!member_can_grow(
+            used + resize::GROW_FUDGE_BYTES,
+            Some(used)
+        ));
+        assert!(member_can_grow(used, None));
+    }
+
+    #[test]
+    fn test_grow_md_array_raid0_refused() {
+        let array = MdArray {
+            name: "md0".to_string(),
+            level: "raid0".to_string(),
+            members: vec!["sda1".to_string(), "sdb1".to_string()],
+        };
+        let result = grow_md_array(&array);
+        assert!(matches!(result, Err(ResizeError::ResizeMd(_))));
+    }
+}
@@ -0,0 +1,398 @@
+use crate::DeviceError;
+use std::path::{Path, PathBuf};
+
+/// One line of `/proc/self/mountinfo`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MountInfo {
+    /// Device number of the filesystem (`major:minor`).
+    pub major: u32,
+    pub minor: u32,
+    /// Mount point, with octal escapes (e.g. `\040`) decoded.
+    pub mount_point: PathBuf,
+    /// Per-mount options (e.g. `rw,relatime`).
+    pub mount_options: String,
+    /// Filesystem type (e.g. `ext4`).
+    pub fs_type: String,
+    /// Mount source (e.g. `/dev/mapper/vg-root`).
+    pub source: String,
+    /// Per-superblock options.
+    pub super_options: String,
+}
+
+impl MountInfo {
+    /// Returns true if the mount is read-write.
+    pub fn is_read_write(&self) -> bool {
+        self.mount_options.split(',').any(|opt| opt == "rw")
+    }
+}
+
+/// Reads and parses `/proc/self/mountinfo`.
+pub fn read_mountinfo() -> Result<Vec<MountInfo>, DeviceError> {
+    let content = std::fs::read_to_string("/proc/self/mountinfo")?;
+    Ok(parse_mountinfo(&content))
+}
+
+/// Parses the content of a mountinfo file. Malformed lines are skipped.
+///
+/// Format (see proc(5)):
+/// `36 35 98:0 /mnt1 /mnt2 rw,noatime master:1 - ext3 /dev/root rw,errors=continue`
+pub fn parse_mountinfo(content: &str) -> Vec<MountInfo> {
+    content.lines().filter_map(parse_mountinfo_line).collect()
+}
+
+fn parse_mountinfo_line(line: &str) -> Option<MountInfo> {
+    let (left, right) = line.split_once(" - ")?;
+
+    let fields: Vec<&str> = left.split(' ').collect();
+    if fields.len() < 6 {
+        return None;
+    }
+    let (major, minor) = fields[2].split_once(':')?;
+
+    let mut right_fields = right.split(' ');
+    let fs_type = right_fields.next()?;
+    let source = right_fields.next()?;
+    let super_options = right_fields.next().unwrap_or("");
+
+    Some(MountInfo {
+        major: major.parse().ok()?,
+        minor: minor.parse().ok()?,
+        mount_point: PathBuf::from(unescape_mountinfo(fields[4])),
+        mount_options: fields[5].to_string(),
+        fs_type: fs_type.to_string(),
+        source: unescape_mountinfo(source),
+        super_options: super_options.to_string(),
+    })
+}
+
+/// Decodes the octal escapes (`\040`, `\011`, `\012`, `\134`) used in mountinfo.
+pub(crate) fn unescape_mountinfo(field: &str) -> String {
+    let bytes = field.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'\\'
+            && let Some(digits) = bytes.get(i + 1..i + 4)
+            && digits.iter().all(|b| (b'0'..=b'7').contains(b))
+        {
+            let value = digits
+                .iter()
+                .fold(0u32, |acc, b| acc * 8 + u32::from(b - b'0'));
+            out.push(value as u8);
+            i += 4;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+
+    String::from_utf8_lossy(&out).to_string()
+}
+
+/// Returns the mount at a mount point. When several filesystems are mounted
+/// on the same path, the last one (the visible one) wins.
+pub fn find_mount<'a>(mounts: &'a [MountInfo], mount_point: &Path) -> Option<&'a MountInfo> {
+    mounts.iter().rev().find(|m| m.mount_point == mount_point)
+}
+
+/// Returns a mount of the filesystem on a block device, if it is mounted
+/// anywhere.
+///
+/// Mounts are matched on the device number, and on the mount source for
+/// filesystems such as Btrfs that report an anonymous device number.
+pub fn find_device_mount<'a>(mounts: &'a [MountInfo], device: &Path) -> Option<&'a MountInfo> {
+    let real = std::fs::canonicalize(device).unwrap_or_else(|_| device.to_path_buf());
+    let rdev = nix::sys::stat::stat(&real).ok().map(|st| {
+        (
+            nix::sys::stat::major(st.st_rdev) as u32,
+            nix::sys::stat::minor(st.st_rdev) as u32,
+        )
+    });
+
+    mounts.iter().find(|m| {
+        rdev == Some((m.major, m.minor))
+            || (m.source.starts_with("/dev/")
+                && std::fs::canonicalize(&m.source).is_ok_and(|source| source == real))
+    })
+}
+
+/// Returns the block device a mount comes from.
+///
+/// The source is used when it is a path under `/dev` (following
+/// `/dev/mapper` and `/dev/disk/by-*` links); otherwise, e.g. for
+/// `/dev/root`, the device is found from its number in `/sys/dev/block`.
+pub fn mount_source_device(mount: &MountInfo) -> Result<PathBuf, DeviceError> {
+    if mount.source.starts_with("/dev/")
+        && let Ok(real) = std::fs::canonicalize(&mount.source)
+    {
+        return Ok(real);
+    }
+
+    let sys_link = PathBuf::from(format!("/sys/dev/block/{}:{}", mount.major, mount.minor));
+    let target = std::fs::read_link(&sys_link).map_err(|_| {
+        DeviceError::DeviceInfo(format!(
+            "Could not find the block device of {} ({}:{})",
+            mount.source, mount.major, mount.minor
+        ))
+    })?;
+
+    target
+        .file_name()
+        .map(|name| PathBuf::from("/dev").join(name))
+        .ok_or_else(|| DeviceError::DeviceInfo(format!("Invalid sysfs link {:?}", target)))
+}
+
+/// Returns the chain of devices a mount is built on, top first.
+///
+/// Starting from the mount source, dm-crypt mappings are followed down to
+/// the device holding the encrypted data, so a mount of
+/// `/dev/mapper/luks-root` yields `[/dev/dm-0, /dev/vda2]`.
+pub fn mount_backing_devices(mount: &MountInfo) -> Result<Vec<PathBuf>, DeviceError> {
+    let source = mount_source_device(mount)?;
+    let name = source
+        .file_name()
+        .and_then(|n| n.to_str())
+        .ok_or_else(|| DeviceError::DeviceInfo(format!("Invalid device path {:?}", source)))?;
+
+    Ok(backing_chain_sysfs(Path::new("/sys/class/block"), name)
+        .into_iter()
+        .map(|name| PathBuf::from("/dev").join(name))
+        .collect())
+}
+
+/// Walks down from a device through dm-crypt mappings in a sysfs-like
+/// directory structure, returning the kernel names visited, top first.
+fn backing_chain_sysfs(sysfs_base: &Path, name: &str) -> Vec<String> {
+    let mut chain = vec![name.to_string()];
+
+    loop {
+        let current = chain.last().expect("chain is never empty");
+        let uuid = std::fs::read_to_string(sysfs_base.join(current).join("dm").join("uuid"))
+            .unwrap_or_default();
+        if !uuid.starts_with("CRYPT-") {
+            break;
+        }
+
+        match crate::read_sysfs_slaves(sysfs_base, current).as_deref() {
+            Ok([slave]) if !chain.contains(slave) => chain.push(slave.clone()),
+            _ => break,
+        }
+    }
+
+    chain
+}
+
+/// Returns every device a mount is built on, top first.
+///
+/// Like [`mount_backing_devices`], but LVM logical volumes are followed as
+/// well, down to all of their physical volumes, so a mount of an LV on a
+/// LUKS volume yields the LV, the crypt mapping and the partition under it.
+pub fn mount_device_stack(mount: &MountInfo) -> Result<Vec<PathBuf>, DeviceError> {
+    let source = mount_source_device(mount)?;
+    let name = source
+        .file_name()
+        .and_then(|n| n.to_str())
+        .ok_or_else(|| DeviceError::DeviceInfo(format!("Invalid device path {:?}", source)))?;
+
+    Ok(device_stack_sysfs(Path::new("/sys/class/block"), name)
+        .into_iter()
+        .map(|name| PathBuf::from("/dev").join(name))
+        .collect())
+}
+
+/// Checks that a mount is backed by a device: either the device itself is
+/// mounted, or a LUKS mapping or LVM logical volume built on it.
+pub fn check_mounted_from(mount: &MountInfo, device: &Path) -> Result<(), DeviceError> {
+    let device = std::fs::canonicalize(device).unwrap_or_else(|_| device.to_path_buf());
+    if mount_device_stack(mount)?.contains(&device) {
+        return Ok(());
+    }
+
+    Err(DeviceError::DeviceInfo(format!(
+        "{} is mounted from {}, which is not built on {}",
+        mount.mount_point.display(),
+        mount.source,
+        device.display()
+    )))
+}
+
+/// Walks down from a device through dm-crypt mappings and LVM logical
+/// volumes in a sysfs-like directory structure, returning the kernel names
+/// visited, top first.
+fn device_stack_sysfs(sysfs_base: &Path, name: &str) -> Vec<String> {
+    let mut stack = vec![name.to_string()];
+    let mut next = 0;
+
+    while let Some(current) = stack.get(next).cloned() {
+        next += 1;
+        let uuid = std::fs::read_to_string(sysfs_base.join(&current).join("dm").join("uuid"))
+            .unwrap_or_default();
+        if !uuid.starts_with("CRYPT-") && !uuid.starts_with("LVM-") {
+            continue;
+        }
+
+        for slave in crate::read_sysfs_slaves(sysfs_base, &current).unwrap_or_default() {
+            if !stack.contains(&slave) {
+                stack.push(slave);
+            }
+        }
+    }
+
+    stack
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_MOUNTINFO: &str = "\
+22 1 252:1 / / rw,relatime shared:1 - ext4 /dev/vda1 rw,errors=remount-ro
+25 22 0:23 / /proc rw,nosuid,nodev,noexec,relatime shared:12 - proc proc rw
+30 22 253:0 / /data rw,noatime shared:5 - xfs /dev/mapper/luks-data rw,attr2,inode64
+31 22 0:45 /@home /home rw,relatime shared:6 - btrfs /dev/vdb2 rw,space_cache=v2,subvol=/@home
+32 22 252:17 / /mnt/with\\040space ro,relatime shared:7 - ext4 /dev/vdc1 ro
+33 30 252:33 / /data rw,relatime shared:8 - ext4 /dev/vdd1 rw
+";
+
+    #[test]
+    fn test_parse_mountinfo() {
+        let mounts = parse_mountinfo(SAMPLE_MOUNTINFO);
+        assert_eq!(mounts.len(), 6);
+
+        let root = &mounts[0];
+        assert_eq!(root.major, 252);
+        assert_eq!(root.minor, 1);
+        assert_eq!(root.mount_point, PathBuf::from("/"));
+        assert_eq!(root.fs_type, "ext4");
+        assert_eq!(root.source, "/dev/vda1");
+        assert!(root.is_read_write());
+
+        let home = &mounts[3];
+        assert_eq!(home.fs_type, "btrfs");
+        assert_eq!(home.source, "/dev/vdb2");
+        assert_eq!(home.super_options, "rw,space_cache=v2,subvol=/@home");
+    }
+
+    #[test]
+    fn test_parse_mountinfo_escaped_mount_point() {
+        let mounts = parse_mountinfo(SAMPLE_MOUNTINFO);
+        let mount = find_mount(&mounts, Path::new("/mnt/with space")).unwrap();
+        assert_eq!(mount.source, "/dev/vdc1");
+        assert!(!mount.is_read_write());
+    }
+
+    #[test]
+    fn test_parse_mountinfo_skips_malformed() {
+        let mounts = parse_mountinfo("garbage\n22 1 x / / rw - ext4 /dev/vda1 rw\n");
+        assert!(mounts.is_empty());
+    }
+
+    #[test]
+    fn test_unescape_mountinfo() {
+        assert_eq!(unescape_mountinfo("/a\\040b"), "/a b");
+        assert_eq!(unescape_mountinfo("/tab\\011x"), "/tab\tx");
+        assert_eq!(unescape_mountinfo("/back\\134slash"), "/back\\slash");
+        assert_eq!(unescape_mountinfo("/plain"), "/plain");
+        assert_eq!(unescape_mountinfo("/trailing\\"), "/trailing\\");
+    }
+
+    #[test]
+    fn test_find_mount_over_mounted() {
+        let mounts = parse_mountinfo(SAMPLE_MOUNTINFO);
+        // /data is mounted twice; the last mount is the visible one
+        let mount = find_mount(&mounts, Path::new("/data")).unwrap();
+        assert_eq!(mount.source, "/dev/vdd1");
+        assert!(find_mount(&mounts, Path::new("/nonexistent")).is_none());
+    }
+
+    #[test]
+    fn test_find_device_mount() {
+        // /dev/null is 1:3 on Linux; /dev/zero (1:5) is mounted by source
+        let mounts = parse_mountinfo(
+            "22 1 1:3 / /data rw,relatime - ext4 /dev/null rw\n\
+             23 1 0:45 / /home rw,relatime - btrfs /dev/zero rw\n",
+        );
+
+        let mount = find_device_mount(&mounts, Path::new("/dev/null")).unwrap();
+        assert_eq!(mount.mount_point, PathBuf::from("/data"));
+        let mount = find_device_mount(&mounts, Path::new("/dev/zero")).unwrap();
+        assert_eq!(mount.mount_point, PathBuf::from("/home"));
+        assert!(find_device_mount(&mounts, Path::new("/dev/full")).is_none());
+    }
+
+    #[test]
+    fn test_mount_source_device_root() {
+        let mounts = read_mountinfo().unwrap();
+        // The root filesystem is always mounted, but not always on a block device
+        if let Some(root) = find_mount(&mounts, Path::new("/"))
+            && root.source.starts_with("/dev/")
+            && let Ok(device) = mount_source_device(root)
+        {
+            assert!(device.starts_with("/dev"));
+        }
+    }
+
+    #[test]
+    fn test_backing_chain_through_crypt() {
+        let tmpdir = tempfile::tempdir().expect("Failed to create temp dir");
+        let base = tmpdir.path();
+        let dm_dir = base.join("dm-0").join("dm");
+        std::fs::create_dir_all(&dm_dir).unwrap();
+        std::fs::write(dm_dir.join("uuid"), "CRYPT-LUKS2-abcd-luks-root\n").unwrap();
+        std::fs::create_dir_all(base.join("dm-0").join("slaves").join("vda2")).unwrap();
+        std::fs::create_dir_all(base.join("vda2")).unwrap();
+
+        assert_eq!(backing_chain_sysfs(base, "dm-0"), vec!["dm-0", "vda2"]);
+        assert_eq!(backing_chain_sysfs(base, "vda2"), vec!["vda2"]);
+    }
+
+    #[test]
+    fn test_backing_chain_stops_at_lvm() {
+        let tmpdir = tempfile::tempdir().expect("Failed to create temp dir");
+        let base = tmpdir.path();
+        let dm_dir = base.join("dm-1").join("dm");
+        std::fs::create_dir_all(&dm_dir).unwrap();
+        std::fs::write(dm_dir.join("uuid"), "LVM-abcdef\n").unwrap();
+        std::fs::create_dir_all(base.join("dm-1").join("slaves").join("vda3")).unwrap();
+
+        assert_eq!(backing_chain_sysfs(base, "dm-1"), vec!["dm-1"]);
+    }
+
+    #[test]
+    fn test_device_stack_through_lvm_and_crypt() {
+        let tmpdir = tempfile::tempdir().expect("Failed to create temp dir");
+        let base = tmpdir.path();
+        let dm_device = |name: &str, uuid: &str, slaves: &[&str]| {
+            std::fs::create_dir_all(base.join(name).join("dm")).unwrap();
+            std::fs::write(base.join(name).join("dm").join("uuid"), uuid).unwrap();
+            for slave in slaves {
+                std::fs::create_dir_all(base.join(name).join("slaves").join(slave)).unwrap();
+            }
+        };
+
+        // An LV spanning a LUKS volume on vda2 and a plain PV on vdb1
+        dm_device("dm-2", "LVM-abcdef\n", &["dm-0", "vdb1"]);
+        dm_device("dm-0", "CRYPT-LUKS2-abcd-luks-pv\n", &["vda2"]);
+        // Multipath maps are not followed
+        dm_device("dm-5", "mpath-3600a0b80\n", &["sdc", "sdd"]);
+
+        assert_eq!(
+            device_stack_sysfs(base, "dm-2"),
+            vec!["dm-2", "dm-0", "vdb1", "vda2"]
+        );
+        assert_eq!(device_stack_sysfs(base, "dm-5"), vec!["dm-5"]);
+        assert_eq!(device_stack_sysfs(base, "vda1"), vec!["vda1"]);
+    }
+
+    #[test]
+    fn test_check_mounted_from() {
+        // /dev/null stands in for a block device that exists on every system
+        let mounts = parse_mountinfo("22 1 1:3 / /data rw,relatime - ext4 /dev/null rw\n");
+        let mount = find_mount(&mounts, Path::new("/data")).unwrap();
+
+        assert!(check_mounted_from(mount, Path::new("/dev/null")).is_ok());
+        let err = check_mounted_from(mount, Path::new("/dev/zero")).unwrap_err();
+        assert!(err.to_string().contains("not built on /dev/zero"));
+    }
+}
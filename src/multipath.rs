@@ -0,0 +1,258 @@
+use crate::resize::ResizeError;
+use std::path::Path;
+use std::process::Command;
+use tracing::info;
+
+/// A dm-multipath map, as described by `/sys/class/block/dm-N/`.
+#[derive(Debug, PartialEq)]
+pub struct MultipathMap {
+    /// Kernel name of the map (e.g. `dm-3`).
+    pub dm_name: String,
+    /// Device-mapper name of the map (e.g. `mpatha`).
+    pub name: String,
+    /// Kernel names of the paths (e.g. `sdb`, `sdc`).
+    pub paths: Vec<String>,
+}
+
+/// Returns the multipath map for a disk name, or `None` if it is not one.
+pub fn detect_multipath(disk_name: &str) -> Result<Option<MultipathMap>, ResizeError> {
+    read_multipath_map(Path::new("/sys/class/block"), disk_name)
+}
+
+/// Reads multipath map information from a sysfs-like directory structure.
+///
+/// A multipath map is a dm device whose `dm/uuid` starts with `mpath-`.
+/// Its paths are listed in `slaves/`.
+fn read_multipath_map(
+    sysfs_base: &Path,
+    dm_name: &str,
+) -> Result<Option<MultipathMap>, ResizeError> {
+    let dev_dir = sysfs_base.join(dm_name);
+    let uuid = match std::fs::read_to_string(dev_dir.join("dm").join("uuid")) {
+        Ok(uuid) => uuid,
+        Err(_) => return Ok(None),
+    };
+
+    if !uuid.trim().starts_with("mpath-") {
+        return Ok(None);
+    }
+
+    let name = std::fs::read_to_string(dev_dir.join("dm").join("name"))
+        .map_err(|e| ResizeError::DeviceNotFound(format!("{}: missing dm name: {}", dm_name, e)))?
+        .trim()
+        .to_string();
+
+    let paths = crate::read_sysfs_slaves(sysfs_base, dm_name)
+        .map_err(|e| ResizeError::DeviceNotFound(e.to_string()))?;
+
+    Ok(Some(MultipathMap {
+        dm_name: dm_name.to_string(),
+        name,
+        paths,
+    }))
+}
+
+/// Rescans every path of a multipath map, then resizes the map.
+///
+/// All paths must report the same size after the rescan, otherwise the map
+/// is left alone: resizing it while some paths still see the old LUN size
+/// would make I/O fail on those paths.
+///
+/// Returns `true` if `multipathd resize map` was run.
+pub fn resize_multipath(map: &MultipathMap) -> Result<bool, ResizeError> {
+    let sysfs_base = Path::new("/sys/class/block");
+
+    if map.paths.is_empty() {
+        return Err(ResizeError::ResizeMultipath(format!(
+            "{} has no active paths",
+            map.name
+        )));
+    }
+
+    for path in &map.paths {
+        info!("Rescanning multipath path {} of {}", path, map.name);
+        rescan_path(sysfs_base, path)?;
+    }
+
+    let path_size = common_path_size(sysfs_base, map)?;
+    let map_size = crate::read_sysfs_device_size(sysfs_base, &map.dm_name)
+        .map_err(|e| ResizeError::DeviceNotFound(e.to_string()))?;
+
+    if path_size <= map_size {
+        info!(
+            "Multipath map {} already matches its paths ({} bytes)",
+            map.name, map_size
+        );
+        return Ok(false);
+    }
+
+    if crate::find_in_path("multipathd").is_none() {
+        return Err(ResizeError::CommandFailed(
+            "multipathd not found, cannot resize multipath map".to_string(),
+        ));
+    }
+
+    info!(
+        "Resizing multipath map {}: {} -> {} bytes",
+        map.name, map_size, path_size
+    );
+    let output = Command::new("multipathd")
+        .args(["resize", "map", &map.name])
+        .output()
+        .map_err(|e| ResizeError::CommandFailed(format!("Failed to execute multipathd: {}", e)))?;
+
+    // multipathd reports failures on stdout ("fail") with a zero exit code
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    if !output.status.success() || stdout.trim() == "fail" {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(ResizeError::ResizeMultipath(format!(
+            "multipathd resize map {} failed: {}{}",
+            map.name,
+            stdout.trim_end(),
+            stderr.trim_end()
+        )));
+    }
+
+    info!("Successfully resized multipath map {}", map.name);
+    Ok(true)
+}
+
+/// Asks the SCSI layer to re-read the capacity of a path device.
+fn rescan_path(sysfs_base: &Path, path: &str) -> Result<(), ResizeError> {
+    let rescan = sysfs_base.join(path).join("device").join("rescan");
+    std::fs::write(&rescan, "1")
+        .map_err(|e| ResizeError::ResizeMultipath(format!("Failed to rescan path {}: {}", path, e)))
+}
+
+/// Returns the size shared by every path of the map, or an error if the
+/// paths disagree.
+fn common_path_size(sysfs_base: &Path, map: &MultipathMap) -> Result<u64, ResizeError> {
+    let mut sizes = Vec::with_capacity(map.paths.len());
+    for path in &map.paths {
+        let size = crate::read_sysfs_device_size(sysfs_base, path)
+            .map_err(|e| ResizeError::DeviceNotFound(e.to_string()))?;
+        sizes.push((path.as_str(), size));
+    }
+
+    let first = sizes[0].1;
+    if sizes.iter().any(|&(_, size)| size != first) {
+        let listing: Vec<String> = sizes
+            .iter()
+            .map(|(path, size)| format!("{}={}", path, size))
+            .collect();
+        return Err(ResizeError::ResizeMultipath(format!(
+            "paths of {} report different sizes ({}), not resizing",
+            map.name,
+            listing.join(", ")
+        )));
+    }
+
+    Ok(first)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates a fake dm device with the given uuid, name and slaves.
+    fn create_fake_dm(base: &Path, dm_name: &str, uuid: &str, name: &str, slaves: &[&str]) {
+        let dm_dir = base.join(dm_name).join("dm");
+        std::fs::create_dir_all(&dm_dir).expect("Failed to create dm dir");
+        std::fs::write(dm_dir.join("uuid"), format!("{}\n", uuid)).unwrap();
+        std::fs::write(dm_dir.join("name"), format!("{}\n", name)).unwrap();
+        let slaves_dir = base.join(dm_name).join("slaves");
+        std::fs::create_dir_all(&slaves_dir).unwrap();
+        for slave in slaves {
+            std::fs::create_dir_all(slaves_dir.join(slave)).unwrap();
+        }
+    }
+
+    /// Creates a fake SCSI path device with a size and a rescan file.
+    fn create_fake_path(base: &Path, name: &str, sectors: u64) {
+        let dev_dir = base.join(name);
+        std::fs::create_dir_all(dev_dir.join("device")).unwrap();
+        std::fs::write(dev_dir.join("size"), format!("{}\n", sectors)).unwrap();
+        std::fs::write(dev_dir.join("device").join("rescan"), "").unwrap();
+    }
+
+    #[test]
+    fn test_read_multipath_map() {
+        let tmpdir = tempfile::tempdir().expect("Failed to create temp dir");
+        create_fake_dm(
+            tmpdir.path(),
+            "dm-3",
+            "mpath-3600508b400105e210000900000490000",
+            "mpatha",
+            &["sdc", "sdb"],
+        );
+
+        let map = read_multipath_map(tmpdir.path(), "dm-3").unwrap().unwrap();
+        assert_eq!(
+            map,
+            MultipathMap {
+                dm_name: "dm-3".to_string(),
+                name: "mpatha".to_string(),
+                paths: vec!["sdb".to_string(), "sdc".to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn test_read_multipath_map_other_dm() {
+        let tmpdir = tempfile::tempdir().expect("Failed to create temp dir");
+        create_fake_dm(tmpdir.path(), "dm-0", "LVM-abcdef", "vg-root", &["sda2"]);
+
+        assert!(read_multipath_map(tmpdir.path(), "dm-0").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_read_multipath_map_not_dm() {
+        let tmpdir = tempfile::tempdir().expect("Failed to create temp dir");
+        std::fs::create_dir_all(tmpdir.path().join("sda")).unwrap();
+
+        assert!(read_multipath_map(tmpdir.path(), "sda").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_common_path_size_equal() {
+        let tmpdir = tempfile::tempdir().expect("Failed to create temp dir");
+        create_fake_path(tmpdir.path(), "sdb", 4096);
+        create_fake_path(tmpdir.path(), "sdc", 4096);
+        let map = MultipathMap {
+            dm_name: "dm-3".to_string(),
+            name: "mpatha".to_string(),
+            paths: vec!["sdb".to_string(), "sdc".to_string()],
+        };
+
+        assert_eq!(common_path_size(tmpdir.path(), &map).unwrap(), 4096 * 512);
+    }
+
+    #[test]
+    fn test_common_path_size_mismatch() {
+        let tmpdir = tempfile::tempdir().expect("Failed to create temp dir");
+        create_fake_path(tmpdir.path(), "sdb", 8192);
+        create_fake_path(tmpdir.path(), "sdc", 4096);
+        let map = MultipathMap {
+            dm_name: "dm-3".to_string(),
+            name: "mpatha".to_string(),
+            paths: vec!["sdb".to_string(), "sdc".to_string()],
+        };
+
+        let result = common_path_size(tmpdir.path(), &map);
+        assert!(matches!(result, Err(ResizeError::ResizeMultipath(_))));
+    }
+
+    #[test]
+    fn test_rescan_path() {
+        let tmpdir = tempfile::tempdir().expect("Failed to create temp dir");
+        create_fake_path(tmpdir.path(), "sdb", 4096);
+
+        rescan_path(tmpdir.path(), "sdb").unwrap();
+        let written =
+            std::fs::read_to_string(tmpdir.path().join("sdb").join("device").join("rescan"))
+                .unwrap();
+        assert_eq!(written, "1");
+
+        assert!(rescan_path(tmpdir.path(), "sdz").is_err());
+    }
+}
@@ -15,6 +15,18 @@ pub enum ResizeError {
     DeviceNotFound(String),
     #[error("Failed to resize LUKS container: {0}")]
     ResizeLuks(String),
+    #[error("Failed to grow md array: {0}")]
+    ResizeMd(String),
+    #[error("Failed to resize multipath map: {0}")]
+    ResizeMultipath(String),
+    #[error("Failed to resize bcache device: {0}")]
+    ResizeBcache(String),
+    #[error("Failed to expand ZFS pool: {0}")]
+    ResizeZfs(String),
+    #[error("Resize verification failed: {0}")]
+    Verify(String),
+    #[error("Filesystem is not healthy: {0}")]
+    Unhealthy(String),
 }
 
 /// Detects the filesystem type of a device by reading superblock magic bytes.
@@ -24,6 +36,11 @@ pub enum ResizeError {
 /// - XFS: magic `XFSB` at offset 0
 /// - Btrfs: magic `_BHRfS_M` at offset 0x10040
 /// - LUKS: magic `LUKS\xBA\xBE` at offset 0
+///
+/// Signatures of things that are not resized as a filesystem are reported
+/// too, with the names blkid uses: `linux_raid_member`, `LVM2_member`,
+/// `bcache`, `bcachefs`, `swap`, `zfs_member`, `f2fs`, `nilfs2`, `vfat`,
+/// `exfat`, `ntfs` and `iso9660`.
 pub fn get_fs_type(device: &Path) -> Result<String, ResizeError> {
     detect_fs_magic(device)
 }
@@ -35,6 +52,12 @@ fn detect_fs_magic(path: &Path) -> Result<String, ResizeError> {
     let mut file = std::fs::File::open(path)
         .map_err(|e| ResizeError::DeviceNotFound(format!("{}: {}", path.display(), e)))?;
 
+    // md superblocks 0.90 and 1.0 sit at the end of the member, so the
+    // filesystem inside the array is visible at its start: check them first
+    if has_md_superblock(&file) {
+        return Ok("linux_raid_member".to_string());
+    }
+
     // XFS: offset 0, magic "XFSB" (4 bytes, big-endian)
     let mut buf4 = [0u8; 4];
     if file.read_exact(&mut buf4).is_ok() && &buf4 == b"XFSB" {
@@ -68,12 +91,156 @@ fn detect_fs_magic(path: &Path) -> Result<String, ResizeError> {
         return Ok("btrfs".to_string());
     }
 
+    if let Some(signature) = detect_other_signature(&file) {
+        return Ok(signature.to_string());
+    }
+
     Err(ResizeError::ResizeFs(format!(
         "Failed to detect filesystem type for {}",
         path.display()
     )))
 }
 
+/// md superblock magic (`0xa92b4efc`, little-endian on disk for 1.x).
+const MD_SB_MAGIC: u32 = 0xa92b_4efc;
+
+/// bcache and bcachefs superblocks start at 4 KiB, with a magic UUID at
+/// offset 24 into the superblock.
+const BCACHE_MAGIC: [u8; 16] = [
+    0xc6, 0x85, 0x73, 0xf6, 0x4e, 0x1a, 0x45, 0xca, 0x82, 0x65, 0xf5, 0x7f, 0x48, 0xba, 0x6d, 0x81,
+];
+pub(crate) const BCACHEFS_MAGIC: [u8; 16] = [
+    0xc6, 0x85, 0x73, 0xf6, 0x66, 0xce, 0x90, 0xa9, 0xd9, 0x6a, 0x60, 0xcf, 0x80, 0x3d, 0xf7, 0xef,
+];
+
+/// ZFS uberblock magic, found in the uberblock array of the vdev labels.
+const ZFS_UBERBLOCK_MAGIC: u64 = 0x00ba_b10c;
+
+/// F2FS superblock magic at offset 1024.
+pub(crate) const F2FS_MAGIC: u32 = 0xF2F5_2010;
+
+/// NILFS2 superblock magic, 6 bytes into the superblock at offset 1024.
+pub(crate) const NILFS_MAGIC: u16 = 0x3434;
+
+/// Reads `len` bytes at `offset`, or `None` past the end of the device.
+fn read_bytes_at(file: &std::fs::File, offset: u64, len: usize) -> Option<Vec<u8>> {
+    use std::os::unix::fs::FileExt;
+
+    let mut buf = vec![0u8; len];
+    file.read_exact_at(&mut buf, offset).ok().map(|_| buf)
+}
+
+/// Looks for an md RAID superblock.
+///
+/// - 1.1: at offset 0
+/// - 1.2: at offset 4096
+/// - 1.0: 8 KiB from the end, aligned down to 4 KiB
+/// - 0.90: in the last 64 KiB-aligned 64 KiB block
+fn has_md_superblock(file: &std::fs::File) -> bool {
+    use std::io::{Seek, SeekFrom};
+
+    let is_md = |offset: u64| {
+        read_bytes_at(file, offset, 4)
+            .is_some_and(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) == MD_SB_MAGIC)
+    };
+
+    if is_md(0) || is_md(4096) {
+        return true;
+    }
+
+    // The size of a block device is only known by seeking to its end;
+    // the position is restored for the sequential reads that follow
+    let mut handle = file;
+    let size = handle.seek(SeekFrom::End(0));
+    if handle.seek(SeekFrom::Start(0)).is_err() {
+        return false;
+    }
+    let Ok(size) = size else {
+        return false;
+    };
+
+    if size >= 8192 && is_md((size - 8192) & !4095) {
+        return true;
+    }
+    size >= 65536 && is_md((size & !65535) - 65536)
+}
+
+/// Detects the signatures that are not ext, XFS, Btrfs or LUKS.
+fn detect_other_signature(file: &std::fs::File) -> Option<&'static str> {
+    let at = |offset: u64, len: usize| read_bytes_at(file, offset, len);
+
+    // LVM2 PV: "LABELONE" in one of the first four sectors, type "LVM2 001"
+    for sector in 0..4u64 {
+        if let Some(label) = at(sector * 512, 32)
+            && &label[0..8] == b"LABELONE"
+            && &label[24..32] == b"LVM2 001"
+        {
+            return Some("LVM2_member");
+        }
+    }
+
+    if let Some(magic) = at(4096 + 24, 16) {
+        if magic == BCACHE_MAGIC {
+            return Some("bcache");
+        }
+        if magic == BCACHEFS_MAGIC {
+            return Some("bcachefs");
+        }
+    }
+
+    // Swap: signature in the last 10 bytes of the first page
+    for page_size in [4096u64, 8192, 16384, 65536] {
+        if let Some(sig) = at(page_size - 10, 10)
+            && (sig == b"SWAPSPACE2" || sig == b"SWAP-SPACE")
+        {
+            return Some("swap");
+        }
+    }
+
+    if at(1024, 4).is_some_and(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) == F2FS_MAGIC) {
+        return Some("f2fs");
+    }
+
+    if at(1024 + 6, 2).is_some_and(|b| u16::from_le_bytes([b[0], b[1]]) == NILFS_MAGIC) {
+        return Some("nilfs2");
+    }
+
+    // ZFS: uberblocks of 1 KiB or more, from 128 KiB into the first label
+    for slot in 0..128u64 {
+        if let Some(b) = at(128 * 1024 + slot * 1024, 8) {
+            let magic = [b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]];
+            if u64::from_le_bytes(magic) == ZFS_UBERBLOCK_MAGIC
+                || u64::from_be_bytes(magic) == ZFS_UBERBLOCK_MAGIC
+            {
+                return Some("zfs_member");
+            }
+        }
+    }
+
+    // Boot-sector filesystems: OEM name at offset 3, or a FAT type string
+    // with the 0x55AA boot signature
+    if let Some(boot) = at(0, 512) {
+        if &boot[3..11] == b"NTFS    " {
+            return Some("ntfs");
+        }
+        if &boot[3..11] == b"EXFAT   " {
+            return Some("exfat");
+        }
+        if boot[510..512] == [0x55, 0xAA]
+            && (&boot[54..59] == b"FAT12" || &boot[54..59] == b"FAT16" || &boot[82..87] == b"FAT32")
+        {
+            return Some("vfat");
+        }
+    }
+
+    // ISO9660: "CD001" in the primary volume descriptor at 32 KiB
+    if at(32769, 5).is_some_and(|b| b == b"CD001") {
+        return Some("iso9660");
+    }
+
+    None
+}
+
 /// Distinguishes between ext2, ext3, and ext4 by reading feature flags.
 ///
 /// - ext4: INCOMPAT_EXTENTS (0x0040) at offset 1124
@@ -107,9 +274,59 @@ fn detect_ext_version(file: &mut std::fs::File) -> Result<String, ResizeError> {
     Ok("ext2".to_string())
 }
 
+/// Filesystem type, UUID and label read from a superblock.
+#[derive(Debug, PartialEq)]
+pub(crate) struct FsIdentity {
+    pub fs_type: String,
+    pub uuid: Option<String>,
+    pub label: Option<String>,
+}
+
+/// Reads the type, UUID and label of the filesystem on a device.
+///
+/// ext2/3/4, XFS, Btrfs, F2FS, bcachefs, NILFS2, swap, NTFS and FAT are read
+/// with [`crate::superblock::read_fs_info`].
+/// For LUKS, the textual UUID is at offset 168 (40 bytes) and the LUKS2
+/// label at offset 24 (48 bytes).
+pub(crate) fn read_fs_identity(path: &Path) -> Result<FsIdentity, ResizeError> {
+    use crate::superblock::{self, nul_terminated};
+    use std::os::unix::fs::FileExt;
+
+    let fs_type = detect_fs_magic(path)?;
+    if fs_type != "crypto_LUKS" {
+        let info = superblock::read_fs_info(path)?;
+        return Ok(FsIdentity {
+            fs_type,
+            uuid: info.uuid,
+            label: info.label,
+        });
+    }
+
+    let file = std::fs::File::open(path)
+        .map_err(|e| ResizeError::DeviceNotFound(format!("{}: {}", path.display(), e)))?;
+    let read_at = |offset: u64, len: usize| {
+        let mut buf = vec![0u8; len];
+        file.read_exact_at(&mut buf, offset).ok().map(|_| buf)
+    };
+
+    // The LUKS version is a big-endian u16 right after the magic
+    let version = read_at(6, 2).map(|b| u16::from_be_bytes([b[0], b[1]]));
+    let label = if version == Some(2) {
+        read_at(24, 48).and_then(|b| nul_terminated(&b))
+    } else {
+        None
+    };
+
+    Ok(FsIdentity {
+        fs_type,
+        uuid: read_at(168, 40).and_then(|b| nul_terminated(&b)),
+        label,
+    })
+}
+
 /// Minimum growth threshold in bytes. If the partition can only grow by less
 /// than this amount, it is considered already at maximum size.
-const GROW_FUDGE_BYTES: u64 = 1024 * 1024; // 1 MiB, same as growpart
+pub const GROW_FUDGE_BYTES: u64 = 1024 * 1024; // 1 MiB, same as growpart
 
 /// Sectors reserved for GPT secondary header and table.
 const GPT_SECONDARY_SECTORS: u64 = 33;
@@ -122,7 +339,15 @@ const MBR_MAX_SECTORS_512: u64 = 4_294_967_296;
 /// of this value for optimal I/O alignment (matches growpart behavior).
 const ALIGN_BYTES: u64 = 1024 * 1024; // 1 MiB
 
-pub fn grow_partition(disk: &str, partition: Option<u32>) -> Result<bool, ResizeError> {
+/// Grows a partition to the end of the free space after it.
+///
+/// `max_bytes` caps the partition size, e.g. at the largest size the
+/// filesystem on it can reach online.
+pub fn grow_partition(
+    disk: &str,
+    partition: Option<u32>,
+    max_bytes: Option<u64>,
+) -> Result<bool, ResizeError> {
     if partition.is_none() {
         info!("Device is a whole disk (not a partition), skipping partition resize");
         return Ok(false);
@@ -138,7 +363,18 @@ pub fn grow_partition(disk: &str, partition: Option<u32>) -> Result<bool, Resize
     let disk_info = parse_sfdisk_dump(&dump, disk, partition_num)?;
 
     // Step 3: Compute max_end considering other partitions and GPT secondary header
-    let max_end = compute_max_end(&disk_info);
+    let mut max_end = compute_max_end(&disk_info);
+    if let Some(max_bytes) = max_bytes {
+        let capped = cap_max_end(&disk_info, max_end, max_bytes);
+        if capped < max_end {
+            warn!(
+                "Growing partition {} only to {}, the largest size its filesystem can use",
+                partition_num,
+                format_bytes((capped + 1 - disk_info.pt_start) * disk_info.sector_size)
+            );
+            max_end = capped;
+        }
+    }
 
     // Step 4: Check if partition can actually grow
     if disk_info.pt_end >= max_end {
@@ -199,57 +435,63 @@ pub fn grow_partition(disk: &str, partition: Option<u32>) -> Result<bool, Resize
     Ok(true)
 }
 
-/// Attempts to resize an LVM physical volume if the partition is one.
-///
-/// After a partition is grown, the LVM PV on it needs to be resized to
-/// use the new space. This is equivalent to growpart's `maybe_lvm_resize`.
-/// If `lvm` is not installed or the partition is not an LVM PV, this is a no-op.
-pub fn maybe_lvm_resize(partition_device: &Path) -> Result<(), ResizeError> {
-    // Check if lvm command is available
-    if crate::find_in_path("lvm").is_none() {
-        return Ok(());
+/// Returns the size in bytes [`grow_partition`] would give a partition,
+/// without changing anything.
+pub fn partition_target_size(
+    disk: &str,
+    partition_num: u32,
+    max_bytes: Option<u64>,
+) -> Result<u64, ResizeError> {
+    let dump = sfdisk_dump(disk)?;
+    let disk_info = parse_sfdisk_dump(&dump, disk, partition_num)?;
+
+    let mut max_end = compute_max_end(&disk_info).max(disk_info.pt_end);
+    if let Some(max_bytes) = max_bytes {
+        max_end = cap_max_end(&disk_info, max_end, max_bytes);
     }
 
-    // Check if the partition is an LVM physical volume
-    let pvs_output = Command::new("lvm")
-        .args([
-            "pvs",
-            "--nolocking",
-            "--readonly",
-            "-o",
-            "pvname",
-            &partition_device.to_string_lossy(),
-        ])
-        .output();
+    Ok((max_end + 1 - disk_info.pt_start) * disk_info.sector_size)
+}
 
-    match pvs_output {
-        Ok(output) => {
-            if output.status.code() == Some(5) {
-                // Exit code 5 means "not an LVM PV"
-                return Ok(());
-            }
-            if !output.status.success() {
-                return Ok(());
-            }
-        }
-        Err(_) => return Ok(()),
+/// Resizes an LVM physical volume if it does not cover its whole device.
+///
+/// The PV size is compared against the size of the underlying device on
+/// every call, so whole-disk PVs and PVs whose partition was grown outside
+/// hot-resize (e.g. by growpart) are picked up too. This replaces growpart's
+/// `maybe_lvm_resize`, which only runs right after a partition change.
+/// If `lvm` is not installed or the device is not an LVM PV, this is a no-op.
+///
+/// Returns how many bytes of the device `pvresize` added to the PV, or 0 if
+/// it was not run.
+pub fn maybe_lvm_resize(pv_device: &Path) -> Result<u64, ResizeError> {
+    let unused = match lvm_pv_unused_bytes(pv_device)? {
+        Some(unused) => unused,
+        None => return Ok(0),
+    };
+
+    if unused == 0 {
+        info!(
+            "LVM physical volume on {} already uses the whole device",
+            pv_device.display()
+        );
+        return Ok(0);
     }
 
-    // Partition is an LVM PV — resize it
     info!(
-        "Detected LVM physical volume on {}, running pvresize",
-        partition_device.display()
+        "LVM physical volume on {} is {} smaller than its device, running pvresize",
+        pv_device.display(),
+        format_bytes(unused)
     );
 
     let pvresize_output = Command::new("lvm")
-        .args(["pvresize", &partition_device.to_string_lossy()])
+        .args(["pvresize", &pv_device.to_string_lossy()])
         .output();
 
     match pvresize_output {
         Ok(output) => {
             if output.status.success() {
                 info!("Successfully resized LVM physical volume");
-                Ok(())
+                Ok(unused)
             } else {
                 let error = String::from_utf8_lossy(&output.stderr);
                 Err(ResizeError::ResizeFs(format!(
@@ -265,6 +507,94 @@ pub fn maybe_lvm_resize(partition_device: &Path) -> Result<(), ResizeError> {
     }
 }
 
+/// Returns how many bytes of a device are not covered by the LVM PV on it.
+///
+/// Returns `None` if `lvm` is not installed or the device is not an LVM PV,
+/// and `Some(0)` if the PV already spans the device (within one extent).
+pub fn lvm_pv_unused_bytes(pv_device: &Path) -> Result<Option<u64>, ResizeError> {
+    if crate::find_in_path("lvm").is_none() {
+        return Ok(None);
+    }
+
+    let pvs_output = Command::new("lvm")
+        .args([
+            "pvs",
+            "--nolocking",
+            "--readonly",
+            "--noheadings",
+            "--nosuffix",
+            "--units",
+            "b",
+            "--separator",
+            ":",
+            "-o",
+            "pv_size,pe_start,vg_extent_size",
+            &pv_device.to_string_lossy(),
+        ])
+        .output();
+
+    // Exit code 5 means "not an LVM PV"; any other failure is treated the same
+    let pv_info = match pvs_output {
+        Ok(output) if output.status.success() => {
+            match parse_pvs_output(&String::from_utf8_lossy(&output.stdout)) {
+                Some(info) => info,
+                None => return Ok(None),
+            }
+        }
+        _ => return Ok(None),
+    };
+
+    let dev_size = crate::get_device_size(pv_device)
+        .map_err(|e| ResizeError::DeviceNotFound(e.to_string()))?;
+
+    Ok(Some(pv_unused_bytes(dev_size, &pv_info)))
+}
+
+/// Size information about an LVM physical volume, in bytes.
+#[derive(Debug, PartialEq)]
+struct PvInfo {
+    /// Size reported by `pv_size` (allocatable extents for PVs in a VG).
+    pv_size: u64,
+    /// Offset of the first physical extent.
+    pe_start: u64,
+    /// Extent size of the VG, or 0 for orphan PVs.
+    extent_size: u64,
+}
+
+/// Parses one line of `pvs --noheadings --nosuffix --units b --separator :`
+/// output with the fields `pv_size,pe_start,vg_extent_size`.
+fn parse_pvs_output(output: &str) -> Option<PvInfo> {
+    let line = output.lines().map(str::trim).find(|l| !l.is_empty())?;
+    let mut fields = line.split(':').map(str::trim);
+
+    let pv_size = fields.next()?.parse().ok()?;
+    let pe_start = fields.next()?.parse().ok()?;
+    // Orphan PVs have no VG, so the extent size is empty or 0
+    let extent_size = fields.next().and_then(|f| f.parse().ok()).unwrap_or(0);
+
+    Some(PvInfo {
+        pv_size,
+        pe_start,
+        extent_size,
+    })
+}
+
+/// Computes how many bytes of the device the PV does not use yet.
+///
+/// For a PV in a VG, `pv_size` only counts whole extents, so anything less
+/// than one extent cannot be used and is reported as 0. Orphan PVs report
+/// the device size recorded at `pvcreate` time, and use the same 1 MiB
+/// threshold as partition growth.
+fn pv_unused_bytes(dev_size: u64, info: &PvInfo) -> u64 {
+    if info.extent_size == 0 {
+        let unused = dev_size.saturating_sub(info.pv_size);
+        if unused < GROW_FUDGE_BYTES { 0 } else { unused }
+    } else {
+        let unused = dev_size.saturating_sub(info.pe_start + info.pv_size);
+        if unused < info.extent_size { 0 } else { unused }
+    }
+}
+
 /// Information about a partition parsed from sfdisk --dump output.
 struct SfdiskDiskInfo {
     sector_num: u64,
@@ -367,7 +697,7 @@ fn parse_disk_geometry(list_output: &str) -> Result<(u64, u64), ResizeError> {
                 }
             }
             if let (Some(bytes), Some(secs)) = (disk_bytes, sectors) {
-                let sector_size = if secs > 0 { bytes / secs } else { 512 };
+                let sector_size = bytes.checked_div(secs).unwrap_or(512);
                 return Ok((secs, sector_size));
             }
         }
@@ -510,15 +840,25 @@ fn compute_max_end(info: &SfdiskDiskInfo) -> u64 {
     // Align partition size to 1 MiB boundary (same logic as growpart).
     // This rounds the partition size DOWN to the nearest 1 MiB multiple.
     let sectors_per_align = ALIGN_BYTES / info.sector_size;
-    if sectors_per_align > 0 {
-        let max_size = max_end + 1 - info.pt_start;
-        let aligned_size = (max_size / sectors_per_align) * sectors_per_align;
-        max_end = aligned_size + info.pt_start - 1;
+    let max_size = max_end + 1 - info.pt_start;
+    if let Some(aligned_units) = max_size.checked_div(sectors_per_align) {
+        max_end = aligned_units * sectors_per_align + info.pt_start - 1;
     }
 
     max_end
 }
 
+/// Lowers the end sector so the partition is at most `max_bytes`, rounded
+/// down to the 1 MiB alignment. A partition already past the cap keeps its
+/// current end.
+fn cap_max_end(info: &SfdiskDiskInfo, max_end: u64, max_bytes: u64) -> u64 {
+    let sectors_per_align = (ALIGN_BYTES / info.sector_size).max(1);
+    let max_sectors = max_bytes / info.sector_size / sectors_per_align * sectors_per_align;
+    let cap_end = (info.pt_start + max_sectors).saturating_sub(1);
+
+    max_end.min(cap_end.max(info.pt_end))
+}
+
 /// Builds a new sfdisk dump with the updated partition size.
 /// Also removes `last-lba:` for GPT to allow sfdisk to use the full disk.
 fn build_new_dump(dump: &str, info: &SfdiskDiskInfo, new_size: u64) -> Result<String, ResizeError> {
@@ -746,7 +1086,29 @@ fn restore_partition_table(disk: &str, backup_path: &std::path::Path) -> Result<
 }
 
 /// Notifies the kernel of a partition size change using partx.
+///
+/// Device-mapper disks (e.g. multipath maps) have no in-kernel partitions,
+/// so their kpartx partition mappings are updated with `kpartx -u` instead.
 fn notify_kernel_partition_change(disk: &str, partition_num: u32) {
+    if Path::new(disk)
+        .file_name()
+        .is_some_and(|n| n.to_string_lossy().starts_with("dm-"))
+    {
+        match Command::new("kpartx").args(["-u", disk]).output() {
+            Ok(output) if output.status.success() => {
+                info!("Partition mappings updated via kpartx");
+            }
+            Ok(output) => {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                warn!("kpartx -u failed: {}", stderr.trim_end());
+            }
+            Err(e) => {
+                warn!("Failed to execute kpartx: {}", e);
+            }
+        }
+        return;
+    }
+
     let partx_output = Command::new("partx")
         .args(["--update", "--nr", &partition_num.to_string(), disk])
         .output();
@@ -780,159 +1142,1022 @@ fn udevadm_settle() {
     // If udevadm is not available, silently continue (same as growpart)
 }
 
+/// Grows a filesystem to fill its device.
+///
+/// `fs_type` is used as given: callers detect the filesystem with
+/// [`get_fs_type`] first and decide what to do when it differs from the
+/// configured type. `mount_point` is where the filesystem is mounted, or
+/// `None` when it is not mounted anywhere.
 pub fn resize_filesystem(
     device: &Path,
     fs_type: &str,
-    mount_point: &Path,
+    mount_point: Option<&Path>,
 ) -> Result<(), ResizeError> {
-    let real_fs_type = match get_fs_type(device) {
-        Ok(fs) => fs,
-        Err(_) => {
-            info!(
-                "Could not detect filesystem type, using specified type: {}",
-                fs_type
-            );
-            fs_type.to_string()
-        }
-    };
-
-    if real_fs_type != fs_type {
-        info!(
-            "Detected filesystem type {} differs from specified {}",
-            real_fs_type, fs_type
-        );
-        return resize_fs(&real_fs_type, device, mount_point);
+    match mount_point {
+        Some(mount_point) => resize_fs(fs_type, device, mount_point),
+        None => resize_fs_offline(fs_type, device),
     }
-
-    resize_fs(fs_type, device, mount_point)
 }
 
-fn resize_fs(fs_type: &str, device: &Path, mount_point: &Path) -> Result<(), ResizeError> {
-    info!("Resizing {} filesystem on {}", fs_type, device.display());
-
-    match fs_type.to_lowercase().as_str() {
-        "ext4" | "ext3" | "ext2" => {
-            let resize2fs_output = Command::new("resize2fs").arg("-f").arg(device).output();
-
-            match resize2fs_output {
-                Ok(output) => {
-                    if output.status.success() {
-                        info!("Successfully resized ext filesystem");
-                        return Ok(());
-                    }
-                    let error = String::from_utf8_lossy(&output.stderr);
-                    warn!("resize2fs failed: {}", error);
-                }
-                Err(e) => {
-                    warn!("Failed to execute resize2fs: {}", e);
-                }
-            }
-
-            Err(ResizeError::ResizeFs(format!(
-                "Failed to resize {} filesystem",
-                fs_type
-            )))
-        }
-        "xfs" => {
-            let xfs_growfs_output = Command::new("xfs_growfs").arg(mount_point).output();
-
-            match xfs_growfs_output {
-                Ok(output) => {
-                    if output.status.success() {
-                        info!("Successfully resized XFS filesystem");
-                        return Ok(());
-                    }
-                    let error = String::from_utf8_lossy(&output.stderr);
-                    warn!("xfs_growfs failed: {}", error);
-
-                    let xfs_growfs_d_output = Command::new("xfs_growfs")
-                        .args(["-d", &mount_point.to_string_lossy()])
-                        .output();
+/// Refuses a filesystem type that cannot be grown while it is mounted.
+pub fn check_online_resizable(fs_type: &str, mount_point: &Path) -> Result<(), ResizeError> {
+    match fs_type {
+        "f2fs" => Err(ResizeError::ResizeFs(format!(
+            "F2FS can only be grown offline, unmount {} and run hot-resize again",
+            mount_point.display()
+        ))),
+        "ntfs" | "vfat" => Err(ResizeError::ResizeFs(format!(
+            "{} is mounted on {}, and {} can only be grown unmounted",
+            fs_type,
+            mount_point.display(),
+            fs_type
+        ))),
+        _ => Ok(()),
+    }
+}
 
-                    match xfs_growfs_d_output {
-                        Ok(output) => {
-                            if output.status.success() {
-                                info!("Successfully resized XFS filesystem using -d flag");
-                                return Ok(());
-                            }
-                            let error = String::from_utf8_lossy(&output.stderr);
-                            warn!("xfs_growfs -d failed: {}", error);
-                        }
-                        Err(e) => {
-                            warn!("Failed to execute xfs_growfs -d: {}", e);
-                        }
-                    }
-                }
-                Err(e) => {
-                    warn!("Failed to execute xfs_growfs: {}", e);
-                }
-            }
+/// Grows an unmounted filesystem.
+fn resize_fs_offline(fs_type: &str, device: &Path) -> Result<(), ResizeError> {
+    info!(
+        "Resizing unmounted {} filesystem on {}",
+        fs_type,
+        device.display()
+    );
 
-            Err(ResizeError::ResizeFs(
-                "Failed to resize XFS filesystem".to_string(),
-            ))
-        }
-        "btrfs" => {
-            let btrfs_resize_output = Command::new("btrfs")
-                .args([
-                    "filesystem",
-                    "resize",
-                    "max",
-                    &mount_point.to_string_lossy(),
-                ])
-                .output();
-
-            match btrfs_resize_output {
-                Ok(output) => {
-                    if output.status.success() {
-                        info!("Successfully resized Btrfs filesystem");
-                        return Ok(());
-                    }
-                    let error = String::from_utf8_lossy(&output.stderr);
-                    warn!("btrfs filesystem resize failed: {}", error);
-                }
-                Err(e) => {
-                    warn!("Failed to execute btrfs filesystem resize: {}", e);
-                }
+    match fs_type {
+        "ext4" | "ext3" | "ext2" => resize_ext_offline(device),
+        "bcachefs" => resize_bcachefs(device),
+        "xfs" | "btrfs" | "nilfs2" => {
+            // A multi-device Btrfs may be mounted through another member
+            if fs_type == "btrfs" && crate::btrfs::is_mounted(device)? {
+                return Err(ResizeError::ResizeFs(format!(
+                    "the Btrfs filesystem on {} is mounted through another device, set mount_point",
+                    device.display()
+                )));
             }
 
-            Err(ResizeError::ResizeFs(
-                "Failed to resize Btrfs filesystem".to_string(),
-            ))
+            // xfs_growfs, btrfs and nilfs-resize only work on a mounted
+            // filesystem
+            let mount = crate::tempmount::TempMount::new(device, fs_type)?;
+            let result = resize_fs(fs_type, device, mount.path());
+            let unmounted = mount.unmount();
+            result.and(unmounted)
         }
+        "f2fs" => resize_f2fs_offline(device),
+        "ntfs" => resize_ntfs_offline(device),
+        "vfat" => resize_fat_offline(device),
         _ => Err(ResizeError::ResizeFs(format!(
-            "Unsupported filesystem: {}",
+            "{} is not mounted, and {} cannot be resized offline",
+            device.display(),
             fs_type
         ))),
     }
 }
 
-pub fn resize_luks(device: &Path) -> Result<(), ResizeError> {
-    info!("Resizing LUKS container on {}", device.display());
-
-    let cryptsetup_output = Command::new("cryptsetup")
-        .args(["resize", &device.to_string_lossy()])
-        .output();
+/// Checks and grows an unmounted ext2/3/4 filesystem: `e2fsck -f -p`, then
+/// `resize2fs`, which requires a freshly checked filesystem when offline.
+fn resize_ext_offline(device: &Path) -> Result<(), ResizeError> {
+    info!("Checking {} with e2fsck", device.display());
+    let output = Command::new("e2fsck")
+        .args(["-f", "-p"])
+        .arg(device)
+        .output()
+        .map_err(|e| ResizeError::CommandFailed(format!("Failed to execute e2fsck: {}", e)))?;
+    check_e2fsck_status(
+        output.status.code(),
+        &String::from_utf8_lossy(&output.stdout),
+    )?;
 
-    match cryptsetup_output {
-        Ok(output) => {
-            if output.status.success() {
-                info!("Successfully resized LUKS container");
-                return Ok(());
-            }
-            let error = String::from_utf8_lossy(&output.stderr);
-            Err(ResizeError::ResizeLuks(error.to_string()))
-        }
-        Err(e) => Err(ResizeError::CommandFailed(e.to_string())),
+    let output = Command::new("resize2fs")
+        .arg(device)
+        .output()
+        .map_err(|e| ResizeError::CommandFailed(format!("Failed to execute resize2fs: {}", e)))?;
+    if !output.status.success() {
+        return Err(resize2fs_error(&String::from_utf8_lossy(&output.stderr)));
     }
-}
 
-pub fn verify_resize(mount_point: &Path) -> Result<(), ResizeError> {
-    info!("Verifying resize at {}", mount_point.display());
+    info!("Successfully resized unmounted ext filesystem");
+    Ok(())
+}
 
-    let stat = nix::sys::statvfs::statvfs(mount_point).map_err(|e| {
-        ResizeError::CommandFailed(format!("statvfs failed on {:?}: {}", mount_point, e))
-    })?;
+/// Grows an unmounted F2FS filesystem with `resize.f2fs`, which rewrites
+/// the checkpoint, so the filesystem must not be mounted anywhere.
+fn resize_f2fs_offline(device: &Path) -> Result<(), ResizeError> {
+    let output = Command::new("resize.f2fs")
+        .arg(device)
+        .output()
+        .map_err(|e| ResizeError::CommandFailed(format!("Failed to execute resize.f2fs: {}", e)))?;
+    if !output.status.success() {
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(ResizeError::ResizeFs(format!(
+            "resize.f2fs failed: {}{}",
+            stdout.trim_end(),
+            stderr.trim_end()
+        )));
+    }
+
+    info!("Successfully resized F2FS filesystem");
+    Ok(())
+}
+
+/// Checks an unmounted NTFS filesystem with `ntfsfix -n` and
+/// `ntfsresize --check`, then grows it to the size of its device with
+/// `ntfsresize`.
+///
+/// ntfsfix only reports in this mode: a volume that needs repairs must be
+/// checked by chkdsk from Windows. ntfsresize is never given `--force`,
+/// which would also skip its own refusal of a volume marked for a
+/// consistency check; its confirmation prompt is answered on stdin instead.
+fn resize_ntfs_offline(device: &Path) -> Result<(), ResizeError> {
+    info!("Checking {} with ntfsfix", device.display());
+    let output = Command::new("ntfsfix")
+        .arg("-n")
+        .arg(device)
+        .output()
+        .map_err(|e| ResizeError::CommandFailed(format!("Failed to execute ntfsfix: {}", e)))?;
+    if !output.status.success() {
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(ResizeError::Unhealthy(format!(
+            "ntfsfix found problems on {}, run chkdsk from Windows: {}{}",
+            device.display(),
+            stdout.trim_end(),
+            stderr.trim_end()
+        )));
+    }
+
+    let output = Command::new("ntfsresize")
+        .arg("--check")
+        .arg(device)
+        .output()
+        .map_err(|e| ResizeError::CommandFailed(format!("Failed to execute ntfsresize: {}", e)))?;
+    if !output.status.success() {
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(ResizeError::Unhealthy(format!(
+            "ntfsresize --check refused {}, run chkdsk from Windows: {}{}",
+            device.display(),
+            stdout.trim_end(),
+            stderr.trim_end()
+        )));
+    }
+
+    // Without a size, ntfsresize grows the filesystem to fill the device
+    let mut child = Command::new("ntfsresize")
+        .arg("--no-progress-bar")
+        .arg(device)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .map_err(|e| ResizeError::CommandFailed(format!("Failed to execute ntfsresize: {}", e)))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        use std::io::Write;
+        // ntfsresize may fail before asking, which closes the pipe
+        let _ = stdin.write_all(b"y\n");
+    }
+    let output = child
+        .wait_with_output()
+        .map_err(|e| ResizeError::CommandFailed(format!("Failed to execute ntfsresize: {}", e)))?;
+    if !output.status.success() {
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(ResizeError::ResizeFs(format!(
+            "ntfsresize failed: {}{}",
+            stdout.trim_end(),
+            stderr.trim_end()
+        )));
+    }
+
+    info!("Successfully resized NTFS filesystem");
+    Ok(())
+}
+
+/// Grows an unmounted FAT filesystem to the size of its device with
+/// `fatresize -s max`.
+fn resize_fat_offline(device: &Path) -> Result<(), ResizeError> {
+    let output = Command::new("fatresize")
+        .args(["-s", "max"])
+        .arg(device)
+        .output()
+        .map_err(|e| ResizeError::CommandFailed(format!("Failed to execute fatresize: {}", e)))?;
+    if !output.status.success() {
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(ResizeError::ResizeFs(format!(
+            "fatresize failed: {}{}",
+            stdout.trim_end(),
+            stderr.trim_end()
+        )));
+    }
+
+    info!("Successfully resized FAT filesystem");
+    Ok(())
+}
+
+/// Grows a bcachefs member device with `bcachefs device resize`, which
+/// works on a mounted filesystem as well as on an unmounted device. Only
+/// this device is resized; other members are resized through their own
+/// entries.
+fn resize_bcachefs(device: &Path) -> Result<(), ResizeError> {
+    let output = Command::new("bcachefs")
+        .args(["device", "resize"])
+        .arg(device)
+        .output()
+        .map_err(|e| {
+            ResizeError::CommandFailed(format!("Failed to execute bcachefs device resize: {}", e))
+        })?;
+    if !output.status.success() {
+        let error = String::from_utf8_lossy(&output.stderr);
+        return Err(ResizeError::ResizeFs(format!(
+            "bcachefs device resize failed: {}",
+            error.trim_end()
+        )));
+    }
+
+    info!("Successfully resized bcachefs device");
+    Ok(())
+}
+
+/// Grows a mounted NILFS2 filesystem to the size of its device with
+/// `nilfs-resize`, which finds the mount from the device.
+fn resize_nilfs2(device: &Path) -> Result<(), ResizeError> {
+    let output = Command::new("nilfs-resize")
+        .arg("-y")
+        .arg(device)
+        .output()
+        .map_err(|e| {
+            ResizeError::CommandFailed(format!("Failed to execute nilfs-resize: {}", e))
+        })?;
+    if !output.status.success() {
+        let error = String::from_utf8_lossy(&output.stderr);
+        return Err(ResizeError::ResizeFs(format!(
+            "nilfs-resize failed: {}",
+            error.trim_end()
+        )));
+    }
+
+    info!("Successfully resized NILFS2 filesystem");
+    Ok(())
+}
+
+/// Maps the exit status of `e2fsck -p` to an error. The status is a bit
+/// mask (see e2fsck(8)): 1 and 2 mean errors were corrected, anything
+/// higher means the filesystem must not be touched.
+fn check_e2fsck_status(code: Option<i32>, output: &str) -> Result<(), ResizeError> {
+    let Some(code) = code else {
+        return Err(ResizeError::ResizeFs(
+            "e2fsck was killed by a signal".to_string(),
+        ));
+    };
+
+    if code & !3 == 0 {
+        if code != 0 {
+            warn!("e2fsck corrected errors: {}", output.trim_end());
+        }
+        return Ok(());
+    }
+
+    let reason = if code & 128 != 0 {
+        "shared library error"
+    } else if code & 32 != 0 {
+        "check was cancelled"
+    } else if code & 16 != 0 {
+        "usage or syntax error"
+    } else if code & 8 != 0 {
+        "operational error"
+    } else {
+        "errors left uncorrected, run e2fsck manually"
+    };
+
+    Err(ResizeError::ResizeFs(format!(
+        "e2fsck failed with status {} ({}): {}",
+        code,
+        reason,
+        output.trim_end()
+    )))
+}
+
+/// Turns the output of a failed `resize2fs`, which always exits with 1,
+/// into an error naming the cause where it is recognized.
+fn resize2fs_error(stderr: &str) -> ResizeError {
+    let reason = if stderr.contains("e2fsck -f") {
+        "the filesystem needs a full check first"
+    } else if stderr.contains("No space left on device") {
+        "not enough free space for the new metadata"
+    } else if stderr.contains("Permission denied") || stderr.contains("Device or resource busy") {
+        "the device is in use"
+    } else if stderr.contains("too large") || stderr.contains("Invalid argument") {
+        "the new size is not supported by this filesystem"
+    } else {
+        "resize2fs failed"
+    };
+
+    ResizeError::ResizeFs(format!("{}: {}", reason, stderr.trim_end()))
+}
+
+fn resize_fs(fs_type: &str, device: &Path, mount_point: &Path) -> Result<(), ResizeError> {
+    check_online_resizable(fs_type, mount_point)?;
+    info!("Resizing {} filesystem on {}", fs_type, device.display());
+
+    match fs_type.to_lowercase().as_str() {
+        "ext4" | "ext3" | "ext2" => {
+            let resize2fs_output = Command::new("resize2fs").arg("-f").arg(device).output();
+
+            match resize2fs_output {
+                Ok(output) => {
+                    if output.status.success() {
+                        info!("Successfully resized ext filesystem");
+                        return Ok(());
+                    }
+                    let error = String::from_utf8_lossy(&output.stderr);
+                    warn!("resize2fs failed: {}", error);
+                }
+                Err(e) => {
+                    warn!("Failed to execute resize2fs: {}", e);
+                }
+            }
+
+            Err(ResizeError::ResizeFs(format!(
+                "Failed to resize {} filesystem",
+                fs_type
+            )))
+        }
+        "xfs" => {
+            let xfs_growfs_output = Command::new("xfs_growfs").arg(mount_point).output();
+
+            match xfs_growfs_output {
+                Ok(output) => {
+                    if output.status.success() {
+                        info!("Successfully resized XFS filesystem");
+                        return Ok(());
+                    }
+                    let error = String::from_utf8_lossy(&output.stderr);
+                    warn!("xfs_growfs failed: {}", error);
+
+                    let xfs_growfs_d_output = Command::new("xfs_growfs")
+                        .args(["-d", &mount_point.to_string_lossy()])
+                        .output();
+
+                    match xfs_growfs_d_output {
+                        Ok(output) => {
+                            if output.status.success() {
+                                info!("Successfully resized XFS filesystem using -d flag");
+                                return Ok(());
+                            }
+                            let error = String::from_utf8_lossy(&output.stderr);
+                            warn!("xfs_growfs -d failed: {}", error);
+                        }
+                        Err(e) => {
+                            warn!("Failed to execute xfs_growfs -d: {}", e);
+                        }
+                    }
+                }
+                Err(e) => {
+                    warn!("Failed to execute xfs_growfs: {}", e);
+                }
+            }
+
+            Err(ResizeError::ResizeFs(
+                "Failed to resize XFS filesystem".to_string(),
+            ))
+        }
+        "btrfs" => crate::btrfs::resize_btrfs(device, mount_point),
+        "bcachefs" => resize_bcachefs(device),
+        "nilfs2" => resize_nilfs2(device),
+        _ => Err(ResizeError::ResizeFs(format!(
+            "Unsupported filesystem: {}",
+            fs_type
+        ))),
+    }
+}
+
+/// Kind of dm-crypt mapping, from the prefix of its dm uuid.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CryptKind {
+    /// `CRYPT-LUKS1-…`
+    Luks1,
+    /// `CRYPT-LUKS2-…`, with the header on the device or detached
+    Luks2,
+    /// `CRYPT-PLAIN-…`, plain dm-crypt without any header
+    Plain,
+    /// Any other `CRYPT-<type>-…` mapping (e.g. TCRYPT, BITLK)
+    Other(String),
+}
+
+impl CryptKind {
+    pub fn as_str(&self) -> &str {
+        match self {
+            CryptKind::Luks1 => "LUKS1",
+            CryptKind::Luks2 => "LUKS2",
+            CryptKind::Plain => "PLAIN",
+            CryptKind::Other(kind) => kind,
+        }
+    }
+}
+
+/// An active dm-crypt mapping on top of a device.
+#[derive(Debug, PartialEq)]
+pub struct CryptMapping {
+    /// Device-mapper name (e.g. `luks-root`), usable as `/dev/mapper/<name>`.
+    pub name: String,
+    pub kind: CryptKind,
+}
+
+impl CryptMapping {
+    pub fn mapper_path(&self) -> std::path::PathBuf {
+        std::path::PathBuf::from(format!("/dev/mapper/{}", self.name))
+    }
+}
+
+/// Returns the device an LVM PV on `device` would be: the dm-crypt mapping
+/// opened on it (LVM on LUKS or plain dm-crypt), or the device itself.
+pub fn lvm_pv_device(device: &Path, crypt_mapping: Option<&CryptMapping>) -> std::path::PathBuf {
+    crypt_mapping
+        .map(CryptMapping::mapper_path)
+        .unwrap_or_else(|| device.to_path_buf())
+}
+
+/// Finds the dm-crypt mapping opened on a device through the device tree.
+///
+/// This works for LUKS volumes with a detached header and plain dm-crypt
+/// mappings, which have no header on the device for `cryptsetup isLuks`.
+pub fn find_crypt_mapping(device: &Path) -> Result<Option<CryptMapping>, ResizeError> {
+    let real_device = std::fs::canonicalize(device)
+        .map_err(|e| ResizeError::DeviceNotFound(format!("{}: {}", device.display(), e)))?;
+    let dev_name = real_device
+        .file_name()
+        .and_then(|n| n.to_str())
+        .ok_or_else(|| ResizeError::DeviceNotFound(format!("{}", real_device.display())))?;
+
+    read_crypt_holder(Path::new("/sys/class/block"), dev_name)
+}
+
+/// Reads the dm-crypt holder of a device from a sysfs-like directory structure.
+///
+/// Looks at `<sysfs_base>/<dev_name>/holders/dm-*` and picks the holder whose
+/// `dm/uuid` starts with `CRYPT-`.
+fn read_crypt_holder(
+    sysfs_base: &Path,
+    dev_name: &str,
+) -> Result<Option<CryptMapping>, ResizeError> {
+    let holders_dir = sysfs_base.join(dev_name).join("holders");
+    let entries = match std::fs::read_dir(&holders_dir) {
+        Ok(entries) => entries,
+        Err(_) => return Ok(None),
+    };
+
+    let mut holders: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .filter(|name| name.starts_with("dm-"))
+        .collect();
+    holders.sort();
+
+    for holder in holders {
+        let dm_dir = sysfs_base.join(&holder).join("dm");
+        let uuid = match std::fs::read_to_string(dm_dir.join("uuid")) {
+            Ok(uuid) => uuid.trim().to_string(),
+            Err(_) => continue,
+        };
+
+        let kind = match uuid.strip_prefix("CRYPT-").and_then(|r| r.split_once('-')) {
+            Some(("LUKS1", _)) => CryptKind::Luks1,
+            Some(("LUKS2", _)) => CryptKind::Luks2,
+            Some(("PLAIN", _)) => CryptKind::Plain,
+            Some((other, _)) => CryptKind::Other(other.to_string()),
+            None => continue,
+        };
+
+        let name = std::fs::read_to_string(dm_dir.join("name")).map_err(|e| {
+            ResizeError::DeviceNotFound(format!("{}: missing dm name: {}", holder, e))
+        })?;
+
+        return Ok(Some(CryptMapping {
+            name: name.trim().to_string(),
+            kind,
+        }));
+    }
+
+    Ok(None)
+}
+
+/// Key source used to unlock a LUKS2 volume for `cryptsetup resize`.
+///
+/// LUKS1 volumes, and LUKS2 volumes whose volume key is in the kernel
+/// keyring, can be resized without a key. Other LUKS2 volumes need one of
+/// `key_file`, `token_id`/`token_type` or `passphrase_fd`.
+#[derive(Debug, Clone, Default, PartialEq, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LuksKey {
+    /// Key file passed to cryptsetup with `--key-file`.
+    #[serde(default)]
+    pub key_file: Option<std::path::PathBuf>,
+    /// Key slot to unlock with (`--key-slot`), together with a key file or
+    /// passphrase.
+    #[serde(default)]
+    pub key_slot: Option<u32>,
+    /// LUKS2 token to unlock with (`--token-id`), e.g. one enrolled by
+    /// systemd-cryptenroll.
+    #[serde(default)]
+    pub token_id: Option<u32>,
+    /// LUKS2 token type to unlock with (`--token-type`, e.g. `systemd-tpm2`).
+    #[serde(default)]
+    pub token_type: Option<String>,
+    /// Inherited file descriptor to read the passphrase from. The passphrase
+    /// is piped to cryptsetup and never appears on its command line.
+    #[serde(default)]
+    pub passphrase_fd: Option<i32>,
+    /// Passphrase already read from `passphrase_fd`, see
+    /// [`read_passphrase_fd`].
+    #[serde(skip)]
+    pub passphrase: Option<Passphrase>,
+}
+
+/// A passphrase kept in memory, zeroed when dropped.
+#[derive(Clone, Default, PartialEq)]
+pub struct Passphrase(Vec<u8>);
+
+impl Passphrase {
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl std::fmt::Debug for Passphrase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Passphrase(..)")
+    }
+}
+
+impl Drop for Passphrase {
+    fn drop(&mut self) {
+        self.0.fill(0);
+    }
+}
+
+impl LuksKey {
+    /// Checks that at most one key source is configured, and that a key
+    /// slot is only given along with a key file or passphrase.
+    pub fn validate(&self) -> Result<(), String> {
+        let uses_token = self.token_id.is_some() || self.token_type.is_some();
+        let sources = [
+            self.key_file.is_some(),
+            self.passphrase_fd.is_some(),
+            uses_token,
+        ];
+        if sources.iter().filter(|&&s| s).count() > 1 {
+            return Err(
+                "luks_key: key_file, passphrase_fd and token_id/token_type are exclusive"
+                    .to_string(),
+            );
+        }
+        if self.key_slot.is_some() && self.key_file.is_none() && self.passphrase_fd.is_none() {
+            return Err("luks_key: key_slot needs key_file or passphrase_fd".to_string());
+        }
+        if !sources.contains(&true) {
+            return Err("luks_key: no key source given".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Builds the `cryptsetup resize` arguments for a device, key source and
+/// optional detached LUKS header.
+///
+/// The passphrase itself is never part of the arguments; with
+/// `passphrase_fd` it is written to cryptsetup's stdin instead.
+fn luks_resize_args(device: &Path, key: Option<&LuksKey>, header: Option<&Path>) -> Vec<String> {
+    let mut args = vec!["resize".to_string()];
+
+    if let Some(header) = header {
+        args.push("--header".to_string());
+        args.push(header.to_string_lossy().to_string());
+    }
+
+    if let Some(key) = key {
+        if let Some(key_file) = &key.key_file {
+            args.push("--key-file".to_string());
+            args.push(key_file.to_string_lossy().to_string());
+        }
+        if let Some(slot) = key.key_slot {
+            args.push("--key-slot".to_string());
+            args.push(slot.to_string());
+        }
+        if let Some(token_id) = key.token_id {
+            args.push("--token-id".to_string());
+            args.push(token_id.to_string());
+        }
+        if let Some(token_type) = &key.token_type {
+            args.push("--token-type".to_string());
+            args.push(token_type.clone());
+        }
+        if key.token_id.is_some() || key.token_type.is_some() {
+            args.push("--token-only".to_string());
+        }
+    }
+
+    args.push(device.to_string_lossy().to_string());
+    args
+}
+
+/// Volume type and volume key location of an active crypt mapping, as
+/// reported by `cryptsetup status`.
+#[derive(Debug, PartialEq)]
+struct CryptStatus {
+    /// e.g. `LUKS1`, `LUKS2` or `PLAIN`.
+    crypt_type: String,
+    /// `keyring` or `dm-crypt`; older cryptsetup versions do not report it.
+    key_location: Option<String>,
+}
+
+impl CryptStatus {
+    /// Returns true if `cryptsetup resize` needs a key to resize this volume.
+    fn needs_key(&self) -> bool {
+        self.crypt_type == "LUKS2" && self.key_location.as_deref() != Some("keyring")
+    }
+}
+
+/// Parses the `type:` and `key location:` lines of `cryptsetup status`.
+fn parse_cryptsetup_status(output: &str) -> Option<CryptStatus> {
+    let mut crypt_type = None;
+    let mut key_location = None;
+
+    for line in output.lines() {
+        if let Some((field, value)) = line.trim().split_once(':') {
+            match field.trim() {
+                "type" => crypt_type = Some(value.trim().to_string()),
+                "key location" => key_location = Some(value.trim().to_string()),
+                _ => {}
+            }
+        }
+    }
+
+    Some(CryptStatus {
+        crypt_type: crypt_type?,
+        key_location,
+    })
+}
+
+/// Runs `cryptsetup status` on an active mapping.
+fn cryptsetup_status(device: &Path, header: Option<&Path>) -> Option<CryptStatus> {
+    let mut command = Command::new("cryptsetup");
+    command.arg("status");
+    if let Some(header) = header {
+        command.arg("--header").arg(header);
+    }
+    let output = command.arg(device).output().ok()?;
+    parse_cryptsetup_status(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Reads a passphrase from an inherited file descriptor.
+///
+/// A pipe is drained by the first read, so the passphrase should be read
+/// once at startup and kept in [`LuksKey::passphrase`] for every later
+/// resize. An empty read is reported instead of sending an empty
+/// passphrase.
+pub fn read_passphrase_fd(fd: i32) -> Result<Passphrase, ResizeError> {
+    use std::io::Read;
+
+    let mut passphrase = Passphrase::default();
+    std::fs::File::open(format!("/proc/self/fd/{}", fd))
+        .and_then(|mut file| file.read_to_end(&mut passphrase.0))
+        .map_err(|e| {
+            ResizeError::ResizeLuks(format!("Failed to read passphrase from fd {}: {}", fd, e))
+        })?;
+
+    if passphrase.0.is_empty() {
+        return Err(ResizeError::ResizeLuks(format!(
+            "No passphrase on fd {}",
+            fd
+        )));
+    }
+
+    Ok(passphrase)
+}
+
+/// Resizes an active LUKS or plain dm-crypt mapping to fill its underlying
+/// device.
+///
+/// `header` is the detached LUKS header of the volume, if any. When the
+/// volume is LUKS2 and its volume key is not in the kernel keyring,
+/// cryptsetup needs a key; a clear error is returned if none is configured
+/// instead of letting cryptsetup fail on a passphrase prompt.
+pub fn resize_luks(
+    device: &Path,
+    key: Option<&LuksKey>,
+    header: Option<&Path>,
+) -> Result<(), ResizeError> {
+    info!("Resizing LUKS container on {}", device.display());
+
+    if let Some(key) = key {
+        key.validate().map_err(ResizeError::ResizeLuks)?;
+
+        if let Some(key_file) = &key.key_file {
+            check_key_file(key_file)?;
+        }
+    } else if let Some(status) = cryptsetup_status(device, header)
+        && status.needs_key()
+    {
+        return Err(ResizeError::ResizeLuks(format!(
+            "{} is LUKS2 and its volume key is not in the kernel keyring; \
+             configure luks_key (key_file, token_id/token_type or passphrase_fd)",
+            device.display()
+        )));
+    }
+
+    let passphrase = match key {
+        Some(LuksKey {
+            passphrase: Some(passphrase),
+            ..
+        }) => Some(passphrase.clone()),
+        Some(LuksKey {
+            passphrase_fd: Some(fd),
+            ..
+        }) => Some(read_passphrase_fd(*fd)?),
+        _ => None,
+    };
+
+    let mut command = Command::new("cryptsetup");
+    command
+        .args(luks_resize_args(device, key, header))
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped());
+
+    let mut child = command
+        .spawn()
+        .map_err(|e| ResizeError::CommandFailed(e.to_string()))?;
+
+    // Write the passphrase (if any) and close stdin, so cryptsetup never
+    // waits for a prompt
+    if let Some(mut stdin) = child.stdin.take()
+        && let Some(passphrase) = passphrase
+    {
+        use std::io::Write;
+        stdin.write_all(passphrase.as_bytes()).map_err(|e| {
+            ResizeError::ResizeLuks(format!("Failed to pass passphrase to cryptsetup: {}", e))
+        })?;
+    }
+
+    let output = child
+        .wait_with_output()
+        .map_err(|e| ResizeError::CommandFailed(e.to_string()))?;
+
+    if output.status.success() {
+        info!("Successfully resized LUKS container");
+        return Ok(());
+    }
+
+    let error = String::from_utf8_lossy(&output.stderr);
+    Err(ResizeError::ResizeLuks(error.trim_end().to_string()))
+}
+
+/// Checks that a LUKS key file exists, and warns if others can read it.
+fn check_key_file(key_file: &Path) -> Result<(), ResizeError> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let metadata = std::fs::metadata(key_file)
+        .map_err(|e| ResizeError::ResizeLuks(format!("Key file {}: {}", key_file.display(), e)))?;
+
+    if !metadata.is_file() {
+        return Err(ResizeError::ResizeLuks(format!(
+            "Key file {} is not a regular file",
+            key_file.display()
+        )));
+    }
+
+    if metadata.permissions().mode() & 0o077 != 0 {
+        warn!(
+            "Key file {} is accessible by group or others",
+            key_file.display()
+        );
+    }
+
+    Ok(())
+}
+
+/// Slack allowed between the end of a filesystem and the end of its device
+/// for alignment, on top of the per-type allowance.
+const GROW_SLACK_BYTES: u64 = 1024 * 1024;
+
+/// Size of a filesystem, and how much of its device it may legitimately
+/// leave unused after growing to the maximum.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FsSize {
+    pub bytes: u64,
+    pub allowed_unused: u64,
+    /// Largest size the filesystem can reach online, if its features limit it
+    pub max_bytes: Option<u64>,
+}
+
+/// Measures the size of a filesystem as its resize tool sees it.
+///
+/// - ext2/3/4: block count from the superblock, which the kernel updates in
+///   the device page cache during an online resize. The last block group is
+///   dropped by resize2fs when it is too small to hold its metadata.
+/// - XFS: data blocks from `statvfs` plus the internal log, since the
+///   on-disk superblock is only written back lazily after `xfs_growfs`.
+/// - Btrfs: the size of this device in the filesystem (`dev_item`), which
+///   `btrfs filesystem resize` commits before returning.
+/// - F2FS: block count from the superblock. resize.f2fs lays out the main
+///   area in whole zones, so up to one zone stays unused.
+/// - bcachefs: size of this member (buckets times bucket size), which
+///   `bcachefs device resize` writes to the superblock. The superblock is
+///   read with `O_DIRECT`, since bcachefs writes it around the page cache.
+///   A partial bucket at the end stays unused.
+/// - NILFS2: the device size recorded in the superblock, which nilfs-resize
+///   commits. A partial segment at the end stays unused.
+/// - swap: the pages in the swap header written by mkswap. A partial page
+///   at the end stays unused.
+pub fn filesystem_size(
+    device: &Path,
+    fs_type: &str,
+    mount_point: Option<&Path>,
+) -> Result<FsSize, ResizeError> {
+    use crate::superblock::{FsDetail, read_fs_info};
+
+    let info = read_fs_info(device)?;
+    match (fs_type, &info.detail) {
+        (
+            "ext2" | "ext3" | "ext4",
+            FsDetail::Ext {
+                blocks_per_group, ..
+            },
+        ) => Ok(FsSize {
+            bytes: info.size_bytes(),
+            allowed_unused: u64::from(*blocks_per_group) * info.block_size + GROW_SLACK_BYTES,
+            max_bytes: match mount_point {
+                Some(_) => info.ext_online_limit(),
+                None => info.ext_offline_limit(),
+            }
+            .map(|limit| limit.max_bytes),
+        }),
+        (
+            "xfs",
+            FsDetail::Xfs {
+                internal_log_blocks,
+                ..
+            },
+        ) => {
+            // Unmounted, the superblock on disk is up to date
+            let bytes = match mount_point {
+                Some(mount_point) => {
+                    let stat = nix::sys::statvfs::statvfs(mount_point).map_err(|e| {
+                        ResizeError::CommandFailed(format!(
+                            "statvfs failed on {:?}: {}",
+                            mount_point, e
+                        ))
+                    })?;
+                    stat.blocks() * stat.fragment_size() as u64
+                        + u64::from(*internal_log_blocks) * info.block_size
+                }
+                None => info.size_bytes(),
+            };
+            Ok(FsSize {
+                bytes,
+                // A last allocation group too small to add stays unused
+                allowed_unused: crate::superblock::XFS_MIN_AG_BLOCKS * info.block_size
+                    + GROW_SLACK_BYTES,
+                max_bytes: None,
+            })
+        }
+        (
+            "btrfs",
+            FsDetail::Btrfs {
+                dev_total_bytes, ..
+            },
+        ) => Ok(FsSize {
+            bytes: *dev_total_bytes,
+            allowed_unused: GROW_SLACK_BYTES,
+            max_bytes: None,
+        }),
+        ("f2fs", FsDetail::F2fs { zone_blocks }) => Ok(FsSize {
+            bytes: info.size_bytes(),
+            allowed_unused: zone_blocks * info.block_size + GROW_SLACK_BYTES,
+            max_bytes: None,
+        }),
+        (
+            "bcachefs",
+            FsDetail::Bcachefs {
+                dev_bytes,
+                bucket_bytes,
+                ..
+            },
+        ) => Ok(FsSize {
+            bytes: *dev_bytes,
+            allowed_unused: bucket_bytes + GROW_SLACK_BYTES,
+            max_bytes: None,
+        }),
+        ("swap", FsDetail::Swap { .. }) => Ok(FsSize {
+            bytes: info.size_bytes(),
+            allowed_unused: info.block_size + GROW_SLACK_BYTES,
+            max_bytes: None,
+        }),
+        (
+            "nilfs2",
+            FsDetail::Nilfs2 {
+                dev_size,
+                blocks_per_segment,
+            },
+        ) => Ok(FsSize {
+            bytes: *dev_size,
+            allowed_unused: u64::from(*blocks_per_segment) * info.block_size + GROW_SLACK_BYTES,
+            max_bytes: None,
+        }),
+        // NTFS keeps a backup boot sector after its last sector
+        ("ntfs", FsDetail::Ntfs { cluster_bytes }) => Ok(FsSize {
+            bytes: info.size_bytes(),
+            allowed_unused: cluster_bytes + info.block_size + GROW_SLACK_BYTES,
+            max_bytes: None,
+        }),
+        ("vfat", FsDetail::Vfat { cluster_bytes, .. }) => Ok(FsSize {
+            bytes: info.size_bytes(),
+            allowed_unused: cluster_bytes + GROW_SLACK_BYTES,
+            max_bytes: info.fat_resize_limit().map(|limit| limit.max_bytes),
+        }),
+        _ => Err(ResizeError::Verify(format!(
+            "{} holds {}, expected {}",
+            device.display(),
+            info.kind.as_str(),
+            fs_type
+        ))),
+    }
+}
+
+/// Checks that a filesystem grew to fill its device.
+///
+/// `before` is the size measured with [`filesystem_size`] before resizing.
+/// Fails when the filesystem shrank, or when more of the device than the
+/// filesystem type needs is left unused, which also catches a resize tool
+/// that reported success without growing anything. Space past the online
+/// resize limit of the filesystem is not counted as unused.
+pub fn verify_resize(
+    device: &Path,
+    fs_type: &str,
+    mount_point: Option<&Path>,
+    before: FsSize,
+) -> Result<(), ResizeError> {
+    info!("Verifying resize of {}", device.display());
+
+    if let Some(mount_point) = mount_point {
+        report_usage(mount_point)?;
+    }
+
+    let after = filesystem_size(device, fs_type, mount_point)?;
+    let device_size = crate::get_device_size(device)
+        .map_err(|e| ResizeError::Verify(format!("{}: {}", device.display(), e)))?;
+
+    info!(
+        "Filesystem size: {} -> {} (device: {})",
+        format_bytes(before.bytes),
+        format_bytes(after.bytes),
+        format_bytes(device_size)
+    );
+
+    check_growth(before.bytes, after, device_size)
+}
+
+/// Compares the filesystem size after a resize with its size before and
+/// with the size of the device under it.
+fn check_growth(before: u64, after: FsSize, device_size: u64) -> Result<(), ResizeError> {
+    if after.bytes < before {
+        return Err(ResizeError::Verify(format!(
+            "filesystem shrank from {} to {}",
+            format_bytes(before),
+            format_bytes(after.bytes)
+        )));
+    }
+
+    // A filesystem stopped at its online limit cannot use the rest
+    let reachable = after
+        .max_bytes
+        .map_or(device_size, |max| device_size.min(max));
+    let unused = reachable.saturating_sub(after.bytes);
+    if unused > after.allowed_unused {
+        let reason = if after.bytes == before {
+            "filesystem did not grow"
+        } else {
+            "filesystem did not grow to the end of the device"
+        };
+        return Err(ResizeError::Verify(format!(
+            "{}: {} of {} unused (at most {} expected)",
+            reason,
+            format_bytes(unused),
+            format_bytes(device_size),
+            format_bytes(after.allowed_unused)
+        )));
+    }
+
+    Ok(())
+}
+
+/// Logs the total, used and available space of a mounted filesystem.
+pub fn report_usage(mount_point: &Path) -> Result<(), ResizeError> {
+    let stat = nix::sys::statvfs::statvfs(mount_point).map_err(|e| {
+        ResizeError::CommandFailed(format!("statvfs failed on {:?}: {}", mount_point, e))
+    })?;
 
     let block_size = stat.fragment_size() as u64;
     let total = stat.blocks() * block_size;
@@ -972,6 +2197,7 @@ fn format_bytes(bytes: u64) -> String {
 mod tests {
     use super::*;
     use std::io::{Seek, SeekFrom, Write};
+    use std::os::unix::fs::FileExt;
     use std::path::Path;
 
     /// Creates a temporary file with specific bytes written at a given offset.
@@ -1028,18 +2254,366 @@ mod tests {
     }
 
     #[test]
-    fn test_verify_resize_root() {
+    fn test_report_usage_root() {
         // Root filesystem should always be available
-        let result = verify_resize(Path::new("/"));
+        let result = report_usage(Path::new("/"));
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn test_report_usage_nonexistent_mount() {
+        let result = report_usage(Path::new("/nonexistent_mount_point_xyz"));
+        assert!(result.is_err());
+    }
+
     #[test]
     fn test_verify_resize_nonexistent_mount() {
-        let result = verify_resize(Path::new("/nonexistent_mount_point_xyz"));
+        let before = FsSize {
+            bytes: 0,
+            allowed_unused: 0,
+            max_bytes: None,
+        };
+        let result = verify_resize(
+            Path::new("/nonexistent_device_xyz"),
+            "ext4",
+            Some(Path::new("/nonexistent_mount_point_xyz")),
+            before,
+        );
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_check_online_resizable() {
+        let mount_point = Path::new("/data");
+        for fs_type in ["ext4", "xfs", "btrfs", "bcachefs", "nilfs2"] {
+            assert!(check_online_resizable(fs_type, mount_point).is_ok());
+        }
+        for fs_type in ["f2fs", "ntfs", "vfat"] {
+            let result = check_online_resizable(fs_type, mount_point);
+            assert!(matches!(result, Err(ResizeError::ResizeFs(ref m)) if m.contains("/data")));
+        }
+    }
+
+    #[test]
+    fn test_check_e2fsck_status() {
+        assert!(check_e2fsck_status(Some(0), "").is_ok());
+        // Errors corrected, with or without a reboot needed
+        assert!(check_e2fsck_status(Some(1), "fixed").is_ok());
+        assert!(check_e2fsck_status(Some(2), "fixed").is_ok());
+
+        let err = check_e2fsck_status(Some(4), "UNEXPECTED INCONSISTENCY").unwrap_err();
+        assert!(err.to_string().contains("run e2fsck manually"));
+        let err = check_e2fsck_status(Some(8), "").unwrap_err();
+        assert!(err.to_string().contains("operational error"));
+        let err = check_e2fsck_status(Some(12), "").unwrap_err();
+        assert!(err.to_string().contains("operational error"));
+        assert!(check_e2fsck_status(Some(32), "").is_err());
+        assert!(check_e2fsck_status(None, "").is_err());
+    }
+
+    #[test]
+    fn test_resize2fs_error() {
+        let err = resize2fs_error("Please run 'e2fsck -f /dev/vdb1' first.\n\n");
+        assert!(err.to_string().contains("needs a full check first"));
+        let err = resize2fs_error("resize2fs: No space left on device while trying to resize");
+        assert!(err.to_string().contains("not enough free space"));
+        let err = resize2fs_error("something else");
+        assert!(err.to_string().contains("resize2fs failed: something else"));
+    }
+
+    #[test]
+    fn test_check_growth() {
+        const GIB: u64 = 1024 * 1024 * 1024;
+        let after = |bytes| FsSize {
+            bytes,
+            allowed_unused: 128 * 1024 * 1024,
+            max_bytes: None,
+        };
+
+        // Grew to the end of the device
+        assert!(check_growth(10 * GIB, after(20 * GIB), 20 * GIB).is_ok());
+        // Last partial block group left out
+        assert!(check_growth(10 * GIB, after(20 * GIB - 64 * 1024 * 1024), 20 * GIB).is_ok());
+        // Nothing to grow into
+        assert!(check_growth(20 * GIB, after(20 * GIB), 20 * GIB).is_ok());
+
+        let err = check_growth(10 * GIB, after(10 * GIB), 20 * GIB).unwrap_err();
+        assert!(err.to_string().contains("did not grow:"));
+        let err = check_growth(10 * GIB, after(15 * GIB), 20 * GIB).unwrap_err();
+        assert!(err.to_string().contains("end of the device"));
+        let err = check_growth(10 * GIB, after(9 * GIB), 20 * GIB).unwrap_err();
+        assert!(err.to_string().contains("shrank"));
+
+        // Stopped at its online limit: the rest of the device is expected
+        let limited = FsSize {
+            max_bytes: Some(16 * GIB),
+            ..after(16 * GIB)
+        };
+        assert!(check_growth(10 * GIB, limited, 20 * GIB).is_ok());
+    }
+
+    #[test]
+    fn test_filesystem_size_ext4() {
+        let mut sb = vec![0u8; 1024];
+        sb[0x04..0x08].copy_from_slice(&262_144u32.to_le_bytes());
+        sb[0x18..0x1C].copy_from_slice(&2u32.to_le_bytes());
+        sb[0x20..0x24].copy_from_slice(&32_768u32.to_le_bytes());
+        sb[0x38..0x3A].copy_from_slice(&0xEF53u16.to_le_bytes());
+        sb[0x3A..0x3C].copy_from_slice(&1u16.to_le_bytes());
+        sb[0x60..0x64].copy_from_slice(&0x40u32.to_le_bytes());
+        let (_file, path) = create_fake_device(&[(1024, &sb)]);
+
+        let size = filesystem_size(&path, "ext4", Some(Path::new("/data"))).unwrap();
+        assert_eq!(size.bytes, 262_144 * 4096);
+        assert_eq!(size.allowed_unused, 32_768 * 4096 + GROW_SLACK_BYTES);
+        // No resize_inode: one block of 32-byte descriptors
+        assert_eq!(size.max_bytes, Some(128 * 32_768 * 4096));
+        // Offline, only the 32-bit block number limit applies
+        let size = filesystem_size(&path, "ext4", None).unwrap();
+        assert_eq!(size.max_bytes, Some(u64::from(u32::MAX) * 4096));
+
+        let err = filesystem_size(&path, "btrfs", None).unwrap_err();
+        assert!(matches!(err, ResizeError::Verify(_)));
+    }
+
+    #[test]
+    fn test_luks_resize_args_no_key() {
+        let args = luks_resize_args(Path::new("/dev/mapper/root"), None, None);
+        assert_eq!(args, vec!["resize", "/dev/mapper/root"]);
+    }
+
+    #[test]
+    fn test_luks_resize_args_detached_header() {
+        let args = luks_resize_args(
+            Path::new("/dev/mapper/data"),
+            None,
+            Some(Path::new("/boot/data.header")),
+        );
+        assert_eq!(
+            args,
+            vec![
+                "resize",
+                "--header",
+                "/boot/data.header",
+                "/dev/mapper/data"
+            ]
+        );
+    }
+
+    /// Creates a fake dm holder of a device with the given dm uuid and name.
+    fn create_fake_holder(base: &Path, dev: &str, holder: &str, uuid: &str, name: &str) {
+        std::fs::create_dir_all(base.join(dev).join("holders").join(holder)).unwrap();
+        let dm_dir = base.join(holder).join("dm");
+        std::fs::create_dir_all(&dm_dir).unwrap();
+        std::fs::write(dm_dir.join("uuid"), format!("{}\n", uuid)).unwrap();
+        std::fs::write(dm_dir.join("name"), format!("{}\n", name)).unwrap();
+    }
+
+    #[test]
+    fn test_read_crypt_holder_luks2() {
+        let tmpdir = tempfile::tempdir().expect("Failed to create temp dir");
+        create_fake_holder(
+            tmpdir.path(),
+            "vda2",
+            "dm-0",
+            "CRYPT-LUKS2-0f3a9c2e7d6b4e8f9a1b2c3d4e5f6a7b-luks-root",
+            "luks-root",
+        );
+
+        let mapping = read_crypt_holder(tmpdir.path(), "vda2").unwrap().unwrap();
+        assert_eq!(mapping.name, "luks-root");
+        assert_eq!(mapping.kind, CryptKind::Luks2);
+        assert_eq!(
+            mapping.mapper_path(),
+            std::path::PathBuf::from("/dev/mapper/luks-root")
+        );
+    }
+
+    #[test]
+    fn test_read_crypt_holder_plain() {
+        let tmpdir = tempfile::tempdir().expect("Failed to create temp dir");
+        create_fake_holder(
+            tmpdir.path(),
+            "sdb1",
+            "dm-2",
+            "CRYPT-PLAIN-scratch",
+            "scratch",
+        );
+
+        let mapping = read_crypt_holder(tmpdir.path(), "sdb1").unwrap().unwrap();
+        assert_eq!(mapping.kind, CryptKind::Plain);
+        assert_eq!(mapping.kind.as_str(), "PLAIN");
+    }
+
+    #[test]
+    fn test_lvm_pv_device_on_crypt_mapping() {
+        let tmpdir = tempfile::tempdir().expect("Failed to create temp dir");
+        create_fake_holder(
+            tmpdir.path(),
+            "nvme0n1p3",
+            "dm-0",
+            "CRYPT-LUKS2-0f3a9c2e7d6b4e8f9a1b2c3d4e5f6a7b-luks-pv",
+            "luks-pv",
+        );
+        let partition = Path::new("/dev/nvme0n1p3");
+
+        // The PV is the mapping, not the partition under it
+        let mapping = read_crypt_holder(tmpdir.path(), "nvme0n1p3").unwrap();
+        assert_eq!(
+            lvm_pv_device(partition, mapping.as_ref()),
+            std::path::PathBuf::from("/dev/mapper/luks-pv")
+        );
+
+        let mapping = read_crypt_holder(tmpdir.path(), "sda2").unwrap();
+        assert_eq!(lvm_pv_device(partition, mapping.as_ref()), partition);
+    }
+
+    #[test]
+    fn test_read_crypt_holder_skips_other_dm() {
+        let tmpdir = tempfile::tempdir().expect("Failed to create temp dir");
+        create_fake_holder(tmpdir.path(), "sda2", "dm-0", "LVM-abcdef", "vg-root");
+
+        assert!(read_crypt_holder(tmpdir.path(), "sda2").unwrap().is_none());
+        assert!(read_crypt_holder(tmpdir.path(), "sdz").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_luks_resize_args_key_file_and_slot() {
+        let key = LuksKey {
+            key_file: Some("/etc/keys/root.key".into()),
+            key_slot: Some(2),
+            ..Default::default()
+        };
+        let args = luks_resize_args(Path::new("/dev/mapper/root"), Some(&key), None);
+        assert_eq!(
+            args,
+            vec![
+                "resize",
+                "--key-file",
+                "/etc/keys/root.key",
+                "--key-slot",
+                "2",
+                "/dev/mapper/root"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_luks_resize_args_token() {
+        let key = LuksKey {
+            token_type: Some("systemd-tpm2".to_string()),
+            ..Default::default()
+        };
+        let args = luks_resize_args(Path::new("/dev/mapper/root"), Some(&key), None);
+        assert_eq!(
+            args,
+            vec![
+                "resize",
+                "--token-type",
+                "systemd-tpm2",
+                "--token-only",
+                "/dev/mapper/root"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_luks_resize_args_passphrase_not_on_command_line() {
+        let key = LuksKey {
+            passphrase_fd: Some(3),
+            ..Default::default()
+        };
+        let args = luks_resize_args(Path::new("/dev/mapper/root"), Some(&key), None);
+        assert_eq!(args, vec!["resize", "/dev/mapper/root"]);
+    }
+
+    #[test]
+    fn test_read_passphrase_fd() {
+        use std::io::Write;
+        use std::os::fd::AsRawFd;
+
+        let (reader, mut writer) = std::io::pipe().unwrap();
+        writer.write_all(b"secret\n").unwrap();
+        drop(writer);
+
+        let passphrase = read_passphrase_fd(reader.as_raw_fd()).unwrap();
+        assert_eq!(passphrase.as_bytes(), b"secret\n");
+        assert_eq!(format!("{:?}", passphrase), "Passphrase(..)");
+
+        // The pipe is drained, which is why it is only read once
+        assert!(read_passphrase_fd(reader.as_raw_fd()).is_err());
+    }
+
+    #[test]
+    fn test_luks_key_validate() {
+        let key_file = LuksKey {
+            key_file: Some("/etc/keys/root.key".into()),
+            ..Default::default()
+        };
+        assert!(key_file.validate().is_ok());
+
+        let both = LuksKey {
+            key_file: Some("/etc/keys/root.key".into()),
+            passphrase_fd: Some(3),
+            ..Default::default()
+        };
+        assert!(both.validate().is_err());
+
+        let slot_only = LuksKey {
+            key_slot: Some(1),
+            ..Default::default()
+        };
+        assert!(slot_only.validate().is_err());
+
+        assert!(LuksKey::default().validate().is_err());
+    }
+
+    #[test]
+    fn test_parse_cryptsetup_status_luks2_keyring() {
+        let output = "\
+/dev/mapper/root is active and is in use.
+  type:    LUKS2
+  cipher:  aes-xts-plain64
+  keysize: 512 bits
+  key location: keyring
+  device:  /dev/vda2
+";
+        let status = parse_cryptsetup_status(output).unwrap();
+        assert_eq!(status.crypt_type, "LUKS2");
+        assert_eq!(status.key_location.as_deref(), Some("keyring"));
+        assert!(!status.needs_key());
+    }
+
+    #[test]
+    fn test_parse_cryptsetup_status_luks2_dm_crypt() {
+        let output = "  type:    LUKS2\n  key location: dm-crypt\n";
+        let status = parse_cryptsetup_status(output).unwrap();
+        assert!(status.needs_key());
+    }
+
+    #[test]
+    fn test_parse_cryptsetup_status_luks1() {
+        let output = "  type:    LUKS1\n  cipher:  aes-xts-plain64\n";
+        let status = parse_cryptsetup_status(output).unwrap();
+        assert_eq!(status.key_location, None);
+        assert!(!status.needs_key());
+    }
+
+    #[test]
+    fn test_parse_cryptsetup_status_inactive() {
+        assert!(parse_cryptsetup_status("/dev/mapper/foo is inactive.\n").is_none());
+    }
+
+    #[test]
+    fn test_check_key_file() {
+        let file = tempfile::NamedTempFile::new().expect("Failed to create temp file");
+        assert!(check_key_file(file.path()).is_ok());
+        assert!(check_key_file(Path::new("/nonexistent_key_file_xyz")).is_err());
+
+        let tmpdir = tempfile::tempdir().expect("Failed to create temp dir");
+        assert!(check_key_file(tmpdir.path()).is_err());
+    }
+
     #[test]
     fn test_detect_xfs() {
         let (_file, path) = create_fake_device(&[(0, b"XFSB")]);
@@ -1125,6 +2699,138 @@ mod tests {
         assert_eq!(result, "xfs");
     }
 
+    #[test]
+    fn test_detect_md_superblocks() {
+        let magic = MD_SB_MAGIC.to_le_bytes();
+        // 1.2: 4 KiB into the member, in front of the filesystem
+        let ext_magic = 0xEF53u16.to_le_bytes();
+        let (_file, path) = create_fake_device(&[(4096, &magic), (1080, &ext_magic)]);
+        assert_eq!(detect_fs_magic(&path).unwrap(), "linux_raid_member");
+
+        // 1.0 and 0.90: near the end, with the filesystem visible at the start
+        for offset in [(0x20000 - 8192) & !4095, 0x20000 - 65536] {
+            let (file, path) = create_fake_device(&[(0, b"XFSB")]);
+            file.as_file().set_len(0x20000).unwrap();
+            file.as_file().write_all_at(&magic, offset).unwrap();
+            assert_eq!(detect_fs_magic(&path).unwrap(), "linux_raid_member");
+        }
+    }
+
+    #[test]
+    fn test_detect_other_signatures() {
+        let f2fs = F2FS_MAGIC.to_le_bytes();
+        let zfs = ZFS_UBERBLOCK_MAGIC.to_le_bytes();
+        let mut fat32 = vec![0u8; 512];
+        fat32[82..90].copy_from_slice(b"FAT32   ");
+        fat32[510..512].copy_from_slice(&[0x55, 0xAA]);
+        let mut lvm = [0u8; 32];
+        lvm[0..8].copy_from_slice(b"LABELONE");
+        lvm[24..32].copy_from_slice(b"LVM2 001");
+
+        let nilfs = NILFS_MAGIC.to_le_bytes();
+        let cases: [(u64, &[u8], &str); 12] = [
+            (512, &lvm[..], "LVM2_member"),
+            (4096 + 24, &BCACHE_MAGIC[..], "bcache"),
+            (4096 + 24, &BCACHEFS_MAGIC[..], "bcachefs"),
+            (4086, b"SWAPSPACE2", "swap"),
+            (65526, b"SWAPSPACE2", "swap"),
+            (1024, &f2fs[..], "f2fs"),
+            (1030, &nilfs[..], "nilfs2"),
+            (128 * 1024 + 5 * 1024, &zfs[..], "zfs_member"),
+            (3, b"NTFS    ", "ntfs"),
+            (3, b"EXFAT   ", "exfat"),
+            (0, &fat32[..], "vfat"),
+            (32769, b"CD001", "iso9660"),
+        ];
+
+        for (offset, data, expected) in cases {
+            let (file, path) = create_fake_device(&[(offset, data)]);
+            // Room for the ZFS label
+            file.as_file().set_len(256 * 1024).unwrap();
+            assert_eq!(detect_fs_magic(&path).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_detect_fat_needs_boot_signature() {
+        let mut boot = vec![0u8; 512];
+        boot[54..62].copy_from_slice(b"FAT16   ");
+        let (_file, path) = create_fake_device(&[(0, &boot)]);
+        assert!(detect_fs_magic(&path).is_err());
+    }
+
+    const TEST_UUID: [u8; 16] = [
+        0x3f, 0x1c, 0x4e, 0x2a, 0x9b, 0x01, 0x4c, 0x5d, 0x8e, 0x7f, 0x10, 0x22, 0x33, 0x44, 0x55,
+        0x66,
+    ];
+
+    #[test]
+    fn test_read_fs_identity_ext4() {
+        let magic = 0xEF53u16.to_le_bytes();
+        let incompat: [u8; 4] = 0x0040u32.to_le_bytes();
+        let (_file, path) = create_fake_device(&[
+            (1080, &magic),
+            (1124, &incompat),
+            (1128, &TEST_UUID),
+            (1144, b"rootfs"),
+        ]);
+
+        let identity = read_fs_identity(&path).unwrap();
+        assert_eq!(
+            identity,
+            FsIdentity {
+                fs_type: "ext4".to_string(),
+                uuid: Some("3f1c4e2a-9b01-4c5d-8e7f-102233445566".to_string()),
+                label: Some("rootfs".to_string()),
+            }
+        );
+    }
+
+    #[test]
+    fn test_read_fs_identity_xfs_and_btrfs() {
+        let (_xfs, xfs_path) =
+            create_fake_device(&[(0, b"XFSB"), (32, &TEST_UUID), (108, b"data")]);
+        let identity = read_fs_identity(&xfs_path).unwrap();
+        assert_eq!(identity.fs_type, "xfs");
+        assert_eq!(identity.label.as_deref(), Some("data"));
+        assert!(identity.uuid.is_some());
+
+        // The whole 4 KiB superblock is read, and needs a sector size
+        let sector_size = 4096u32.to_le_bytes();
+        let (_btrfs, btrfs_path) = create_fake_device(&[
+            (0x10020, &TEST_UUID),
+            (0x10040, b"_BHRfS_M"),
+            (0x10090, &sector_size),
+            (0x10FFF, &[0]),
+        ]);
+        let identity = read_fs_identity(&btrfs_path).unwrap();
+        assert_eq!(identity.fs_type, "btrfs");
+        assert_eq!(
+            identity.uuid.as_deref(),
+            Some("3f1c4e2a-9b01-4c5d-8e7f-102233445566")
+        );
+        // No label set
+        assert_eq!(identity.label, None);
+    }
+
+    #[test]
+    fn test_read_fs_identity_luks2() {
+        let uuid = b"0a1b2c3d-0000-4000-8000-123456789abc";
+        let (_file, path) = create_fake_device(&[
+            (0, b"LUKS\xBA\xBE\x00\x02"),
+            (24, b"cryptroot"),
+            (168, uuid),
+        ]);
+
+        let identity = read_fs_identity(&path).unwrap();
+        assert_eq!(identity.fs_type, "crypto_LUKS");
+        assert_eq!(
+            identity.uuid.as_deref(),
+            Some("0a1b2c3d-0000-4000-8000-123456789abc")
+        );
+        assert_eq!(identity.label.as_deref(), Some("cryptroot"));
+    }
+
     #[test]
     fn test_extract_sfdisk_field_start() {
         let line = "/dev/vda1 : start=     2048, size=    39999487, type=83";
@@ -1339,6 +3045,31 @@ unit: sectors
         assert_eq!(max, 41940991);
     }
 
+    #[test]
+    fn test_cap_max_end() {
+        let info = SfdiskDiskInfo {
+            sector_num: 41943040,
+            sector_size: 512,
+            pt_start: 2048,
+            pt_size: 2097152,
+            pt_end: 2048 + 2097152 - 1,
+            other_starts: vec![],
+            part_device: "/dev/sda1".to_string(),
+            is_gpt: true,
+        };
+        let max = compute_max_end(&info);
+
+        // 8 GiB cap: partition ends 8 GiB after its start
+        let capped = cap_max_end(&info, max, 8 << 30);
+        assert_eq!(capped, 2048 + 16777216 - 1);
+        // Unaligned cap is rounded down to 1 MiB
+        assert_eq!(cap_max_end(&info, max, (8 << 30) + 4096), capped);
+        // A cap past the end of the disk changes nothing
+        assert_eq!(cap_max_end(&info, max, 1 << 40), max);
+        // The partition never shrinks
+        assert_eq!(cap_max_end(&info, max, 1 << 20), info.pt_end);
+    }
+
     #[test]
     fn test_compute_max_end_with_next_partition() {
         // Partition 2 starts at 40001536, so partition 1 can't go past 40001535
@@ -1418,6 +3149,73 @@ unit: sectors
         assert!(new_dump.contains("label: gpt"));
     }
 
+    #[test]
+    fn test_parse_pvs_output_in_vg() {
+        let output = "  21470642176:1048576:4194304\n";
+        let info = parse_pvs_output(output).unwrap();
+        assert_eq!(
+            info,
+            PvInfo {
+                pv_size: 21470642176,
+                pe_start: 1048576,
+                extent_size: 4194304,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_pvs_output_orphan() {
+        let info = parse_pvs_output("  10737418240:1048576:\n").unwrap();
+        assert_eq!(info.pv_size, 10737418240);
+        assert_eq!(info.extent_size, 0);
+
+        let info = parse_pvs_output("  10737418240:1048576:0\n").unwrap();
+        assert_eq!(info.extent_size, 0);
+    }
+
+    #[test]
+    fn test_parse_pvs_output_invalid() {
+        assert!(parse_pvs_output("").is_none());
+        assert!(parse_pvs_output("  not:a:number\n").is_none());
+    }
+
+    #[test]
+    fn test_pv_unused_bytes_already_full() {
+        // 20 GiB device, 1 MiB pe_start, 5119 extents of 4 MiB
+        let info = PvInfo {
+            pv_size: 5119 * 4194304,
+            pe_start: 1048576,
+            extent_size: 4194304,
+        };
+        assert_eq!(pv_unused_bytes(20 * 1024 * 1024 * 1024, &info), 0);
+    }
+
+    #[test]
+    fn test_pv_unused_bytes_disk_grew() {
+        // PV created on a 20 GiB device that is now 30 GiB
+        let info = PvInfo {
+            pv_size: 5119 * 4194304,
+            pe_start: 1048576,
+            extent_size: 4194304,
+        };
+        let unused = pv_unused_bytes(30 * 1024 * 1024 * 1024, &info);
+        assert_eq!(unused, 10 * 1024 * 1024 * 1024 + 3 * 1024 * 1024);
+    }
+
+    #[test]
+    fn test_pv_unused_bytes_orphan() {
+        let info = PvInfo {
+            pv_size: 10 * 1024 * 1024 * 1024,
+            pe_start: 1048576,
+            extent_size: 0,
+        };
+        assert_eq!(pv_unused_bytes(10 * 1024 * 1024 * 1024, &info), 0);
+        assert_eq!(
+            pv_unused_bytes(12 * 1024 * 1024 * 1024, &info),
+            2 * 1024 * 1024 * 1024
+        );
+    }
+
     #[test]
     fn test_grow_partition_fudge_threshold() {
         // Verify the fudge constant matches growpart's default
@@ -0,0 +1,1424 @@
+use crate::resize::{self, ResizeError};
+use std::os::unix::fs::FileExt;
+use std::path::Path;
+
+/// Byte offset of the ext2/3/4 superblock.
+const EXT_SB_OFFSET: u64 = 1024;
+/// Byte offset of the primary Btrfs superblock.
+const BTRFS_SB_OFFSET: u64 = 0x10000;
+/// Byte offset of the first F2FS superblock.
+const F2FS_SB_OFFSET: u64 = 1024;
+/// Byte offset of the primary NILFS2 superblock.
+const NILFS_SB_OFFSET: u64 = 1024;
+/// Byte offset of the primary bcachefs superblock (sector 8).
+const BCACHEFS_SB_OFFSET: u64 = 4096;
+/// Size of the fixed part of a bcachefs superblock, up to its first field.
+const BCACHEFS_SB_FIXED: usize = 752;
+/// bcachefs superblock field types holding the member devices.
+const BCACHEFS_FIELD_MEMBERS_V1: u32 = 1;
+const BCACHEFS_FIELD_MEMBERS_V2: u32 = 11;
+/// Size of a member entry in a `members_v1` field.
+const BCACHEFS_MEMBER_V1_BYTES: usize = 56;
+
+/// Page sizes a swap area can be created with; its signature ends the first
+/// page.
+const SWAP_PAGE_SIZES: [usize; 4] = [4096, 8192, 16384, 65536];
+
+/// `s_state` bit of a NILFS2 superblock set when the filesystem hit an error.
+const NILFS_STATE_ERROR: u16 = 0x0002;
+
+/// Largest cluster counts of FAT12, FAT16 and FAT32. The FAT type follows
+/// from the cluster count, so a FAT12 or FAT16 filesystem cannot grow past
+/// its limit without being converted.
+const FAT12_MAX_CLUSTERS: u64 = 4084;
+const FAT16_MAX_CLUSTERS: u64 = 65524;
+const FAT32_MAX_CLUSTERS: u64 = 0x0FFF_FFF5;
+
+// ext2/3/4 feature flags used by the pre-flight checks and resize planning
+pub const EXT_COMPAT_HAS_JOURNAL: u64 = 0x0004;
+pub const EXT_COMPAT_RESIZE_INODE: u64 = 0x0010;
+pub const EXT_INCOMPAT_META_BG: u64 = 0x0010;
+pub const EXT_INCOMPAT_EXTENTS: u64 = 0x0040;
+pub const EXT_INCOMPAT_64BIT: u64 = 0x0080;
+pub const EXT_INCOMPAT_FLEX_BG: u64 = 0x0200;
+
+/// `s_state` bits of an ext2/3/4 superblock.
+const EXT_STATE_VALID: u16 = 0x0001;
+const EXT_STATE_ERROR: u16 = 0x0002;
+
+/// Btrfs superblock flag set when the filesystem hit an error.
+const BTRFS_SUPER_FLAG_ERROR: u64 = 1 << 2;
+
+/// XFS does not add an allocation group smaller than this many blocks
+/// (`XFS_MIN_AG_BLOCKS`).
+pub const XFS_MIN_AG_BLOCKS: u64 = 64;
+
+/// Filesystem family of a parsed superblock.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FsKind {
+    Ext2,
+    Ext3,
+    Ext4,
+    Xfs,
+    Btrfs,
+    F2fs,
+    Bcachefs,
+    Nilfs2,
+    Swap,
+    Ntfs,
+    Vfat,
+}
+
+impl FsKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FsKind::Ext2 => "ext2",
+            FsKind::Ext3 => "ext3",
+            FsKind::Ext4 => "ext4",
+            FsKind::Xfs => "xfs",
+            FsKind::Btrfs => "btrfs",
+            FsKind::F2fs => "f2fs",
+            FsKind::Bcachefs => "bcachefs",
+            FsKind::Nilfs2 => "nilfs2",
+            FsKind::Swap => "swap",
+            FsKind::Ntfs => "ntfs",
+            FsKind::Vfat => "vfat",
+        }
+    }
+}
+
+/// Feature flag words as stored in the superblock. XFS only has them from
+/// version 5 on; they are zero for older XFS filesystems.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FsFeatures {
+    pub compat: u64,
+    pub incompat: u64,
+    pub ro_compat: u64,
+}
+
+/// Fields only found in one filesystem family.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FsDetail {
+    Ext {
+        /// `s_blocks_per_group`
+        blocks_per_group: u32,
+        /// `s_reserved_gdt_blocks`: blocks reserved for online growth
+        reserved_gdt_blocks: u16,
+        /// `s_desc_size`: group descriptor size (64 with the 64bit feature)
+        desc_size: u16,
+        /// `s_errors`: behaviour on errors (1 continue, 2 remount-ro, 3 panic)
+        errors_behavior: u16,
+        /// `s_error_count`: number of errors recorded since the last fsck
+        error_count: u32,
+    },
+    Xfs {
+        /// `sb_agblocks`: blocks per allocation group
+        ag_blocks: u32,
+        /// `sb_agcount`: number of allocation groups
+        ag_count: u32,
+        /// `sb_logblocks` when the log is inside the data section, else 0
+        internal_log_blocks: u32,
+        /// Superblock version (4 or 5)
+        version: u16,
+    },
+    Btrfs {
+        /// Id of this device in the filesystem
+        devid: u64,
+        /// Number of devices in the filesystem
+        num_devices: u64,
+        /// Size of this device as recorded by Btrfs
+        dev_total_bytes: u64,
+    },
+    F2fs {
+        /// Blocks per zone (segment size times `segs_per_sec` times
+        /// `secs_per_zone`), the unit the main area is laid out in
+        zone_blocks: u64,
+    },
+    Bcachefs {
+        /// Index of this device in the filesystem
+        dev_idx: u8,
+        /// Number of devices in the filesystem
+        nr_devices: u8,
+        /// Size of this device as recorded by bcachefs
+        dev_bytes: u64,
+        /// Bucket size of this device in bytes
+        bucket_bytes: u64,
+    },
+    Nilfs2 {
+        /// `s_dev_size`: size of the device as recorded by NILFS2
+        dev_size: u64,
+        /// `s_blocks_per_segment`
+        blocks_per_segment: u32,
+    },
+    Swap {
+        /// `version` of the swap header (1 for every current mkswap)
+        version: u32,
+    },
+    Ntfs {
+        /// Cluster size in bytes
+        cluster_bytes: u64,
+    },
+    Vfat {
+        /// Cluster size in bytes
+        cluster_bytes: u64,
+        /// FAT entry width: 12, 16 or 32
+        fat_bits: u8,
+    },
+}
+
+/// Filesystem information parsed straight from the superblock.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FsInfo {
+    pub kind: FsKind,
+    pub uuid: Option<String>,
+    pub label: Option<String>,
+    /// Filesystem block size in bytes (sector size for Btrfs)
+    pub block_size: u64,
+    /// Filesystem size in blocks (all devices for Btrfs)
+    pub total_blocks: u64,
+    pub features: FsFeatures,
+    /// false if the filesystem was not cleanly unmounted or is being created
+    pub clean: bool,
+    /// true if the filesystem recorded errors
+    pub has_errors: bool,
+    pub detail: FsDetail,
+}
+
+impl FsInfo {
+    /// Returns the filesystem size in bytes.
+    pub fn size_bytes(&self) -> u64 {
+        self.total_blocks.saturating_mul(self.block_size)
+    }
+
+    /// Returns the largest size an ext2/3/4 filesystem can reach with an
+    /// online resize, or `None` if its features do not limit it.
+    ///
+    /// - Without `64bit`, block numbers are 32-bit, so the filesystem stops
+    ///   at 2^32 - 1 blocks (16 TiB with 4 KiB blocks).
+    /// - Without `resize_inode` and `meta_bg`, no group descriptor blocks
+    ///   can be added, so only the groups that fit in the current
+    ///   descriptor blocks can be used. With `resize_inode`, the kernel
+    ///   switches to `meta_bg` once the reserved blocks run out.
+    pub fn ext_online_limit(&self) -> Option<OnlineLimit> {
+        let FsDetail::Ext {
+            blocks_per_group,
+            desc_size,
+            ..
+        } = self.detail
+        else {
+            return None;
+        };
+
+        let is_64bit = self.features.incompat & EXT_INCOMPAT_64BIT != 0;
+        let mut limit = self.ext_offline_limit();
+
+        if self.features.compat & EXT_COMPAT_RESIZE_INODE == 0
+            && self.features.incompat & EXT_INCOMPAT_META_BG == 0
+            && blocks_per_group > 0
+        {
+            let desc_size = if is_64bit && desc_size >= 64 {
+                u64::from(desc_size)
+            } else {
+                32
+            };
+            let descs_per_block = self.block_size / desc_size;
+            let groups = self.total_blocks.div_ceil(u64::from(blocks_per_group));
+            let max_groups = groups.div_ceil(descs_per_block) * descs_per_block;
+            let max_bytes = max_groups * u64::from(blocks_per_group) * self.block_size;
+
+            if limit
+                .as_ref()
+                .is_none_or(|l: &OnlineLimit| max_bytes < l.max_bytes)
+            {
+                limit = Some(OnlineLimit {
+                    max_bytes,
+                    reason: "without resize_inode or meta_bg, no group descriptor blocks can be added",
+                });
+            }
+        }
+
+        limit
+    }
+
+    /// Returns the largest size an unmounted ext2/3/4 filesystem can reach
+    /// with resize2fs, or `None` if its features do not limit it. Offline,
+    /// resize2fs moves blocks out of the way to add group descriptor blocks,
+    /// so only the 32-bit block number limit is left.
+    pub fn ext_offline_limit(&self) -> Option<OnlineLimit> {
+        let FsDetail::Ext { .. } = self.detail else {
+            return None;
+        };
+
+        (self.features.incompat & EXT_INCOMPAT_64BIT == 0).then(|| OnlineLimit {
+            max_bytes: u64::from(u32::MAX) * self.block_size,
+            reason: "without the 64bit feature, block numbers are 32-bit",
+        })
+    }
+
+    /// Returns the largest size a FAT filesystem can be grown to without
+    /// changing its FAT type, or `None` for other filesystems.
+    ///
+    /// Only the clusters are counted, not the reserved sectors and FATs in
+    /// front of them, so the limit stays on the safe side.
+    pub fn fat_resize_limit(&self) -> Option<OnlineLimit> {
+        let FsDetail::Vfat {
+            cluster_bytes,
+            fat_bits,
+        } = self.detail
+        else {
+            return None;
+        };
+
+        let (max_clusters, reason) = match fat_bits {
+            12 => (FAT12_MAX_CLUSTERS, "FAT12 holds at most 4084 clusters"),
+            16 => (FAT16_MAX_CLUSTERS, "FAT16 holds at most 65524 clusters"),
+            _ => (FAT32_MAX_CLUSTERS, "FAT32 holds at most 268435445 clusters"),
+        };
+        // The sector count in the boot sector is 32-bit as well
+        let max_bytes = (max_clusters * cluster_bytes).min(u64::from(u32::MAX) * self.block_size);
+
+        Some(OnlineLimit { max_bytes, reason })
+    }
+
+    /// Projects the allocation group count of an XFS filesystem grown to
+    /// `new_bytes`, or `None` for other filesystems.
+    ///
+    /// The allocation group size is fixed when the filesystem is created, so
+    /// growth only adds groups. A last group smaller than
+    /// [`XFS_MIN_AG_BLOCKS`] is not added.
+    pub fn xfs_ag_projection(&self, new_bytes: u64) -> Option<AgProjection> {
+        let FsDetail::Xfs {
+            ag_blocks,
+            ag_count,
+            ..
+        } = self.detail
+        else {
+            return None;
+        };
+        if ag_blocks == 0 || self.block_size == 0 {
+            return None;
+        }
+
+        let new_blocks = (new_bytes / self.block_size).max(self.total_blocks);
+        let full_groups = new_blocks / u64::from(ag_blocks);
+        let partial = new_blocks % u64::from(ag_blocks) >= XFS_MIN_AG_BLOCKS;
+
+        Some(AgProjection {
+            ag_blocks,
+            current: ag_count,
+            projected: (full_groups + u64::from(partial)).max(u64::from(ag_count)),
+        })
+    }
+}
+
+/// Allocation group count of an XFS filesystem, now and after growing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AgProjection {
+    /// Blocks per allocation group, fixed at mkfs time
+    pub ag_blocks: u32,
+    pub current: u32,
+    pub projected: u64,
+}
+
+impl AgProjection {
+    /// Returns how many times more allocation groups there would be.
+    pub fn ratio(&self) -> f64 {
+        self.projected as f64 / f64::from(self.current.max(1))
+    }
+}
+
+/// Largest size a filesystem can be grown to, online or offline.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OnlineLimit {
+    pub max_bytes: u64,
+    /// Which missing feature sets the limit
+    pub reason: &'static str,
+}
+
+/// Reads and parses the superblock of an ext2/3/4, XFS, Btrfs, F2FS,
+/// bcachefs, NILFS2, NTFS or FAT filesystem, or the header of a swap area.
+pub fn read_fs_info(device: &Path) -> Result<FsInfo, ResizeError> {
+    let fs_type = resize::get_fs_type(device)?;
+    let file = std::fs::File::open(device)
+        .map_err(|e| ResizeError::DeviceNotFound(format!("{}: {}", device.display(), e)))?;
+
+    let read_at = |offset: u64, len: usize| {
+        let mut buf = vec![0u8; len];
+        file.read_exact_at(&mut buf, offset)
+            .map(|_| buf)
+            .map_err(|e| {
+                ResizeError::ResizeFs(format!(
+                    "Failed to read the {} superblock of {}: {}",
+                    fs_type,
+                    device.display(),
+                    e
+                ))
+            })
+    };
+
+    let info = match fs_type.as_str() {
+        "ext2" | "ext3" | "ext4" => parse_ext_superblock(&read_at(EXT_SB_OFFSET, 1024)?),
+        "xfs" => parse_xfs_superblock(&read_at(0, 512)?),
+        "btrfs" => parse_btrfs_superblock(&read_at(BTRFS_SB_OFFSET, 4096)?),
+        "f2fs" => parse_f2fs_superblock(&read_at(F2FS_SB_OFFSET, 3072)?),
+        "nilfs2" => parse_nilfs2_superblock(&read_at(NILFS_SB_OFFSET, 1024)?),
+        "ntfs" => parse_ntfs_boot_sector(&read_at(0, 512)?),
+        "vfat" => parse_fat_boot_sector(&read_at(0, 512)?),
+        "swap" => {
+            let page_size = SWAP_PAGE_SIZES
+                .into_iter()
+                .find(|&size| read_at(size as u64 - 10, 10).is_ok_and(|sig| sig == b"SWAPSPACE2"));
+            match page_size {
+                Some(size) => parse_swap_header(&read_at(0, size)?),
+                None => None,
+            }
+        }
+        "bcachefs" => {
+            // bcachefs writes its superblock around the page cache, so a
+            // buffered read can return the copy from before a resize
+            let read_sb = |len: usize| {
+                read_direct(device, BCACHEFS_SB_OFFSET, len)
+                    .or_else(|_| read_at(BCACHEFS_SB_OFFSET, len))
+            };
+            // The fields after the fixed part are u64s words long
+            let fixed = read_sb(BCACHEFS_SB_FIXED)?;
+            let u64s = le_u32(&fixed, 124).unwrap_or(0) as usize;
+            parse_bcachefs_superblock(&read_sb(BCACHEFS_SB_FIXED + u64s.min(1 << 17) * 8)?)
+        }
+        other => {
+            return Err(ResizeError::ResizeFs(format!(
+                "{} holds {}, which has no superblock parser",
+                device.display(),
+                other
+            )));
+        }
+    };
+
+    info.ok_or_else(|| {
+        ResizeError::ResizeFs(format!(
+            "Invalid {} superblock on {}",
+            fs_type,
+            device.display()
+        ))
+    })
+}
+
+/// Alignment of direct I/O buffers, offsets and lengths, enough for any
+/// logical block size.
+const DIRECT_IO_ALIGN: usize = 4096;
+
+/// Reads `len` bytes at `offset` with `O_DIRECT`, bypassing the page
+/// cache. `offset` must be aligned to [`DIRECT_IO_ALIGN`]; the read is
+/// done in aligned blocks, so it fails if the last one runs past the end of
+/// the device.
+fn read_direct(device: &Path, offset: u64, len: usize) -> std::io::Result<Vec<u8>> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    #[repr(C, align(4096))]
+    struct Block([u8; DIRECT_IO_ALIGN]);
+
+    let file = std::fs::OpenOptions::new()
+        .read(true)
+        .custom_flags(nix::fcntl::OFlag::O_DIRECT.bits())
+        .open(device)?;
+
+    let mut block = Box::new(Block([0; DIRECT_IO_ALIGN]));
+    let mut buf = Vec::with_capacity(len.next_multiple_of(DIRECT_IO_ALIGN));
+    while buf.len() < len {
+        file.read_exact_at(&mut block.0, offset + buf.len() as u64)?;
+        buf.extend_from_slice(&block.0);
+    }
+    buf.truncate(len);
+    Ok(buf)
+}
+
+/// Parses the 1024-byte ext2/3/4 superblock (read at offset 1024).
+fn parse_ext_superblock(sb: &[u8]) -> Option<FsInfo> {
+    if le_u16(sb, 0x38)? != 0xEF53 {
+        return None;
+    }
+
+    let features = FsFeatures {
+        compat: u64::from(le_u32(sb, 0x5C)?),
+        incompat: u64::from(le_u32(sb, 0x60)?),
+        ro_compat: u64::from(le_u32(sb, 0x64)?),
+    };
+
+    let mut total_blocks = u64::from(le_u32(sb, 0x04)?);
+    if features.incompat & EXT_INCOMPAT_64BIT != 0 {
+        total_blocks |= u64::from(le_u32(sb, 0x150)?) << 32;
+    }
+
+    let log_block_size = le_u32(sb, 0x18)?;
+    if log_block_size > 16 {
+        return None;
+    }
+
+    let kind = if features.incompat & EXT_INCOMPAT_EXTENTS != 0 {
+        FsKind::Ext4
+    } else if features.compat & EXT_COMPAT_HAS_JOURNAL != 0 {
+        FsKind::Ext3
+    } else {
+        FsKind::Ext2
+    };
+
+    let state = le_u16(sb, 0x3A)?;
+    let error_count = le_u32(sb, 0x194)?;
+
+    Some(FsInfo {
+        kind,
+        uuid: format_uuid(sb.get(0x68..0x78)?),
+        label: nul_terminated(sb.get(0x78..0x88)?),
+        block_size: 1024 << log_block_size,
+        total_blocks,
+        features,
+        clean: state & EXT_STATE_VALID != 0,
+        has_errors: state & EXT_STATE_ERROR != 0 || error_count > 0,
+        detail: FsDetail::Ext {
+            blocks_per_group: le_u32(sb, 0x20)?,
+            reserved_gdt_blocks: le_u16(sb, 0xCE)?,
+            desc_size: le_u16(sb, 0xFE)?,
+            errors_behavior: le_u16(sb, 0x3C)?,
+            error_count,
+        },
+    })
+}
+
+/// Parses the XFS superblock (the first sector of the device). All fields
+/// are big-endian.
+fn parse_xfs_superblock(sb: &[u8]) -> Option<FsInfo> {
+    if sb.get(0..4)? != b"XFSB" {
+        return None;
+    }
+
+    let version = be_u16(sb, 100)? & 0x000F;
+    let features = if version >= 5 {
+        FsFeatures {
+            compat: u64::from(be_u32(sb, 208)?),
+            ro_compat: u64::from(be_u32(sb, 212)?),
+            incompat: u64::from(be_u32(sb, 216)?),
+        }
+    } else {
+        FsFeatures::default()
+    };
+
+    Some(FsInfo {
+        kind: FsKind::Xfs,
+        uuid: format_uuid(sb.get(32..48)?),
+        label: nul_terminated(sb.get(108..120)?),
+        block_size: u64::from(be_u32(sb, 4)?),
+        total_blocks: be_u64(sb, 8)?,
+        features,
+        // sb_inprogress is set while mkfs is still running
+        clean: *sb.get(126)? == 0,
+        has_errors: false,
+        detail: FsDetail::Xfs {
+            ag_blocks: be_u32(sb, 84)?,
+            ag_count: be_u32(sb, 88)?,
+            // sb_logstart is 0 for an external log device
+            internal_log_blocks: if be_u64(sb, 48)? != 0 {
+                be_u32(sb, 96)?
+            } else {
+                0
+            },
+            version,
+        },
+    })
+}
+
+/// Parses the primary Btrfs superblock (read at offset 0x10000).
+fn parse_btrfs_superblock(sb: &[u8]) -> Option<FsInfo> {
+    if sb.get(0x40..0x48)? != b"_BHRfS_M" {
+        return None;
+    }
+
+    let sector_size = u64::from(le_u32(sb, 0x90)?);
+    if sector_size == 0 {
+        return None;
+    }
+    let total_bytes = le_u64(sb, 0x70)?;
+    let flags = le_u64(sb, 0x38)?;
+
+    Some(FsInfo {
+        kind: FsKind::Btrfs,
+        uuid: format_uuid(sb.get(0x20..0x30)?),
+        label: nul_terminated(sb.get(0x12B..0x22B)?),
+        block_size: sector_size,
+        total_blocks: total_bytes / sector_size,
+        features: FsFeatures {
+            compat: le_u64(sb, 0xAC)?,
+            ro_compat: le_u64(sb, 0xB4)?,
+            incompat: le_u64(sb, 0xBC)?,
+        },
+        clean: true,
+        has_errors: flags & BTRFS_SUPER_FLAG_ERROR != 0,
+        detail: FsDetail::Btrfs {
+            // dev_item starts at 0xC9: devid, then total_bytes
+            devid: le_u64(sb, 0xC9)?,
+            num_devices: le_u64(sb, 0x88)?,
+            dev_total_bytes: le_u64(sb, 0xD1)?,
+        },
+    })
+}
+
+/// Parses the 3072-byte F2FS superblock (read at offset 1024).
+fn parse_f2fs_superblock(sb: &[u8]) -> Option<FsInfo> {
+    if le_u32(sb, 0)? != resize::F2FS_MAGIC {
+        return None;
+    }
+
+    let log_blocksize = le_u32(sb, 16)?;
+    let log_blocks_per_seg = le_u32(sb, 20)?;
+    if log_blocksize > 16 || log_blocks_per_seg > 16 {
+        return None;
+    }
+
+    // volume_name is 512 UTF-16LE code units
+    let name: Vec<u16> = sb
+        .get(124..1148)?
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .take_while(|&c| c != 0)
+        .collect();
+    let label = String::from_utf16_lossy(&name).trim().to_string();
+
+    Some(FsInfo {
+        kind: FsKind::F2fs,
+        uuid: format_uuid(sb.get(108..124)?),
+        label: (!label.is_empty()).then_some(label),
+        block_size: 1 << log_blocksize,
+        total_blocks: le_u64(sb, 36)?,
+        features: FsFeatures {
+            incompat: u64::from(le_u32(sb, 2180)?),
+            ..FsFeatures::default()
+        },
+        clean: true,
+        // s_errors: one bit per kind of error the kernel ran into
+        has_errors: sb.get(2794..2810)?.iter().any(|&b| b != 0),
+        detail: FsDetail::F2fs {
+            zone_blocks: (1u64 << log_blocks_per_seg)
+                * u64::from(le_u32(sb, 24)?)
+                * u64::from(le_u32(sb, 28)?),
+        },
+    })
+}
+
+/// Parses the first page of a swap area, whose size is the page size the
+/// area was created with. The header fields are in native byte order,
+/// little-endian on every architecture hot-resize runs on.
+fn parse_swap_header(page: &[u8]) -> Option<FsInfo> {
+    let page_size = page.len();
+    if !SWAP_PAGE_SIZES.contains(&page_size) || page.get(page_size - 10..)? != b"SWAPSPACE2" {
+        return None;
+    }
+
+    // last_page is the index of the last usable page
+    let last_page = le_u32(page, 1028)?;
+
+    Some(FsInfo {
+        kind: FsKind::Swap,
+        uuid: format_uuid(page.get(1036..1052)?),
+        label: nul_terminated(page.get(1052..1068)?),
+        block_size: page_size as u64,
+        total_blocks: u64::from(last_page) + 1,
+        features: FsFeatures::default(),
+        clean: true,
+        has_errors: false,
+        detail: FsDetail::Swap {
+            version: le_u32(page, 1024)?,
+        },
+    })
+}
+
+/// Parses the NTFS boot sector. The volume serial number is reported as the
+/// UUID, in the 16 hex digit form blkid uses; the label is kept in the
+/// `$Volume` file and not read.
+fn parse_ntfs_boot_sector(boot: &[u8]) -> Option<FsInfo> {
+    if boot.get(3..11)? != b"NTFS    " {
+        return None;
+    }
+
+    let sector_size = u64::from(le_u16(boot, 11)?);
+    let sectors_per_cluster = u64::from(*boot.get(13)?);
+    if sector_size == 0 || sectors_per_cluster == 0 {
+        return None;
+    }
+    let serial = le_u64(boot, 72)?;
+
+    Some(FsInfo {
+        kind: FsKind::Ntfs,
+        uuid: (serial != 0).then(|| format!("{:016X}", serial)),
+        label: None,
+        block_size: sector_size,
+        total_blocks: le_u64(boot, 40)?,
+        features: FsFeatures::default(),
+        clean: true,
+        has_errors: false,
+        detail: FsDetail::Ntfs {
+            cluster_bytes: sector_size * sectors_per_cluster,
+        },
+    })
+}
+
+/// Parses a FAT12/16/32 boot sector. The volume serial number is reported
+/// as the UUID in the `XXXX-XXXX` form blkid uses.
+fn parse_fat_boot_sector(boot: &[u8]) -> Option<FsInfo> {
+    let sector_size = u64::from(le_u16(boot, 11)?);
+    let sectors_per_cluster = u64::from(*boot.get(13)?);
+    if sector_size == 0 || sectors_per_cluster == 0 {
+        return None;
+    }
+
+    let total_sectors = match le_u16(boot, 19)? {
+        0 => u64::from(le_u32(boot, 32)?),
+        sectors => u64::from(sectors),
+    };
+
+    // FAT32 has no 16-bit FAT size, and its extended boot record is larger
+    let (fat_bits, ebr) = if le_u16(boot, 22)? == 0 {
+        (32, 64)
+    } else if boot.get(54..59)? == b"FAT12" {
+        (12, 36)
+    } else {
+        (16, 36)
+    };
+    let serial = le_u32(boot, ebr + 3)?;
+    let label = nul_terminated(boot.get(ebr + 7..ebr + 18)?).filter(|l| l != "NO NAME");
+
+    Some(FsInfo {
+        kind: FsKind::Vfat,
+        uuid: (serial != 0).then(|| format!("{:04X}-{:04X}", serial >> 16, serial & 0xFFFF)),
+        label,
+        block_size: sector_size,
+        total_blocks: total_sectors,
+        features: FsFeatures::default(),
+        clean: true,
+        has_errors: false,
+        detail: FsDetail::Vfat {
+            cluster_bytes: sector_size * sectors_per_cluster,
+            fat_bits,
+        },
+    })
+}
+
+/// Parses the NILFS2 superblock (read at offset 1024).
+fn parse_nilfs2_superblock(sb: &[u8]) -> Option<FsInfo> {
+    if le_u16(sb, 6)? != resize::NILFS_MAGIC {
+        return None;
+    }
+
+    let log_block_size = le_u32(sb, 20)?;
+    if log_block_size > 16 {
+        return None;
+    }
+    let block_size = 1024u64 << log_block_size;
+    let dev_size = le_u64(sb, 32)?;
+    let state = le_u16(sb, 116)?;
+
+    Some(FsInfo {
+        kind: FsKind::Nilfs2,
+        uuid: format_uuid(sb.get(152..168)?),
+        label: nul_terminated(sb.get(168..248)?),
+        block_size,
+        total_blocks: dev_size / block_size,
+        features: FsFeatures {
+            compat: le_u64(sb, 256)?,
+            ro_compat: le_u64(sb, 264)?,
+            incompat: le_u64(sb, 272)?,
+        },
+        // The valid bit is cleared while mounted, so it says nothing here
+        clean: true,
+        has_errors: state & NILFS_STATE_ERROR != 0,
+        detail: FsDetail::Nilfs2 {
+            dev_size,
+            blocks_per_segment: le_u32(sb, 48)?,
+        },
+    })
+}
+
+/// Parses a bcachefs superblock (read at offset 4096) with its fields.
+///
+/// Device sizes are only found in the `members` field, as a number of
+/// buckets and a bucket size in 512-byte sectors for each device.
+fn parse_bcachefs_superblock(sb: &[u8]) -> Option<FsInfo> {
+    if sb.get(24..40)? != resize::BCACHEFS_MAGIC {
+        return None;
+    }
+
+    let block_size = u64::from(le_u16(sb, 120)?) * 512;
+    if block_size == 0 {
+        return None;
+    }
+    let dev_idx = *sb.get(122)?;
+    let nr_devices = *sb.get(123)?;
+    let members = bcachefs_members(sb, nr_devices)?;
+    let (dev_bytes, bucket_bytes) = *members.get(usize::from(dev_idx))?;
+    let total_bytes: u64 = members.iter().map(|(bytes, _)| bytes).sum();
+
+    Some(FsInfo {
+        kind: FsKind::Bcachefs,
+        uuid: format_uuid(sb.get(56..72)?),
+        label: nul_terminated(sb.get(72..104)?),
+        block_size,
+        total_blocks: total_bytes / block_size,
+        features: FsFeatures {
+            incompat: le_u64(sb, 208)?,
+            compat: le_u64(sb, 224)?,
+            ..FsFeatures::default()
+        },
+        clean: true,
+        has_errors: false,
+        detail: FsDetail::Bcachefs {
+            dev_idx,
+            nr_devices,
+            dev_bytes,
+            bucket_bytes,
+        },
+    })
+}
+
+/// Returns the size and bucket size of each member device, in bytes, from
+/// the `members_v2` field, or `members_v1` on older filesystems.
+fn bcachefs_members(sb: &[u8], nr_devices: u8) -> Option<Vec<(u64, u64)>> {
+    let end = BCACHEFS_SB_FIXED + le_u32(sb, 124)? as usize * 8;
+    let mut pos = BCACHEFS_SB_FIXED;
+    let mut v1 = None;
+    let mut v2 = None;
+
+    // Each field starts with its length in u64s (header included) and type
+    while pos + 8 <= end {
+        let field_u64s = le_u32(sb, pos)? as usize;
+        if field_u64s == 0 {
+            break;
+        }
+        match le_u32(sb, pos + 4)? {
+            BCACHEFS_FIELD_MEMBERS_V1 => v1 = Some((pos + 8, BCACHEFS_MEMBER_V1_BYTES)),
+            BCACHEFS_FIELD_MEMBERS_V2 => {
+                v2 = Some((pos + 16, usize::from(le_u16(sb, pos + 8)?)));
+            }
+            _ => {}
+        }
+        pos += field_u64s * 8;
+    }
+
+    let (start, member_bytes) = v2.or(v1)?;
+    (0..usize::from(nr_devices))
+        .map(|i| {
+            let member = start + i * member_bytes;
+            let nbuckets = le_u64(sb, member + 16)?;
+            let bucket_bytes = u64::from(le_u16(sb, member + 26)?) * 512;
+            Some((nbuckets.saturating_mul(bucket_bytes), bucket_bytes))
+        })
+        .collect()
+}
+
+/// Formats a binary UUID as `xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx`.
+/// An all-zero UUID means "not set" and yields `None`.
+pub(crate) fn format_uuid(bytes: &[u8]) -> Option<String> {
+    if bytes.len() != 16 || bytes.iter().all(|&b| b == 0) {
+        return None;
+    }
+
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    Some(format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    ))
+}
+
+/// Returns the text before the first NUL byte, or `None` if it is empty.
+pub(crate) fn nul_terminated(bytes: &[u8]) -> Option<String> {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    let text = String::from_utf8_lossy(&bytes[..end]).trim().to_string();
+    (!text.is_empty()).then_some(text)
+}
+
+fn le_u16(buf: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        buf.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn le_u32(buf: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        buf.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn le_u64(buf: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(
+        buf.get(offset..offset + 8)?.try_into().ok()?,
+    ))
+}
+
+fn be_u16(buf: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(
+        buf.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn be_u32(buf: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(
+        buf.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn be_u64(buf: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_be_bytes(
+        buf.get(offset..offset + 8)?.try_into().ok()?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_UUID: [u8; 16] = [
+        0x3f, 0x1c, 0x4e, 0x2a, 0x9b, 0x01, 0x4c, 0x5d, 0x8e, 0x7f, 0x10, 0x22, 0x33, 0x44, 0x55,
+        0x66,
+    ];
+    const TEST_UUID_STR: &str = "3f1c4e2a-9b01-4c5d-8e7f-102233445566";
+
+    fn put(buf: &mut [u8], offset: usize, data: &[u8]) {
+        buf[offset..offset + data.len()].copy_from_slice(data);
+    }
+
+    /// Builds an ext4 superblock: 4 KiB blocks, 64bit, 2^32 + 1000 blocks.
+    fn ext4_superblock() -> Vec<u8> {
+        let mut sb = vec![0u8; 1024];
+        put(&mut sb, 0x04, &1000u32.to_le_bytes());
+        put(&mut sb, 0x18, &2u32.to_le_bytes());
+        put(&mut sb, 0x20, &32768u32.to_le_bytes());
+        put(&mut sb, 0x38, &0xEF53u16.to_le_bytes());
+        put(&mut sb, 0x3A, &EXT_STATE_VALID.to_le_bytes());
+        put(&mut sb, 0x3C, &2u16.to_le_bytes());
+        put(
+            &mut sb,
+            0x5C,
+            &((EXT_COMPAT_HAS_JOURNAL | EXT_COMPAT_RESIZE_INODE) as u32).to_le_bytes(),
+        );
+        put(
+            &mut sb,
+            0x60,
+            &((EXT_INCOMPAT_EXTENTS | EXT_INCOMPAT_64BIT | EXT_INCOMPAT_FLEX_BG) as u32)
+                .to_le_bytes(),
+        );
+        put(&mut sb, 0x68, &TEST_UUID);
+        put(&mut sb, 0x78, b"rootfs");
+        put(&mut sb, 0xCE, &1024u16.to_le_bytes());
+        put(&mut sb, 0xFE, &64u16.to_le_bytes());
+        put(&mut sb, 0x150, &1u32.to_le_bytes());
+        sb
+    }
+
+    #[test]
+    fn test_parse_ext4_superblock() {
+        let info = parse_ext_superblock(&ext4_superblock()).unwrap();
+
+        assert_eq!(info.kind, FsKind::Ext4);
+        assert_eq!(info.uuid.as_deref(), Some(TEST_UUID_STR));
+        assert_eq!(info.label.as_deref(), Some("rootfs"));
+        assert_eq!(info.block_size, 4096);
+        assert_eq!(info.total_blocks, (1u64 << 32) + 1000);
+        assert_eq!(info.size_bytes(), ((1u64 << 32) + 1000) * 4096);
+        assert_ne!(info.features.incompat & EXT_INCOMPAT_64BIT, 0);
+        assert!(info.clean);
+        assert!(!info.has_errors);
+        assert_eq!(
+            info.detail,
+            FsDetail::Ext {
+                blocks_per_group: 32768,
+                reserved_gdt_blocks: 1024,
+                desc_size: 64,
+                errors_behavior: 2,
+                error_count: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_ext_superblock_errors() {
+        let mut sb = ext4_superblock();
+        put(&mut sb, 0x194, &3u32.to_le_bytes());
+        let info = parse_ext_superblock(&sb).unwrap();
+        assert!(info.has_errors);
+
+        put(&mut sb, 0x3A, &EXT_STATE_ERROR.to_le_bytes());
+        put(&mut sb, 0x194, &0u32.to_le_bytes());
+        let info = parse_ext_superblock(&sb).unwrap();
+        assert!(!info.clean);
+        assert!(info.has_errors);
+    }
+
+    #[test]
+    fn test_ext_online_limit() {
+        let mut sb = ext4_superblock();
+        assert_eq!(parse_ext_superblock(&sb).unwrap().ext_online_limit(), None);
+
+        // 32-bit block numbers, with reserved GDT blocks to grow into
+        put(&mut sb, 0x60, &(EXT_INCOMPAT_EXTENTS as u32).to_le_bytes());
+        let limit = parse_ext_superblock(&sb)
+            .unwrap()
+            .ext_online_limit()
+            .unwrap();
+        assert_eq!(limit.max_bytes, u64::from(u32::MAX) * 4096);
+        assert!(limit.reason.contains("64bit"));
+
+        // No resize_inode either: one 4 KiB block of 32-byte descriptors
+        // covers 128 groups of 128 MiB
+        put(
+            &mut sb,
+            0x5C,
+            &(EXT_COMPAT_HAS_JOURNAL as u32).to_le_bytes(),
+        );
+        let limit = parse_ext_superblock(&sb)
+            .unwrap()
+            .ext_online_limit()
+            .unwrap();
+        assert_eq!(limit.max_bytes, 128 * 32768 * 4096);
+        assert!(limit.reason.contains("resize_inode"));
+        // resize2fs adds descriptor blocks offline, only 32 bits remain
+        let limit = parse_ext_superblock(&sb)
+            .unwrap()
+            .ext_offline_limit()
+            .unwrap();
+        assert_eq!(limit.max_bytes, u64::from(u32::MAX) * 4096);
+
+        // meta_bg adds descriptor blocks as needed
+        put(
+            &mut sb,
+            0x60,
+            &((EXT_INCOMPAT_EXTENTS | EXT_INCOMPAT_META_BG) as u32).to_le_bytes(),
+        );
+        let limit = parse_ext_superblock(&sb)
+            .unwrap()
+            .ext_online_limit()
+            .unwrap();
+        assert_eq!(limit.max_bytes, u64::from(u32::MAX) * 4096);
+    }
+
+    #[test]
+    fn test_parse_ext2_superblock_32bit() {
+        let mut sb = ext4_superblock();
+        put(&mut sb, 0x5C, &0u32.to_le_bytes());
+        put(&mut sb, 0x60, &0u32.to_le_bytes());
+        let info = parse_ext_superblock(&sb).unwrap();
+
+        assert_eq!(info.kind, FsKind::Ext2);
+        // Without the 64bit feature the high word is ignored
+        assert_eq!(info.total_blocks, 1000);
+    }
+
+    #[test]
+    fn test_parse_ext_superblock_bad_magic() {
+        let mut sb = ext4_superblock();
+        put(&mut sb, 0x38, &0u16.to_le_bytes());
+        assert!(parse_ext_superblock(&sb).is_none());
+        assert!(parse_ext_superblock(&[0u8; 16]).is_none());
+    }
+
+    #[test]
+    fn test_parse_xfs_superblock() {
+        let mut sb = vec![0u8; 512];
+        put(&mut sb, 0, b"XFSB");
+        put(&mut sb, 4, &4096u32.to_be_bytes());
+        put(&mut sb, 8, &2_621_440u64.to_be_bytes());
+        put(&mut sb, 32, &TEST_UUID);
+        put(&mut sb, 48, &1_310_724u64.to_be_bytes());
+        put(&mut sb, 84, &655_360u32.to_be_bytes());
+        put(&mut sb, 88, &4u32.to_be_bytes());
+        put(&mut sb, 96, &2560u32.to_be_bytes());
+        put(&mut sb, 100, &0xB4A5u16.to_be_bytes());
+        put(&mut sb, 108, b"data");
+        put(&mut sb, 216, &0x1u32.to_be_bytes());
+
+        let info = parse_xfs_superblock(&sb).unwrap();
+        assert_eq!(info.kind, FsKind::Xfs);
+        assert_eq!(info.uuid.as_deref(), Some(TEST_UUID_STR));
+        assert_eq!(info.label.as_deref(), Some("data"));
+        assert_eq!(info.block_size, 4096);
+        assert_eq!(info.total_blocks, 2_621_440);
+        assert_eq!(info.features.incompat, 1);
+        assert!(info.clean);
+        assert_eq!(
+            info.detail,
+            FsDetail::Xfs {
+                ag_blocks: 655_360,
+                ag_count: 4,
+                internal_log_blocks: 2560,
+                version: 5,
+            }
+        );
+    }
+
+    #[test]
+    fn test_xfs_ag_projection() {
+        const GIB: u64 = 1 << 30;
+        let mut sb = vec![0u8; 512];
+        put(&mut sb, 0, b"XFSB");
+        put(&mut sb, 4, &4096u32.to_be_bytes());
+        put(&mut sb, 8, &2_621_440u64.to_be_bytes());
+        put(&mut sb, 84, &655_360u32.to_be_bytes());
+        put(&mut sb, 88, &4u32.to_be_bytes());
+        put(&mut sb, 100, &0xB4A5u16.to_be_bytes());
+        let info = parse_xfs_superblock(&sb).unwrap();
+
+        // 10 GiB in four 2.5 GiB groups
+        let same = info.xfs_ag_projection(10 * GIB).unwrap();
+        assert_eq!((same.current, same.projected), (4, 4));
+        assert_eq!(same.ratio(), 1.0);
+
+        // A partial group too small to add
+        let tiny = info.xfs_ag_projection(10 * GIB + 32 * 4096).unwrap();
+        assert_eq!(tiny.projected, 4);
+        assert_eq!(info.xfs_ag_projection(11 * GIB).unwrap().projected, 5);
+
+        let huge = info.xfs_ag_projection(4096 * GIB).unwrap();
+        assert_eq!(huge.projected, 1639);
+        assert!(huge.ratio() > 400.0);
+
+        assert!(
+            parse_ext_superblock(&ext4_superblock())
+                .unwrap()
+                .xfs_ag_projection(4096 * GIB)
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_parse_xfs_v4_has_no_features() {
+        let mut sb = vec![0u8; 512];
+        put(&mut sb, 0, b"XFSB");
+        put(&mut sb, 4, &4096u32.to_be_bytes());
+        put(&mut sb, 100, &0x3494u16.to_be_bytes());
+        put(&mut sb, 96, &2560u32.to_be_bytes());
+        put(&mut sb, 126, &[1]);
+        put(&mut sb, 216, &0xFFu32.to_be_bytes());
+
+        let info = parse_xfs_superblock(&sb).unwrap();
+        assert_eq!(info.features, FsFeatures::default());
+        assert!(!info.clean);
+        // No sb_logstart: the log is on an external device
+        assert!(matches!(
+            info.detail,
+            FsDetail::Xfs {
+                internal_log_blocks: 0,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_parse_btrfs_superblock() {
+        let mut sb = vec![0u8; 4096];
+        put(&mut sb, 0x20, &TEST_UUID);
+        put(&mut sb, 0x40, b"_BHRfS_M");
+        put(&mut sb, 0x70, &(20u64 << 30).to_le_bytes());
+        put(&mut sb, 0x88, &2u64.to_le_bytes());
+        put(&mut sb, 0x90, &4096u32.to_le_bytes());
+        put(&mut sb, 0xBC, &0x161u64.to_le_bytes());
+        put(&mut sb, 0xC9, &2u64.to_le_bytes());
+        put(&mut sb, 0xD1, &(10u64 << 30).to_le_bytes());
+        put(&mut sb, 0x12B, b"pool");
+
+        let info = parse_btrfs_superblock(&sb).unwrap();
+        assert_eq!(info.kind, FsKind::Btrfs);
+        assert_eq!(info.uuid.as_deref(), Some(TEST_UUID_STR));
+        assert_eq!(info.label.as_deref(), Some("pool"));
+        assert_eq!(info.size_bytes(), 20 << 30);
+        assert_eq!(info.features.incompat, 0x161);
+        assert!(!info.has_errors);
+        assert_eq!(
+            info.detail,
+            FsDetail::Btrfs {
+                devid: 2,
+                num_devices: 2,
+                dev_total_bytes: 10 << 30,
+            }
+        );
+
+        put(&mut sb, 0x38, &BTRFS_SUPER_FLAG_ERROR.to_le_bytes());
+        assert!(parse_btrfs_superblock(&sb).unwrap().has_errors);
+    }
+
+    #[test]
+    fn test_parse_f2fs_superblock() {
+        let mut sb = vec![0u8; 3072];
+        put(&mut sb, 0, &resize::F2FS_MAGIC.to_le_bytes());
+        put(&mut sb, 16, &12u32.to_le_bytes());
+        put(&mut sb, 20, &9u32.to_le_bytes());
+        put(&mut sb, 24, &1u32.to_le_bytes());
+        put(&mut sb, 28, &1u32.to_le_bytes());
+        put(&mut sb, 36, &262_144u64.to_le_bytes());
+        put(&mut sb, 108, &TEST_UUID);
+        for (i, c) in "emmc".encode_utf16().enumerate() {
+            put(&mut sb, 124 + 2 * i, &c.to_le_bytes());
+        }
+
+        let info = parse_f2fs_superblock(&sb).unwrap();
+        assert_eq!(info.kind, FsKind::F2fs);
+        assert_eq!(info.uuid.as_deref(), Some(TEST_UUID_STR));
+        assert_eq!(info.label.as_deref(), Some("emmc"));
+        assert_eq!(info.size_bytes(), 1 << 30);
+        assert_eq!(info.detail, FsDetail::F2fs { zone_blocks: 512 });
+        assert!(!info.has_errors);
+
+        put(&mut sb, 2794, &[0x04]);
+        assert!(parse_f2fs_superblock(&sb).unwrap().has_errors);
+
+        put(&mut sb, 0, &[0u8; 4]);
+        assert!(parse_f2fs_superblock(&sb).is_none());
+    }
+
+    #[test]
+    fn test_parse_nilfs2_superblock() {
+        let mut sb = vec![0u8; 1024];
+        put(&mut sb, 6, &resize::NILFS_MAGIC.to_le_bytes());
+        put(&mut sb, 20, &2u32.to_le_bytes());
+        put(&mut sb, 32, &(8u64 << 30).to_le_bytes());
+        put(&mut sb, 48, &2048u32.to_le_bytes());
+        put(&mut sb, 152, &TEST_UUID);
+        put(&mut sb, 168, b"lab");
+
+        let info = parse_nilfs2_superblock(&sb).unwrap();
+        assert_eq!(info.kind, FsKind::Nilfs2);
+        assert_eq!(info.uuid.as_deref(), Some(TEST_UUID_STR));
+        assert_eq!(info.label.as_deref(), Some("lab"));
+        assert_eq!(info.block_size, 4096);
+        assert_eq!(info.size_bytes(), 8 << 30);
+        assert_eq!(
+            info.detail,
+            FsDetail::Nilfs2 {
+                dev_size: 8 << 30,
+                blocks_per_segment: 2048,
+            }
+        );
+        assert!(!info.has_errors);
+
+        put(&mut sb, 116, &NILFS_STATE_ERROR.to_le_bytes());
+        assert!(parse_nilfs2_superblock(&sb).unwrap().has_errors);
+    }
+
+    /// Builds a bcachefs superblock for two devices of 1024 and 2048
+    /// buckets of 512 KiB, with a `members_v2` field.
+    fn bcachefs_superblock(dev_idx: u8) -> Vec<u8> {
+        const MEMBER_BYTES: usize = 112;
+        let field_u64s = (16 + 2 * MEMBER_BYTES) / 8;
+        let mut sb = vec![0u8; BCACHEFS_SB_FIXED + field_u64s * 8];
+        put(&mut sb, 24, &resize::BCACHEFS_MAGIC);
+        put(&mut sb, 56, &TEST_UUID);
+        put(&mut sb, 72, b"lab");
+        put(&mut sb, 120, &8u16.to_le_bytes());
+        put(&mut sb, 122, &[dev_idx, 2]);
+        put(&mut sb, 124, &(field_u64s as u32).to_le_bytes());
+
+        let field = BCACHEFS_SB_FIXED;
+        put(&mut sb, field, &(field_u64s as u32).to_le_bytes());
+        put(&mut sb, field + 4, &BCACHEFS_FIELD_MEMBERS_V2.to_le_bytes());
+        put(&mut sb, field + 8, &(MEMBER_BYTES as u16).to_le_bytes());
+        for (i, nbuckets) in [1024u64, 2048].into_iter().enumerate() {
+            let member = field + 16 + i * MEMBER_BYTES;
+            put(&mut sb, member + 16, &nbuckets.to_le_bytes());
+            put(&mut sb, member + 26, &1024u16.to_le_bytes());
+        }
+        sb
+    }
+
+    #[test]
+    fn test_parse_bcachefs_superblock() {
+        let info = parse_bcachefs_superblock(&bcachefs_superblock(1)).unwrap();
+        assert_eq!(info.kind, FsKind::Bcachefs);
+        assert_eq!(info.uuid.as_deref(), Some(TEST_UUID_STR));
+        assert_eq!(info.label.as_deref(), Some("lab"));
+        assert_eq!(info.block_size, 4096);
+        assert_eq!(info.size_bytes(), 3 << 29);
+        assert_eq!(
+            info.detail,
+            FsDetail::Bcachefs {
+                dev_idx: 1,
+                nr_devices: 2,
+                dev_bytes: 1 << 30,
+                bucket_bytes: 512 * 1024,
+            }
+        );
+
+        // dev_idx past the members
+        assert!(parse_bcachefs_superblock(&bcachefs_superblock(2)).is_none());
+    }
+
+    #[test]
+    fn test_read_fs_info_bcachefs_from_file() {
+        let file = tempfile::NamedTempFile::new().expect("Failed to create temp file");
+        file.as_file()
+            .write_all_at(&bcachefs_superblock(0), BCACHEFS_SB_OFFSET)
+            .unwrap();
+
+        let info = read_fs_info(file.path()).unwrap();
+        assert_eq!(info.kind, FsKind::Bcachefs);
+        assert!(matches!(
+            info.detail,
+            FsDetail::Bcachefs {
+                dev_bytes: 0x2000_0000,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_parse_swap_header() {
+        let mut page = vec![0u8; 4096];
+        put(&mut page, 1024, &1u32.to_le_bytes());
+        put(&mut page, 1028, &262_143u32.to_le_bytes());
+        put(&mut page, 1036, &TEST_UUID);
+        put(&mut page, 1052, b"swap0");
+        put(&mut page, 4086, b"SWAPSPACE2");
+
+        let info = parse_swap_header(&page).unwrap();
+        assert_eq!(info.kind, FsKind::Swap);
+        assert_eq!(info.uuid.as_deref(), Some(TEST_UUID_STR));
+        assert_eq!(info.label.as_deref(), Some("swap0"));
+        assert_eq!(info.block_size, 4096);
+        assert_eq!(info.size_bytes(), 1 << 30);
+        assert_eq!(info.detail, FsDetail::Swap { version: 1 });
+
+        // The signature must end the page
+        page.resize(8192, 0);
+        assert!(parse_swap_header(&page).is_none());
+    }
+
+    #[test]
+    fn test_read_fs_info_swap_64k_pages() {
+        let file = tempfile::NamedTempFile::new().expect("Failed to create temp file");
+        file.as_file()
+            .write_all_at(&1u32.to_le_bytes(), 1024)
+            .unwrap();
+        file.as_file()
+            .write_all_at(&15u32.to_le_bytes(), 1028)
+            .unwrap();
+        file.as_file().write_all_at(b"SWAPSPACE2", 65526).unwrap();
+        file.as_file().set_len(16 * 65536).unwrap();
+
+        let info = read_fs_info(file.path()).unwrap();
+        assert_eq!(info.kind, FsKind::Swap);
+        assert_eq!(info.block_size, 65536);
+        assert_eq!(info.size_bytes(), 16 * 65536);
+    }
+
+    #[test]
+    fn test_parse_ntfs_boot_sector() {
+        let mut boot = vec![0u8; 512];
+        put(&mut boot, 3, b"NTFS    ");
+        put(&mut boot, 11, &512u16.to_le_bytes());
+        boot[13] = 8;
+        put(&mut boot, 40, &(2_097_151u64).to_le_bytes());
+        put(&mut boot, 72, &0x0123_4567_89AB_CDEFu64.to_le_bytes());
+
+        let info = parse_ntfs_boot_sector(&boot).unwrap();
+        assert_eq!(info.kind, FsKind::Ntfs);
+        assert_eq!(info.uuid.as_deref(), Some("0123456789ABCDEF"));
+        assert_eq!(info.label, None);
+        assert_eq!(info.size_bytes(), (1 << 30) - 512);
+        assert_eq!(
+            info.detail,
+            FsDetail::Ntfs {
+                cluster_bytes: 4096
+            }
+        );
+
+        put(&mut boot, 3, b"EXFAT   ");
+        assert!(parse_ntfs_boot_sector(&boot).is_none());
+    }
+
+    #[test]
+    fn test_parse_fat_boot_sector() {
+        // FAT32: 32-bit sector count, no 16-bit FAT size
+        let mut boot = vec![0u8; 512];
+        put(&mut boot, 11, &512u16.to_le_bytes());
+        boot[13] = 8;
+        put(&mut boot, 32, &2_097_152u32.to_le_bytes());
+        put(&mut boot, 67, &0x1234_ABCDu32.to_le_bytes());
+        put(&mut boot, 71, b"DATA       ");
+        put(&mut boot, 82, b"FAT32   ");
+
+        let info = parse_fat_boot_sector(&boot).unwrap();
+        assert_eq!(info.kind, FsKind::Vfat);
+        assert_eq!(info.uuid.as_deref(), Some("1234-ABCD"));
+        assert_eq!(info.label.as_deref(), Some("DATA"));
+        assert_eq!(info.size_bytes(), 1 << 30);
+        assert_eq!(
+            info.detail,
+            FsDetail::Vfat {
+                cluster_bytes: 4096,
+                fat_bits: 32
+            }
+        );
+
+        // FAT16: 16-bit sector count, default label
+        let mut boot = vec![0u8; 512];
+        put(&mut boot, 11, &512u16.to_le_bytes());
+        boot[13] = 4;
+        put(&mut boot, 19, &40_960u16.to_le_bytes());
+        put(&mut boot, 22, &40u16.to_le_bytes());
+        put(&mut boot, 39, &0xCAFE_F00Du32.to_le_bytes());
+        put(&mut boot, 43, b"NO NAME    ");
+        put(&mut boot, 54, b"FAT16   ");
+
+        let info = parse_fat_boot_sector(&boot).unwrap();
+        assert_eq!(info.uuid.as_deref(), Some("CAFE-F00D"));
+        assert_eq!(info.label, None);
+        assert_eq!(info.size_bytes(), 20 << 20);
+        assert!(matches!(info.detail, FsDetail::Vfat { fat_bits: 16, .. }));
+        // 2 KiB clusters
+        assert_eq!(info.fat_resize_limit().unwrap().max_bytes, 65524 * 2048);
+
+        assert!(parse_fat_boot_sector(&[0u8; 512]).is_none());
+    }
+
+    #[test]
+    fn test_read_direct() {
+        let file = tempfile::NamedTempFile::new().expect("Failed to create temp file");
+        let data: Vec<u8> = (0..3 * DIRECT_IO_ALIGN).map(|i| (i % 251) as u8).collect();
+        file.as_file().write_all_at(&data, 0).unwrap();
+
+        // tmpfs and some other filesystems refuse O_DIRECT, in which case
+        // read_fs_info falls back to a buffered read
+        if let Ok(buf) = read_direct(file.path(), 4096, 5000) {
+            assert_eq!(buf, &data[4096..9096]);
+        }
+        // The last aligned block would run past the end of the file
+        assert!(read_direct(file.path(), 8192, 5000).is_err());
+    }
+
+    #[test]
+    fn test_read_fs_info_from_file() {
+        let file = tempfile::NamedTempFile::new().expect("Failed to create temp file");
+        file.as_file()
+            .write_all_at(&ext4_superblock(), EXT_SB_OFFSET)
+            .unwrap();
+
+        let info = read_fs_info(file.path()).unwrap();
+        assert_eq!(info.kind, FsKind::Ext4);
+        assert_eq!(info.label.as_deref(), Some("rootfs"));
+
+        let empty = tempfile::NamedTempFile::new().expect("Failed to create temp file");
+        assert!(read_fs_info(empty.path()).is_err());
+    }
+
+    #[test]
+    fn test_format_uuid() {
+        assert_eq!(format_uuid(&TEST_UUID).as_deref(), Some(TEST_UUID_STR));
+        assert_eq!(format_uuid(&[0u8; 16]), None);
+        assert_eq!(format_uuid(&[1u8; 4]), None);
+    }
+
+    #[test]
+    fn test_nul_terminated() {
+        assert_eq!(nul_terminated(b"root\0\0\0").as_deref(), Some("root"));
+        assert_eq!(nul_terminated(b"\0\0"), None);
+        assert_eq!(nul_terminated(b"full").as_deref(), Some("full"));
+    }
+}
@@ -0,0 +1,252 @@
+use crate::resize::ResizeError;
+use crate::superblock;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tracing::{info, warn};
+
+/// Part of the total memory that must stay available after swapoff has
+/// moved the swapped out pages back into memory, in percent.
+const SWAPOFF_MEMORY_RESERVE_PERCENT: u64 = 10;
+
+/// A swap area in use, as listed in `/proc/swaps`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActiveSwap {
+    pub device: PathBuf,
+    /// Size of the swap area in KiB.
+    pub size_kib: u64,
+    /// Swap in use in KiB.
+    pub used_kib: u64,
+    /// Priority; negative values are assigned by the kernel.
+    pub priority: i32,
+}
+
+/// Returns the swap area in use on a device, or `None` if swap is off.
+pub fn find_active_swap(device: &Path) -> Result<Option<ActiveSwap>, ResizeError> {
+    let content = std::fs::read_to_string("/proc/swaps")
+        .map_err(|e| ResizeError::DeviceNotFound(format!("Failed to read /proc/swaps: {}", e)))?;
+
+    let canonical = |path: &Path| std::fs::canonicalize(path).unwrap_or_else(|_| path.into());
+    let device = canonical(device);
+    Ok(parse_proc_swaps(&content)
+        .into_iter()
+        .find(|swap| canonical(&swap.device) == device))
+}
+
+/// Parses `/proc/swaps`. Swap files are listed too; spaces and other
+/// special characters in paths are octal escapes, like in mountinfo.
+fn parse_proc_swaps(content: &str) -> Vec<ActiveSwap> {
+    content
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [device, _kind, size, used, priority] = fields[..] else {
+                return None;
+            };
+            Some(ActiveSwap {
+                device: PathBuf::from(crate::mountinfo::unescape_mountinfo(device)),
+                size_kib: size.parse().ok()?,
+                used_kib: used.parse().ok()?,
+                priority: priority.parse().ok()?,
+            })
+        })
+        .collect()
+}
+
+/// Refuses to turn off swap when the memory available could not take the
+/// swapped out pages back with some room to spare.
+pub fn check_swapoff_safe(swap: &ActiveSwap) -> Result<(), ResizeError> {
+    let meminfo = std::fs::read_to_string("/proc/meminfo")
+        .map_err(|e| ResizeError::DeviceNotFound(format!("Failed to read /proc/meminfo: {}", e)))?;
+    check_swapoff_headroom(swap, &meminfo)
+}
+
+fn check_swapoff_headroom(swap: &ActiveSwap, meminfo: &str) -> Result<(), ResizeError> {
+    let field = |name: &str| {
+        meminfo.lines().find_map(|line| {
+            let value = line.strip_prefix(name)?.strip_prefix(':')?;
+            value
+                .trim()
+                .trim_end_matches("kB")
+                .trim()
+                .parse::<u64>()
+                .ok()
+        })
+    };
+    let (Some(total), Some(available)) = (field("MemTotal"), field("MemAvailable")) else {
+        return Err(ResizeError::ResizeFs(
+            "Could not read MemTotal and MemAvailable from /proc/meminfo".to_string(),
+        ));
+    };
+
+    let needed = swap.used_kib + total * SWAPOFF_MEMORY_RESERVE_PERCENT / 100;
+    if available < needed {
+        return Err(ResizeError::ResizeFs(format!(
+            "turning off swap on {} would move {} KiB back into memory, but only {} KiB is available (at least {} KiB needed)",
+            swap.device.display(),
+            swap.used_kib,
+            available,
+            needed
+        )));
+    }
+
+    Ok(())
+}
+
+/// A swap area turned off so that it can be grown.
+///
+/// The UUID, label and page size are read from the swap header first, so
+/// that [`SwapOff::recreate`] can run mkswap with them. If the swap area is
+/// dropped without being recreated, e.g. because mkswap failed, it is
+/// turned back on with its old header.
+pub struct SwapOff {
+    device: PathBuf,
+    page_size: u64,
+    uuid: Option<String>,
+    label: Option<String>,
+    /// Priority to turn swap back on with, if it was on.
+    active: Option<Option<i32>>,
+}
+
+impl SwapOff {
+    /// Reads the swap header of `device` and turns swap off on it if it is
+    /// in use.
+    pub fn new(device: &Path) -> Result<Self, ResizeError> {
+        let info = superblock::read_fs_info(device)?;
+        if info.kind != superblock::FsKind::Swap {
+            return Err(ResizeError::ResizeFs(format!(
+                "{} holds {}, not swap",
+                device.display(),
+                info.kind.as_str()
+            )));
+        }
+
+        let mut swap = SwapOff {
+            device: device.to_path_buf(),
+            page_size: info.block_size,
+            uuid: info.uuid,
+            label: info.label,
+            active: None,
+        };
+
+        if let Some(active) = find_active_swap(device)? {
+            check_swapoff_safe(&active)?;
+            info!(
+                "Turning off swap on {} ({} KiB in use)",
+                device.display(),
+                active.used_kib
+            );
+            run(Command::new("swapoff").arg(device))?;
+            // Negative priorities are kernel defaults, which swapon assigns again
+            swap.active = Some((active.priority >= 0).then_some(active.priority));
+        }
+
+        Ok(swap)
+    }
+
+    /// Recreates the swap area over the whole device, keeping its UUID,
+    /// label and page size, and turns it back on if it was on.
+    pub fn recreate(mut self) -> Result<(), ResizeError> {
+        let mut mkswap = Command::new("mkswap");
+        mkswap.arg("-p").arg(self.page_size.to_string());
+        if let Some(uuid) = &self.uuid {
+            mkswap.arg("-U").arg(uuid);
+        }
+        if let Some(label) = &self.label {
+            mkswap.arg("-L").arg(label);
+        }
+
+        info!("Recreating swap on {}", self.device.display());
+        run(mkswap.arg(&self.device))?;
+
+        self.swap_on()
+    }
+
+    fn swap_on(&mut self) -> Result<(), ResizeError> {
+        let Some(priority) = self.active.take() else {
+            return Ok(());
+        };
+
+        let mut swapon = Command::new("swapon");
+        if let Some(priority) = priority {
+            swapon.arg("-p").arg(priority.to_string());
+        }
+
+        info!("Turning swap back on on {}", self.device.display());
+        run(swapon.arg(&self.device))
+    }
+}
+
+impl Drop for SwapOff {
+    fn drop(&mut self) {
+        if self.active.is_some()
+            && let Err(e) = self.swap_on()
+        {
+            warn!("{}", e);
+        }
+    }
+}
+
+/// Runs a swap utility, turning a failure into an error with its output.
+fn run(command: &mut Command) -> Result<(), ResizeError> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let output = command
+        .output()
+        .map_err(|e| ResizeError::CommandFailed(format!("Failed to execute {}: {}", program, e)))?;
+
+    if !output.status.success() {
+        let error = String::from_utf8_lossy(&output.stderr);
+        return Err(ResizeError::ResizeFs(format!(
+            "{} failed: {}",
+            program,
+            error.trim_end()
+        )));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROC_SWAPS: &str = "\
+Filename\t\t\t\tType\t\tSize\t\tUsed\t\tPriority
+/dev/sda3                               partition\t8388604\t\t524288\t\t-2
+/dev/dm-1                               partition\t2097148\t\t0\t\t10
+/var/lib/swap\\040file                   file\t\t1048572\t\t0\t\t-3
+";
+
+    fn active(used_kib: u64) -> ActiveSwap {
+        ActiveSwap {
+            device: PathBuf::from("/dev/sda3"),
+            size_kib: 8_388_604,
+            used_kib,
+            priority: -2,
+        }
+    }
+
+    #[test]
+    fn test_parse_proc_swaps() {
+        let swaps = parse_proc_swaps(PROC_SWAPS);
+        assert_eq!(swaps.len(), 3);
+        assert_eq!(swaps[0], active(524_288));
+        assert_eq!(swaps[1].device, PathBuf::from("/dev/dm-1"));
+        assert_eq!(swaps[1].priority, 10);
+        assert_eq!(swaps[2].device, PathBuf::from("/var/lib/swap file"));
+
+        assert!(parse_proc_swaps("Filename Type Size Used Priority\n").is_empty());
+    }
+
+    #[test]
+    fn test_check_swapoff_headroom() {
+        let meminfo = "MemTotal:        8000000 kB\nMemFree:         1000000 kB\nMemAvailable:    2000000 kB\n";
+
+        // 10% of MemTotal is kept in reserve
+        assert!(check_swapoff_headroom(&active(0), meminfo).is_ok());
+        assert!(check_swapoff_headroom(&active(1_200_000), meminfo).is_ok());
+        assert!(check_swapoff_headroom(&active(1_200_001), meminfo).is_err());
+
+        assert!(check_swapoff_headroom(&active(0), "MemTotal: 8000000 kB\n").is_err());
+    }
+}
@@ -0,0 +1,169 @@
+use crate::resize::ResizeError;
+use nix::mount::{MntFlags, MsFlags};
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// A filesystem mounted on a private temporary directory, unmounted and
+/// removed again when dropped.
+///
+/// The directory is first bind-mounted onto itself and made private, so the
+/// filesystem mount below it does not propagate to peer or slave mounts in
+/// other mount namespaces. It is still visible in the current namespace
+/// (e.g. in `/proc/self/mountinfo`) until it is dropped.
+pub struct TempMount {
+    path: PathBuf,
+    bind_mounted: bool,
+    fs_mounted: bool,
+}
+
+impl TempMount {
+    /// Mounts `device` (of type `fs_type`) on a new directory under the
+    /// runtime directory.
+    pub fn new(device: &Path, fs_type: &str) -> Result<Self, ResizeError> {
+        let path = create_private_dir(&runtime_dir())?;
+        let mut mount = TempMount {
+            path,
+            bind_mounted: false,
+            fs_mounted: false,
+        };
+
+        nix::mount::mount(
+            Some(&mount.path),
+            &mount.path,
+            None::<&str>,
+            MsFlags::MS_BIND,
+            None::<&str>,
+        )
+        .map_err(|e| mount_error(&mount.path, "bind-mount", e))?;
+        mount.bind_mounted = true;
+
+        nix::mount::mount(
+            None::<&str>,
+            &mount.path,
+            None::<&str>,
+            MsFlags::MS_PRIVATE,
+            None::<&str>,
+        )
+        .map_err(|e| mount_error(&mount.path, "make private", e))?;
+
+        nix::mount::mount(
+            Some(device),
+            &mount.path,
+            Some(fs_type),
+            MsFlags::MS_NOSUID | MsFlags::MS_NODEV | MsFlags::MS_NOEXEC | MsFlags::MS_NOATIME,
+            None::<&str>,
+        )
+        .map_err(|e| {
+            ResizeError::ResizeFs(format!(
+                "Failed to mount {} ({}) on {}: {}",
+                device.display(),
+                fs_type,
+                mount.path.display(),
+                e
+            ))
+        })?;
+        mount.fs_mounted = true;
+
+        info!(
+            "Mounted {} on temporary mount point {}",
+            device.display(),
+            mount.path.display()
+        );
+        Ok(mount)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Unmounts the filesystem and removes the directory, reporting errors
+    /// instead of only logging them as a drop would.
+    pub fn unmount(mut self) -> Result<(), ResizeError> {
+        self.release()
+    }
+
+    fn release(&mut self) -> Result<(), ResizeError> {
+        if self.fs_mounted {
+            nix::mount::umount2(&self.path, MntFlags::empty())
+                .map_err(|e| mount_error(&self.path, "unmount", e))?;
+            self.fs_mounted = false;
+        }
+        if self.bind_mounted {
+            nix::mount::umount2(&self.path, MntFlags::MNT_DETACH)
+                .map_err(|e| mount_error(&self.path, "unmount the bind mount of", e))?;
+            self.bind_mounted = false;
+        }
+        std::fs::remove_dir(&self.path).map_err(|e| {
+            ResizeError::ResizeFs(format!("Failed to remove {}: {}", self.path.display(), e))
+        })
+    }
+}
+
+impl Drop for TempMount {
+    fn drop(&mut self) {
+        if (self.fs_mounted || self.bind_mounted || self.path.exists())
+            && let Err(e) = self.release()
+        {
+            warn!("{}", e);
+        }
+    }
+}
+
+fn mount_error(path: &Path, action: &str, e: nix::errno::Errno) -> ResizeError {
+    ResizeError::ResizeFs(format!("Failed to {} {}: {}", action, path.display(), e))
+}
+
+/// `/run` when it exists (it is a tmpfs, also in an initrd), else the
+/// system temporary directory.
+fn runtime_dir() -> PathBuf {
+    let run = Path::new("/run");
+    if run.is_dir() {
+        run.to_path_buf()
+    } else {
+        std::env::temp_dir()
+    }
+}
+
+/// Creates a new directory only root can enter, named after the process.
+fn create_private_dir(base: &Path) -> Result<PathBuf, ResizeError> {
+    use std::os::unix::fs::DirBuilderExt;
+
+    for attempt in 0..100 {
+        let path = base.join(format!("hot-resize-mnt-{}-{}", std::process::id(), attempt));
+        match std::fs::DirBuilder::new().mode(0o700).create(&path) {
+            Ok(()) => return Ok(path),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => {
+                return Err(ResizeError::ResizeFs(format!(
+                    "Failed to create {}: {}",
+                    path.display(),
+                    e
+                )));
+            }
+        }
+    }
+
+    Err(ResizeError::ResizeFs(format!(
+        "Failed to create a temporary mount point in {}",
+        base.display()
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn test_create_private_dir() {
+        let tmpdir = tempfile::tempdir().expect("Failed to create temp dir");
+
+        let first = create_private_dir(tmpdir.path()).unwrap();
+        let second = create_private_dir(tmpdir.path()).unwrap();
+        assert_ne!(first, second);
+        assert!(first.starts_with(tmpdir.path()));
+
+        let mode = std::fs::metadata(&first).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+    }
+}
@@ -0,0 +1,245 @@
+use crate::resize::{self, ResizeError};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tracing::{info, warn};
+
+/// A leaf vdev of an imported ZFS pool.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ZpoolVdev {
+    /// Name of the pool (e.g. `rpool`).
+    pub pool: String,
+    /// Device path as listed by `zpool list -P` (e.g. `/dev/sda3`).
+    pub path: PathBuf,
+    /// Space the vdev can grow into (`EXPANDSZ`), if ZFS already sees it.
+    pub expand_bytes: Option<u64>,
+}
+
+/// Returns the pool vdev on a device, or `None` if the device holds no ZFS
+/// label.
+///
+/// Fails when the device is a ZFS member but `zpool` is missing or the
+/// device is not part of an imported pool.
+pub fn detect_pool_vdev(device: &Path) -> Result<Option<ZpoolVdev>, ResizeError> {
+    if !resize::get_fs_type(device).is_ok_and(|fs_type| fs_type == "zfs_member") {
+        return Ok(None);
+    }
+
+    if crate::find_in_path("zpool").is_none() {
+        return Err(ResizeError::CommandFailed(format!(
+            "{} is a ZFS pool member, but zpool was not found",
+            device.display()
+        )));
+    }
+
+    let output = Command::new("zpool")
+        .args(["list", "-v", "-H", "-P", "-p", "-o", "name,expandsz"])
+        .output()
+        .map_err(|e| ResizeError::CommandFailed(format!("Failed to execute zpool list: {}", e)))?;
+    if !output.status.success() {
+        let error = String::from_utf8_lossy(&output.stderr);
+        return Err(ResizeError::ResizeZfs(format!(
+            "zpool list failed: {}",
+            error.trim_end()
+        )));
+    }
+
+    let vdevs = parse_zpool_list(&String::from_utf8_lossy(&output.stdout));
+    match find_vdev(&vdevs, device) {
+        Some(vdev) => Ok(Some(vdev.clone())),
+        None => Err(ResizeError::ResizeZfs(format!(
+            "{} holds a ZFS label but is not a vdev of any imported pool",
+            device.display()
+        ))),
+    }
+}
+
+/// Vdev class headings that `zpool list -v` prints between the vdevs of a
+/// pool. Depending on the version, they may not be indented.
+const VDEV_CLASSES: &[&str] = &["dedup", "special", "logs", "cache", "spare", "spares"];
+
+/// Parses the output of `zpool list -v -H -P -p -o name,expandsz`.
+///
+/// Pool lines start with the pool name; vdev lines start with a tab. Only
+/// leaf vdevs, listed by their device path, are returned: `mirror-0`,
+/// `raidz1-0` and the vdev class headings are skipped.
+fn parse_zpool_list(output: &str) -> Vec<ZpoolVdev> {
+    let mut vdevs = Vec::new();
+    let mut pool = None;
+
+    for line in output.lines() {
+        let mut fields = line.split('\t');
+        match fields.next() {
+            Some("") => {}
+            Some(name) if VDEV_CLASSES.contains(&name) => continue,
+            Some(name) => {
+                pool = Some(name.to_string());
+                continue;
+            }
+            None => continue,
+        }
+
+        let (Some(pool), Some(path)) = (&pool, fields.next()) else {
+            continue;
+        };
+        if !path.starts_with('/') {
+            continue;
+        }
+
+        vdevs.push(ZpoolVdev {
+            pool: pool.clone(),
+            path: PathBuf::from(path),
+            expand_bytes: fields
+                .next()
+                .and_then(|size| size.trim().parse().ok())
+                .filter(|&size: &u64| size > 0),
+        });
+    }
+
+    vdevs
+}
+
+/// Finds the vdev on `device`, following links such as
+/// `/dev/disk/by-id/...` on both sides.
+fn find_vdev<'a>(vdevs: &'a [ZpoolVdev], device: &Path) -> Option<&'a ZpoolVdev> {
+    let canonical = |path: &Path| std::fs::canonicalize(path).unwrap_or_else(|_| path.into());
+    let device = canonical(device);
+    vdevs.iter().find(|vdev| canonical(&vdev.path) == device)
+}
+
+/// Reads the `autoexpand` property of a pool.
+pub fn autoexpand(pool: &str) -> Result<bool, ResizeError> {
+    let output = Command::new("zpool")
+        .args(["get", "-H", "-o", "value", "autoexpand", pool])
+        .output()
+        .map_err(|e| ResizeError::CommandFailed(format!("Failed to execute zpool get: {}", e)))?;
+    if !output.status.success() {
+        let error = String::from_utf8_lossy(&output.stderr);
+        return Err(ResizeError::ResizeZfs(format!(
+            "zpool get autoexpand {} failed: {}",
+            pool,
+            error.trim_end()
+        )));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim() == "on")
+}
+
+/// Expands a vdev into the space its device gained with
+/// `zpool online -e <pool> <vdev>`.
+///
+/// This is needed with `autoexpand` on as well: the pool only picks up the
+/// new size by itself when the kernel reports a size change event for the
+/// vdev, which a partition grown in place does not always trigger.
+pub fn expand_pool(vdev: &ZpoolVdev) -> Result<(), ResizeError> {
+    match autoexpand(&vdev.pool) {
+        Ok(true) => info!("ZFS pool {} has autoexpand on", vdev.pool),
+        Ok(false) => info!(
+            "ZFS pool {} has autoexpand off, expanding {} explicitly",
+            vdev.pool,
+            vdev.path.display()
+        ),
+        Err(e) => warn!("{}", e),
+    }
+
+    info!(
+        "Expanding ZFS pool {} onto {}",
+        vdev.pool,
+        vdev.path.display()
+    );
+    let output = Command::new("zpool")
+        .args(["online", "-e", &vdev.pool])
+        .arg(&vdev.path)
+        .output()
+        .map_err(|e| {
+            ResizeError::CommandFailed(format!("Failed to execute zpool online: {}", e))
+        })?;
+
+    if !output.status.success() {
+        let error = String::from_utf8_lossy(&output.stderr);
+        return Err(ResizeError::ResizeZfs(format!(
+            "zpool online -e {} {} failed: {}",
+            vdev.pool,
+            vdev.path.display(),
+            error.trim_end()
+        )));
+    }
+
+    info!("Successfully expanded ZFS pool {}", vdev.pool);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ZPOOL_LIST: &str = "rpool\t-\n\
+        \t/dev/disk/by-id/nvme-disk1-part3\t10737418240\n\
+        tank\t-\n\
+        \tmirror-0\t-\n\
+        \t/dev/sdb1\t-\n\
+        \t/dev/sdc1\t0\n\
+        logs\t-\n\
+        \t/dev/nvme1n1p1\t-\n";
+
+    #[test]
+    fn test_parse_zpool_list() {
+        let vdevs = parse_zpool_list(ZPOOL_LIST);
+        assert_eq!(
+            vdevs,
+            vec![
+                ZpoolVdev {
+                    pool: "rpool".to_string(),
+                    path: PathBuf::from("/dev/disk/by-id/nvme-disk1-part3"),
+                    expand_bytes: Some(10 << 30),
+                },
+                ZpoolVdev {
+                    pool: "tank".to_string(),
+                    path: PathBuf::from("/dev/sdb1"),
+                    expand_bytes: None,
+                },
+                ZpoolVdev {
+                    pool: "tank".to_string(),
+                    path: PathBuf::from("/dev/sdc1"),
+                    expand_bytes: None,
+                },
+                ZpoolVdev {
+                    pool: "tank".to_string(),
+                    path: PathBuf::from("/dev/nvme1n1p1"),
+                    expand_bytes: None,
+                },
+            ]
+        );
+        assert!(parse_zpool_list("").is_empty());
+    }
+
+    #[test]
+    fn test_find_vdev_follows_links() {
+        let tmpdir = tempfile::tempdir().expect("Failed to create temp dir");
+        let device = tmpdir.path().join("sda3");
+        let link = tmpdir.path().join("ata-disk1-part3");
+        std::fs::write(&device, b"").unwrap();
+        std::os::unix::fs::symlink(&device, &link).unwrap();
+
+        let vdevs = vec![
+            ZpoolVdev {
+                pool: "tank".to_string(),
+                path: PathBuf::from("/dev/sdb1"),
+                expand_bytes: None,
+            },
+            ZpoolVdev {
+                pool: "rpool".to_string(),
+                path: link,
+                expand_bytes: None,
+            },
+        ];
+
+        assert_eq!(find_vdev(&vdevs, &device).unwrap().pool, "rpool");
+        assert!(find_vdev(&vdevs, &tmpdir.path().join("sdc1")).is_none());
+    }
+
+    #[test]
+    fn test_detect_pool_vdev_not_zfs() {
+        let file = tempfile::NamedTempFile::new().expect("Failed to create temp file");
+        assert_eq!(detect_pool_vdev(file.path()).unwrap(), None);
+    }
+}